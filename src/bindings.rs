@@ -0,0 +1,726 @@
+use crate::{
+    Action, Axis,
+    gamepad::{Gamepad, PadAxis, PadAxisBinding, PadButton},
+};
+use graphics::{
+    input::{self, Bindings, InputEvent, InputHandler, Key, MouseAxis},
+    *,
+};
+use log::{info, warn};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, mem,
+    path::Path,
+};
+use winit::{event::MouseButton, keyboard::NamedKey};
+
+/// Axis values read from the input for the game logic.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GameInput {
+    /// Player movement from the Forward and Sideward axes.
+    pub movement: Vec2,
+    /// Camera panning from the Yaw and Pitch axes.
+    pub pan: Vec2,
+}
+
+/// A single key or mouse button a binding can be set to.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BindingInput {
+    Character(char),
+    Named(NamedKey),
+    Mouse(MouseButton),
+}
+
+impl BindingInput {
+    /// The input a key press or mouse click would be bound to.
+    pub fn from_event(event: &InputEvent) -> Option<Self> {
+        match Self::from_input(event) {
+            Some((input, true)) => Some(input),
+            _ => None,
+        }
+    }
+
+    /// The key or mouse button within the event and if it went down.
+    pub fn from_input(event: &InputEvent) -> Option<(Self, bool)> {
+        match event {
+            InputEvent::KeyInput { key, pressed, .. } => match key {
+                Key::Character(c) => {
+                    Some((Self::Character(c.to_ascii_lowercase()), *pressed))
+                }
+                Key::Named(named) => Some((Self::Named(*named), *pressed)),
+                _ => None,
+            },
+            InputEvent::MouseButton { button, pressed } => {
+                Some((Self::Mouse(*button), *pressed))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn button(self) -> input::Button {
+        match self {
+            Self::Character(c) => Key::Character(c).into(),
+            Self::Named(named) => Key::Named(named).into(),
+            Self::Mouse(button) => button.into(),
+        }
+    }
+}
+
+impl fmt::Display for BindingInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Character(c) => write!(f, "{c}"),
+            Self::Named(named) => write!(f, "{named:?}"),
+            Self::Mouse(button) => write!(f, "Mouse {button:?}"),
+        }
+    }
+}
+
+/// Each axis goes from -1.0 while neg is held to 1.0 while pos is held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub pos: BindingInput,
+    pub neg: BindingInput,
+}
+
+/// Which way the mouse moves an axis. Right and up are positive.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseMotion {
+    X,
+    Y,
+}
+
+/// Moves an axis by the pixels the mouse is dragged while any of `buttons`
+/// is held.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MouseAxisBinding {
+    pub motion: MouseMotion,
+    pub buttons: Vec<MouseButton>,
+}
+
+/// The players bindings as saved within the bindings file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BindingsFile {
+    pub actions: Vec<(Action, Vec<BindingInput>)>,
+    pub axes: Vec<(Axis, AxisBinding)>,
+    /// Dragging moves the camera by pixels rather than at a speed so only
+    /// the Yaw and Pitch axes are read from the mouse.
+    pub mouse_axes: Vec<(Axis, MouseAxisBinding)>,
+    pub pad_actions: Vec<(Action, Vec<PadButton>)>,
+    pub pad_axes: Vec<(Axis, PadAxisBinding)>,
+}
+
+impl Default for BindingsFile {
+    /// The bindings used when none have been set by the player.
+    fn default() -> Self {
+        use BindingInput::{Character, Named};
+
+        Self {
+            actions: vec![
+                (Action::Quit, vec![Character('q')]),
+                (Action::Screenshot, vec![Named(NamedKey::F12)]),
+                (Action::ToggleStats, vec![Named(NamedKey::F3)]),
+                (Action::Rebind, vec![Named(NamedKey::F2)]),
+                (Action::Shake, vec![Character('e')]),
+                (Action::ToggleViewports, vec![Character('m')]),
+                (Action::SaveMap, vec![Named(NamedKey::F5)]),
+                (Action::ToggleEditor, vec![Named(NamedKey::F4)]),
+                (Action::ToggleCollision, vec![Named(NamedKey::F6)]),
+            ],
+            axes: vec![
+                (
+                    Axis::Forward,
+                    AxisBinding {
+                        pos: Character('w'),
+                        neg: Character('s'),
+                    },
+                ),
+                (
+                    Axis::Sideward,
+                    AxisBinding {
+                        pos: Character('d'),
+                        neg: Character('a'),
+                    },
+                ),
+                (
+                    Axis::Yaw,
+                    AxisBinding {
+                        pos: Named(NamedKey::ArrowRight),
+                        neg: Named(NamedKey::ArrowLeft),
+                    },
+                ),
+                (
+                    Axis::Pitch,
+                    AxisBinding {
+                        pos: Named(NamedKey::ArrowUp),
+                        neg: Named(NamedKey::ArrowDown),
+                    },
+                ),
+            ],
+            mouse_axes: vec![
+                (
+                    Axis::Yaw,
+                    MouseAxisBinding {
+                        motion: MouseMotion::X,
+                        buttons: vec![MouseButton::Right, MouseButton::Middle],
+                    },
+                ),
+                (
+                    Axis::Pitch,
+                    MouseAxisBinding {
+                        motion: MouseMotion::Y,
+                        buttons: vec![MouseButton::Right, MouseButton::Middle],
+                    },
+                ),
+            ],
+            pad_actions: vec![
+                (Action::Quit, Vec::new()),
+                (Action::Screenshot, Vec::new()),
+                (Action::ToggleStats, vec![PadButton::Select]),
+                (Action::Rebind, Vec::new()),
+                (Action::Shake, vec![PadButton::RightBumper]),
+                (Action::ToggleViewports, vec![PadButton::Start]),
+                (Action::SaveMap, Vec::new()),
+                (Action::ToggleEditor, Vec::new()),
+                (Action::ToggleCollision, Vec::new()),
+            ],
+            pad_axes: vec![
+                (Axis::Forward, PadAxisBinding::Analog(PadAxis::LeftStickY)),
+                (Axis::Sideward, PadAxisBinding::Analog(PadAxis::LeftStickX)),
+                (Axis::Yaw, PadAxisBinding::Analog(PadAxis::RightStickX)),
+                (Axis::Pitch, PadAxisBinding::Analog(PadAxis::RightStickY)),
+            ],
+        }
+    }
+}
+
+impl BindingsFile {
+    /// Loads the bindings file. Uses the defaults if it does not exist or
+    /// can not be read so a bad file never stops the demo from starting.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Self::default();
+        }
+
+        let mut file = match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                ron::from_str::<Self>(&data).map_err(|e| e.to_string())
+            }) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("failed to load bindings {}: {e}", path.display());
+                return Self::default();
+            }
+        };
+
+        // Anything added since the file was saved gets its default binding.
+        let defaults = Self::default();
+
+        for (action, inputs) in defaults.actions {
+            if !file.actions.iter().any(|(a, _)| *a == action) {
+                file.actions.push((action, inputs));
+            }
+        }
+
+        for (axis, binding) in defaults.axes {
+            if !file.axes.iter().any(|(a, _)| *a == axis) {
+                file.axes.push((axis, binding));
+            }
+        }
+
+        for (axis, binding) in defaults.mouse_axes {
+            if !file.mouse_axes.iter().any(|(a, _)| *a == axis) {
+                file.mouse_axes.push((axis, binding));
+            }
+        }
+
+        for (action, buttons) in defaults.pad_actions {
+            if !file.pad_actions.iter().any(|(a, _)| *a == action) {
+                file.pad_actions.push((action, buttons));
+            }
+        }
+
+        for (axis, binding) in defaults.pad_axes {
+            if !file.pad_axes.iter().any(|(a, _)| *a == axis) {
+                file.pad_axes.push((axis, binding));
+            }
+        }
+
+        file
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GraphicsError> {
+        let path = path.as_ref();
+        let data = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| {
+                OtherError::new(&format!("failed to serialize bindings: {e}"))
+            })?;
+
+        fs::write(path, data)?;
+        info!("saved bindings to {}", path.display());
+        Ok(())
+    }
+
+    pub fn to_bindings(&self) -> Bindings<Action, Axis> {
+        let mut bindings = Bindings::<Action, Axis>::new();
+
+        for (action, inputs) in &self.actions {
+            bindings.insert_action(
+                action.clone(),
+                inputs.iter().map(|input| input.button()).collect(),
+            );
+        }
+
+        for (axis, binding) in &self.axes {
+            bindings.insert_axis(
+                axis.clone(),
+                input::Axis::Emulated {
+                    pos: binding.pos.button(),
+                    neg: binding.neg.button(),
+                },
+            );
+        }
+
+        bindings
+    }
+}
+
+/// Tracks the mouse being dragged along each axis it is bound to and the
+/// mouse wheel so the camera can be panned and zoomed with the mouse as well
+/// as the Yaw and Pitch keys.
+#[derive(Clone, Debug, Default)]
+pub struct MouseDrag {
+    buttons: HashSet<MouseButton>,
+    bindings: Vec<(Axis, MouseAxisBinding)>,
+    last: Option<Vec2>,
+    /// Pixels each axis was dragged since the last call to `take`.
+    dragged: HashMap<Axis, f32>,
+    /// Notches scrolled since the last call to `take_scroll`. Up is positive.
+    scroll: f32,
+}
+
+impl MouseDrag {
+    pub fn new(bindings: &BindingsFile) -> Self {
+        Self {
+            bindings: bindings.mouse_axes.clone(),
+            ..Self::default()
+        }
+    }
+
+    pub fn set_bindings(&mut self, bindings: &BindingsFile) {
+        self.bindings = bindings.mouse_axes.clone();
+    }
+
+    pub fn handle_event(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::MouseButton { button, pressed } => {
+                if *pressed {
+                    self.buttons.insert(*button);
+                } else {
+                    self.buttons.remove(button);
+                }
+            }
+            // Only scrolling up and down zooms.
+            InputEvent::MouseWheel {
+                amount,
+                axis: MouseAxis::Vertical,
+            } => self.scroll += *amount,
+            InputEvent::MousePosition { x, y } => {
+                let pos = Vec2::new(*x, *y);
+
+                if let Some(last) = self.last {
+                    // Window positions go down while the world goes up.
+                    let moved = Vec2::new(pos.x - last.x, last.y - pos.y);
+
+                    for (axis, binding) in &self.bindings {
+                        if !binding
+                            .buttons
+                            .iter()
+                            .any(|b| self.buttons.contains(b))
+                        {
+                            continue;
+                        }
+
+                        *self.dragged.entry(axis.clone()).or_default() +=
+                            match binding.motion {
+                                MouseMotion::X => moved.x,
+                                MouseMotion::Y => moved.y,
+                            };
+                    }
+                }
+
+                self.last = Some(pos);
+            }
+            InputEvent::WindowFocused(false) => self.buttons.clear(),
+            _ => {}
+        }
+    }
+
+    /// Where the mouse was last seen within the window.
+    pub fn position(&self) -> Option<Vec2> {
+        self.last
+    }
+
+    /// Returns and clears how far the Yaw and Pitch axes were dragged.
+    pub fn take(&mut self) -> Vec2 {
+        let dragged = mem::take(&mut self.dragged);
+        let axis = |axis| dragged.get(&axis).copied().unwrap_or_default();
+
+        Vec2::new(axis(Axis::Yaw), axis(Axis::Pitch))
+    }
+
+    /// Returns and clears how far the mouse wheel was scrolled.
+    pub fn take_scroll(&mut self) -> f32 {
+        mem::take(&mut self.scroll)
+    }
+}
+
+/// Anything the state of each Action and Axis can be read from.
+pub trait ActionInput {
+    fn is_action_down(&self, action: &Action) -> bool;
+    fn axis_value(&self, axis: &Axis) -> f32;
+}
+
+/// Tracks which bound keys and mouse buttons are down from the events that
+/// are dispatched. Live and replayed input both go through this so a replay
+/// triggers actions exactly like the run it was recorded from.
+#[derive(Clone, Debug)]
+pub struct HeldInputs {
+    held: HashSet<BindingInput>,
+    bindings: BindingsFile,
+}
+
+impl HeldInputs {
+    pub fn new(bindings: &BindingsFile) -> Self {
+        Self {
+            held: HashSet::new(),
+            bindings: bindings.clone(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &InputEvent) {
+        match BindingInput::from_input(event) {
+            Some((input, true)) => {
+                self.held.insert(input);
+            }
+            Some((input, false)) => {
+                self.held.remove(&input);
+            }
+            // Nothing is released while the window is not focused.
+            None if matches!(event, InputEvent::WindowFocused(false)) => {
+                self.held.clear();
+            }
+            None => {}
+        }
+    }
+
+    pub fn set_bindings(&mut self, bindings: &BindingsFile) {
+        self.bindings = bindings.clone();
+    }
+}
+
+impl ActionInput for HeldInputs {
+    fn is_action_down(&self, action: &Action) -> bool {
+        self.bindings
+            .actions
+            .iter()
+            .filter(|(a, _)| a == action)
+            .any(|(_, inputs)| inputs.iter().any(|i| self.held.contains(i)))
+    }
+
+    fn axis_value(&self, axis: &Axis) -> f32 {
+        let Some((_, binding)) =
+            self.bindings.axes.iter().find(|(a, _)| a == axis)
+        else {
+            return 0.0;
+        };
+        let value = |input: &BindingInput| {
+            if self.held.contains(input) { 1.0 } else { 0.0 }
+        };
+
+        value(&binding.pos) - value(&binding.neg)
+    }
+}
+
+/// Remembers which actions are down so each only triggers on the frame it
+/// goes down and not for as long as it is held.
+#[derive(Clone, Debug, Default)]
+pub struct ActionPresses {
+    held: HashSet<Action>,
+}
+
+impl ActionPresses {
+    /// Returns every bound action that went down since the last call. Has
+    /// to run each frame even while the keyboard is captured so an action
+    /// released in the meantime can trigger again.
+    pub fn update(
+        &mut self,
+        bindings: &BindingsFile,
+        input: &dyn ActionInput,
+        gamepad: &Gamepad,
+    ) -> HashSet<Action> {
+        let actions: HashSet<&Action> = bindings
+            .actions
+            .iter()
+            .map(|(action, _)| action)
+            .chain(bindings.pad_actions.iter().map(|(action, _)| action))
+            .collect();
+        let mut pressed = HashSet::new();
+
+        for action in actions {
+            if input.is_action_down(action) || gamepad.is_action_down(action) {
+                if self.held.insert(action.clone()) {
+                    pressed.insert(action.clone());
+                }
+            } else {
+                self.held.remove(action);
+            }
+        }
+
+        pressed
+    }
+
+    /// Forgets what was held so anything still down triggers again.
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
+
+/// Reads the current value of each axis from the keyboard, mouse and
+/// gamepad combined.
+pub fn read_game_input(
+    input: &dyn ActionInput,
+    gamepad: &Gamepad,
+) -> GameInput {
+    let axis = |axis: Axis| {
+        (input.axis_value(&axis) + gamepad.axis_value(&axis)).clamp(-1.0, 1.0)
+    };
+
+    GameInput {
+        movement: Vec2::new(axis(Axis::Sideward), axis(Axis::Forward))
+            .clamp_length_max(1.0),
+        pan: Vec2::new(axis(Axis::Yaw), axis(Axis::Pitch))
+            .clamp_length_max(1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::keyboard::KeyLocation;
+
+    fn key(c: char, pressed: bool) -> InputEvent {
+        InputEvent::KeyInput {
+            key: Key::Character(c),
+            location: KeyLocation::Standard,
+            pressed,
+        }
+    }
+
+    fn named(named: NamedKey, pressed: bool) -> InputEvent {
+        InputEvent::KeyInput {
+            key: Key::Named(named),
+            location: KeyLocation::Standard,
+            pressed,
+        }
+    }
+
+    fn mouse(button: MouseButton, pressed: bool) -> InputEvent {
+        InputEvent::MouseButton { button, pressed }
+    }
+
+    fn held(events: &[InputEvent]) -> HeldInputs {
+        let mut held = HeldInputs::new(&BindingsFile::default());
+
+        events.iter().for_each(|event| held.handle_event(event));
+        held
+    }
+
+    fn gamepad() -> Gamepad {
+        Gamepad::new(None, &BindingsFile::default(), 0.0)
+    }
+
+    #[test]
+    fn held_inputs_read_actions_and_axes() {
+        let mut held = held(&[key('W', true), key('q', true)]);
+
+        assert!(held.is_action_down(&Action::Quit));
+        assert!(!held.is_action_down(&Action::Shake));
+        assert_eq!(held.axis_value(&Axis::Forward), 1.0);
+
+        held.handle_event(&key('s', true));
+        assert_eq!(held.axis_value(&Axis::Forward), 0.0);
+
+        held.handle_event(&key('w', false));
+        held.handle_event(&key('q', false));
+        assert_eq!(held.axis_value(&Axis::Forward), -1.0);
+        assert!(!held.is_action_down(&Action::Quit));
+    }
+
+    #[test]
+    fn held_inputs_release_everything_on_focus_loss() {
+        let mut held = held(&[key('d', true), named(NamedKey::F3, true)]);
+
+        held.handle_event(&InputEvent::WindowFocused(false));
+
+        assert_eq!(held.axis_value(&Axis::Sideward), 0.0);
+        assert!(!held.is_action_down(&Action::ToggleStats));
+    }
+
+    #[test]
+    fn held_inputs_follow_new_bindings() {
+        let mut bindings = BindingsFile::default();
+        let mut held = held(&[mouse(MouseButton::Left, true)]);
+
+        bindings.actions =
+            vec![(Action::Shake, vec![BindingInput::Mouse(MouseButton::Left)])];
+        assert!(!held.is_action_down(&Action::Shake));
+
+        held.set_bindings(&bindings);
+        assert!(held.is_action_down(&Action::Shake));
+    }
+
+    #[test]
+    fn game_input_combines_and_clamps_the_axes() {
+        let held = held(&[
+            key('w', true),
+            key('d', true),
+            named(NamedKey::ArrowLeft, true),
+        ]);
+        let mut gamepad = gamepad();
+
+        gamepad.handle_event(crate::gamepad::GamepadEvent::Axis {
+            axis: PadAxis::RightStickX,
+            value: -1.0,
+        });
+
+        let input = read_game_input(&held, &gamepad);
+        let diagonal = 1.0 / 2.0f32.sqrt();
+
+        assert!((input.movement - Vec2::splat(diagonal)).length() < 1e-6);
+        assert_eq!(input.pan, Vec2::new(-1.0, 0.0));
+        assert_eq!(
+            read_game_input(
+                &HeldInputs::new(&BindingsFile::default()),
+                &gamepad
+            )
+            .pan,
+            Vec2::new(-1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn actions_only_trigger_when_they_go_down() {
+        let bindings = BindingsFile::default();
+        let gamepad = gamepad();
+        let mut presses = ActionPresses::default();
+        let mut held = held(&[named(NamedKey::F3, true)]);
+
+        assert_eq!(
+            presses.update(&bindings, &held, &gamepad),
+            HashSet::from([Action::ToggleStats])
+        );
+        assert!(presses.update(&bindings, &held, &gamepad).is_empty());
+
+        held.handle_event(&named(NamedKey::F3, false));
+        assert!(presses.update(&bindings, &held, &gamepad).is_empty());
+
+        held.handle_event(&named(NamedKey::F3, true));
+        assert_eq!(
+            presses.update(&bindings, &held, &gamepad),
+            HashSet::from([Action::ToggleStats])
+        );
+    }
+
+    #[test]
+    fn actions_released_while_captured_trigger_again() {
+        let bindings = BindingsFile::default();
+        let gamepad = gamepad();
+        let mut presses = ActionPresses::default();
+        let mut held = held(&[key('e', true)]);
+
+        presses.update(&bindings, &held, &gamepad);
+
+        // A text field took the keyboard while the key came back up. The
+        // presses are still updated but nothing acts on them.
+        held.handle_event(&key('e', false));
+        presses.update(&bindings, &held, &gamepad);
+
+        held.handle_event(&key('e', true));
+        assert!(
+            presses
+                .update(&bindings, &held, &gamepad)
+                .contains(&Action::Shake)
+        );
+    }
+
+    #[test]
+    fn mouse_drag_moves_the_bound_axes() {
+        let mut drag = MouseDrag::new(&BindingsFile::default());
+        let moved = |drag: &mut MouseDrag, x, y| {
+            drag.handle_event(&InputEvent::MousePosition { x, y })
+        };
+
+        moved(&mut drag, 10.0, 10.0);
+        moved(&mut drag, 20.0, 20.0);
+        assert_eq!(drag.take(), Vec2::ZERO);
+
+        drag.handle_event(&mouse(MouseButton::Right, true));
+        moved(&mut drag, 25.0, 14.0);
+        moved(&mut drag, 30.0, 10.0);
+        // Window positions go down while the world goes up.
+        assert_eq!(drag.take(), Vec2::new(10.0, 10.0));
+        assert_eq!(drag.take(), Vec2::ZERO);
+
+        drag.handle_event(&InputEvent::WindowFocused(false));
+        moved(&mut drag, 40.0, 10.0);
+        assert_eq!(drag.take(), Vec2::ZERO);
+        assert_eq!(drag.position(), Some(Vec2::new(40.0, 10.0)));
+    }
+
+    #[test]
+    fn mouse_drag_follows_new_bindings() {
+        let mut bindings = BindingsFile::default();
+        let mut drag = MouseDrag::new(&bindings);
+
+        bindings.mouse_axes = vec![(
+            Axis::Yaw,
+            MouseAxisBinding {
+                motion: MouseMotion::Y,
+                buttons: vec![MouseButton::Left],
+            },
+        )];
+        drag.set_bindings(&bindings);
+        drag.handle_event(&InputEvent::MousePosition { x: 0.0, y: 0.0 });
+        drag.handle_event(&mouse(MouseButton::Right, true));
+        drag.handle_event(&InputEvent::MousePosition { x: 5.0, y: 5.0 });
+        assert_eq!(drag.take(), Vec2::ZERO);
+
+        drag.handle_event(&mouse(MouseButton::Left, true));
+        drag.handle_event(&InputEvent::MousePosition { x: 10.0, y: 0.0 });
+        assert_eq!(drag.take(), Vec2::new(5.0, 0.0));
+    }
+
+    #[test]
+    fn only_vertical_scrolling_zooms() {
+        let mut drag = MouseDrag::default();
+
+        drag.handle_event(&InputEvent::MouseWheel {
+            amount: 2.0,
+            axis: MouseAxis::Vertical,
+        });
+        drag.handle_event(&InputEvent::MouseWheel {
+            amount: 1.0,
+            axis: MouseAxis::Horizontal,
+        });
+
+        assert_eq!(drag.take_scroll(), 2.0);
+        assert_eq!(drag.take_scroll(), 0.0);
+    }
+}
@@ -0,0 +1,61 @@
+use log::warn;
+
+/// Copies and pastes through the system clipboard when it is available.
+/// Otherwise text is only kept within the demo so copy and paste still work
+/// between our own text fields.
+pub struct Clipboard {
+    #[cfg(feature = "clipboard")]
+    system: Option<arboard::Clipboard>,
+    local: String,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "clipboard")]
+            system: arboard::Clipboard::new()
+                .map_err(|e| warn!("system clipboard is unavailable: {e}"))
+                .ok(),
+            local: String::new(),
+        }
+    }
+
+    /// Never touches the system clipboard even with the `clipboard`
+    /// feature. Used by tests so they do not clobber it.
+    pub fn local() -> Self {
+        Self {
+            #[cfg(feature = "clipboard")]
+            system: None,
+            local: String::new(),
+        }
+    }
+
+    pub fn get(&mut self) -> String {
+        #[cfg(feature = "clipboard")]
+        if let Some(system) = self.system.as_mut() {
+            match system.get_text() {
+                Ok(text) => return text,
+                Err(e) => warn!("failed to read the clipboard: {e}"),
+            }
+        }
+
+        self.local.clone()
+    }
+
+    pub fn set(&mut self, text: &str) {
+        #[cfg(feature = "clipboard")]
+        if let Some(system) = self.system.as_mut()
+            && let Err(e) = system.set_text(text)
+        {
+            warn!("failed to write the clipboard: {e}");
+        }
+
+        self.local = text.to_owned();
+    }
+}
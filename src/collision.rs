@@ -0,0 +1,445 @@
+use crate::scene::{MAP_SIZE, TILE_SIZE};
+use graphics::*;
+use serde::{Deserialize, Serialize};
+
+/// Boxes are shrunk by this much when finding the tiles under them so a box
+/// resting against a tile does not count as being within it.
+const EDGE: f32 = 0.01;
+/// Where the debug overlay is drawn.
+const OVERLAY_Z: f32 = 1.0;
+
+/// What a tile does to anything moving over it. Kept apart from the
+/// TileData since that only holds how a tile looks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TileAttributes {
+    /// Nothing can move into the tile.
+    pub blocked: bool,
+    /// Slows down anything moving through it.
+    pub water: bool,
+    /// Sends anything stepping onto the tile to this world position.
+    pub warp: Option<Vec2>,
+    /// Free for the game's own uses.
+    pub flags: u32,
+}
+
+impl TileAttributes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The TileAttributes of every tile within a map. Tiles outside of the map
+/// have none so anything can move through them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeGrid {
+    tiles: Vec<TileAttributes>,
+}
+
+impl Default for AttributeGrid {
+    fn default() -> Self {
+        Self {
+            tiles: vec![
+                TileAttributes::default();
+                (MAP_SIZE * MAP_SIZE) as usize
+            ],
+        }
+    }
+}
+
+impl AttributeGrid {
+    pub fn get(&self, tile: UVec2) -> TileAttributes {
+        index(tile)
+            .and_then(|i| self.tiles.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Tiles outside of the map are ignored.
+    pub fn set(&mut self, tile: UVec2, attributes: TileAttributes) {
+        if let Some(i) = index(tile) {
+            self.tiles[i] = attributes;
+        }
+    }
+
+    /// Every tile with any attributes set.
+    pub fn iter(&self) -> impl Iterator<Item = (UVec2, TileAttributes)> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, attributes)| !attributes.is_empty())
+            .map(|(i, attributes)| {
+                let i = i as u32;
+
+                (UVec2::new(i % MAP_SIZE, i / MAP_SIZE), *attributes)
+            })
+    }
+
+    /// The attributes of the tile under a world position on a map at
+    /// `map_pos`.
+    pub fn at(&self, map_pos: Vec2, world: Vec2) -> TileAttributes {
+        let tile = ((world - map_pos) / TILE_SIZE as f32).floor();
+
+        if tile.x < 0.0 || tile.y < 0.0 {
+            return TileAttributes::default();
+        }
+
+        self.get(tile.as_uvec2())
+    }
+
+    /// If any blocked tile is under the box at `pos` of `size`.
+    pub fn is_blocked(&self, map_pos: Vec2, pos: Vec2, size: Vec2) -> bool {
+        is_blocked(&[(map_pos, self)], pos, size)
+    }
+
+    /// Moves the box at `pos` of `size` by `delta` and returns where it
+    /// ends up. See the `move_box` function.
+    pub fn move_box(
+        &self,
+        map_pos: Vec2,
+        pos: Vec2,
+        size: Vec2,
+        delta: Vec2,
+    ) -> Vec2 {
+        move_box(&[(map_pos, self)], pos, size, delta)
+    }
+
+    /// Fills in every blocked tile in red, warps in purple and water in
+    /// blue so the collision can be seen over the map.
+    pub fn debug_overlay(
+        &self,
+        renderer: &mut GpuRenderer,
+        map_pos: Vec2,
+        render_layer: u32,
+    ) -> Result<Mesh2D, GraphicsError> {
+        let mut builder = Mesh2DBuilder::default();
+        let size = Vec2::splat(TILE_SIZE as f32);
+
+        for (tile, attributes) in self.iter() {
+            let color = if attributes.blocked {
+                Color::rgba(255, 40, 40, 110)
+            } else if attributes.warp.is_some() {
+                Color::rgba(200, 60, 255, 110)
+            } else if attributes.water {
+                Color::rgba(40, 120, 255, 110)
+            } else {
+                continue;
+            };
+            let min = tile.as_vec2() * size;
+            let max = min + size;
+
+            builder.polyline(
+                DrawMode::Fill(FillOptions::DEFAULT),
+                &[min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)],
+                OVERLAY_Z,
+                color,
+            )?;
+        }
+
+        let mut overlay =
+            Mesh2D::new(renderer, map_pos.extend(OVERLAY_Z), render_layer);
+
+        overlay.from_builder(builder.finalize());
+        Ok(overlay)
+    }
+}
+
+/// The attributes under a world position from the first of the maps at
+/// each position that has any there.
+pub fn attributes_at(
+    grids: &[(Vec2, &AttributeGrid)],
+    world: Vec2,
+) -> TileAttributes {
+    grids
+        .iter()
+        .map(|(map_pos, grid)| grid.at(*map_pos, world))
+        .find(|attributes| !attributes.is_empty())
+        .unwrap_or_default()
+}
+
+/// If any blocked tile of the maps at each position is under the box at
+/// `pos` of `size`.
+pub fn is_blocked(
+    grids: &[(Vec2, &AttributeGrid)],
+    pos: Vec2,
+    size: Vec2,
+) -> bool {
+    grids.iter().any(|(map_pos, grid)| {
+        tiles_within(*map_pos, pos, size).any(|tile| grid.get(tile).blocked)
+    })
+}
+
+/// Moves the box at `pos` of `size` by `delta` through the maps at each
+/// position and returns where it ends up. Stops flush against any blocked
+/// tile in the way. Each axis is moved on its own so the box slides along
+/// walls. Checking every map lets it cross from one onto the next.
+pub fn move_box(
+    grids: &[(Vec2, &AttributeGrid)],
+    pos: Vec2,
+    size: Vec2,
+    delta: Vec2,
+) -> Vec2 {
+    // Never moving more than a tile at a time means a fast box can not
+    // jump over a blocked tile.
+    let steps = (delta.abs().max_element() / TILE_SIZE as f32)
+        .ceil()
+        .max(1.0);
+    let step = delta / steps;
+    let mut pos = pos;
+
+    for _ in 0..steps as u32 {
+        pos = move_axis(grids, pos, size, step.x, 0);
+        pos = move_axis(grids, pos, size, step.y, 1);
+    }
+
+    pos
+}
+
+// Moves along a single axis, 0 for x and 1 for y. `delta` is at most a tile.
+fn move_axis(
+    grids: &[(Vec2, &AttributeGrid)],
+    pos: Vec2,
+    size: Vec2,
+    delta: f32,
+    axis: usize,
+) -> Vec2 {
+    let mut moved = pos;
+
+    moved[axis] += delta;
+
+    if delta == 0.0 || !is_blocked(grids, moved, size) {
+        return moved;
+    }
+
+    let tile_size = TILE_SIZE as f32;
+
+    for &(map_pos, grid) in grids {
+        for tile in tiles_within(map_pos, moved, size) {
+            // Tiles the box is already within are left out so anything that
+            // ends up inside a blocked tile, like after a warp, can still
+            // walk out of it.
+            if !grid.get(tile).blocked
+                || tiles_within(map_pos, pos, size).any(|within| within == tile)
+            {
+                continue;
+            }
+
+            let edge = map_pos[axis] + tile[axis] as f32 * tile_size;
+
+            if delta > 0.0 {
+                moved[axis] = moved[axis].min(edge - size[axis]);
+            } else {
+                moved[axis] = moved[axis].max(edge + tile_size);
+            }
+        }
+    }
+
+    // Never pushed backwards by a tile it was only just touching.
+    if delta > 0.0 {
+        moved[axis] = moved[axis].max(pos[axis]);
+    } else {
+        moved[axis] = moved[axis].min(pos[axis]);
+    }
+
+    moved
+}
+
+/// Every tile within the map under the box at `pos` of `size`.
+pub fn tiles_within(
+    map_pos: Vec2,
+    pos: Vec2,
+    size: Vec2,
+) -> impl Iterator<Item = UVec2> {
+    let tile_size = TILE_SIZE as f32;
+    let min = ((pos - map_pos + EDGE) / tile_size)
+        .floor()
+        .as_ivec2()
+        .max(IVec2::ZERO);
+    let max = ((pos + size - map_pos - EDGE) / tile_size)
+        .floor()
+        .as_ivec2()
+        .min(IVec2::splat(MAP_SIZE as i32 - 1));
+
+    (min.y..=max.y).flat_map(move |y| {
+        (min.x..=max.x).map(move |x| UVec2::new(x as u32, y as u32))
+    })
+}
+
+fn index(tile: UVec2) -> Option<usize> {
+    (tile.x < MAP_SIZE && tile.y < MAP_SIZE)
+        .then(|| (tile.y * MAP_SIZE + tile.x) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOX: Vec2 = Vec2::splat(10.0);
+
+    fn blocked(tiles: &[(u32, u32)]) -> AttributeGrid {
+        let mut grid = AttributeGrid::default();
+
+        for &(x, y) in tiles {
+            grid.set(
+                UVec2::new(x, y),
+                TileAttributes {
+                    blocked: true,
+                    ..TileAttributes::default()
+                },
+            );
+        }
+
+        grid
+    }
+
+    fn tiles(pos: Vec2, size: Vec2) -> Vec<(u32, u32)> {
+        tiles_within(Vec2::ZERO, pos, size)
+            .map(|tile| (tile.x, tile.y))
+            .collect()
+    }
+
+    #[test]
+    fn stops_flush_moving_either_way() {
+        let grid = blocked(&[(5, 0)]);
+        let start = Vec2::new(70.0, 5.0);
+
+        assert_eq!(
+            grid.move_box(Vec2::ZERO, start, BOX, Vec2::new(25.0, 0.0)),
+            Vec2::new(90.0, 5.0)
+        );
+        assert_eq!(
+            grid.move_box(
+                Vec2::ZERO,
+                Vec2::new(130.0, 5.0),
+                BOX,
+                Vec2::new(-25.0, 0.0)
+            ),
+            Vec2::new(120.0, 5.0)
+        );
+
+        let grid = blocked(&[(0, 5)]);
+
+        assert_eq!(
+            grid.move_box(
+                Vec2::ZERO,
+                Vec2::new(5.0, 70.0),
+                BOX,
+                Vec2::new(0.0, 25.0)
+            ),
+            Vec2::new(5.0, 90.0)
+        );
+        assert_eq!(
+            grid.move_box(
+                Vec2::ZERO,
+                Vec2::new(5.0, 130.0),
+                BOX,
+                Vec2::new(0.0, -25.0)
+            ),
+            Vec2::new(5.0, 120.0)
+        );
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        let grid = blocked(&[(5, 0), (5, 1), (5, 2), (5, 3)]);
+
+        assert_eq!(
+            grid.move_box(
+                Vec2::ZERO,
+                Vec2::new(85.0, 20.0),
+                BOX,
+                Vec2::new(10.0, 10.0)
+            ),
+            Vec2::new(90.0, 30.0)
+        );
+    }
+
+    #[test]
+    fn fast_boxes_do_not_jump_over_tiles() {
+        let grid = blocked(&[(5, 0)]);
+
+        assert_eq!(
+            grid.move_box(
+                Vec2::ZERO,
+                Vec2::new(70.0, 5.0),
+                BOX,
+                Vec2::new(200.0, 0.0)
+            ),
+            Vec2::new(90.0, 5.0)
+        );
+        assert_eq!(
+            grid.move_box(
+                Vec2::ZERO,
+                Vec2::new(300.0, 5.0),
+                BOX,
+                Vec2::new(-250.0, 0.0)
+            ),
+            Vec2::new(120.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn boxes_can_leave_a_blocked_tile_they_start_in() {
+        let grid = blocked(&[(5, 0), (6, 0)]);
+        let inside = Vec2::new(102.0, 5.0);
+
+        assert_eq!(
+            grid.move_box(Vec2::ZERO, inside, BOX, Vec2::new(-30.0, 0.0)),
+            Vec2::new(72.0, 5.0)
+        );
+        // Moving deeper stops at the next blocked tile.
+        assert_eq!(
+            grid.move_box(Vec2::ZERO, inside, BOX, Vec2::new(30.0, 0.0)),
+            Vec2::new(110.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn checks_every_map_the_box_crosses() {
+        let left = AttributeGrid::default();
+        let right = blocked(&[(0, 0)]);
+        let right_pos = Vec2::new((MAP_SIZE * TILE_SIZE) as f32, 0.0);
+        let grids = [(Vec2::ZERO, &left), (right_pos, &right)];
+        let start = right_pos - Vec2::new(15.0, -5.0);
+
+        assert_eq!(
+            move_box(&grids, start, BOX, Vec2::new(20.0, 0.0)),
+            right_pos - Vec2::new(10.0, -5.0)
+        );
+        assert!(attributes_at(&grids, right_pos + 1.0).blocked);
+        assert!(!attributes_at(&grids, right_pos - 1.0).blocked);
+    }
+
+    #[test]
+    fn tiles_within_ignores_touching_edges() {
+        assert_eq!(tiles(Vec2::ZERO, Vec2::splat(20.0)), [(0, 0)]);
+        assert_eq!(
+            tiles(Vec2::splat(10.0), Vec2::splat(20.0)),
+            [(0, 0), (1, 0), (0, 1), (1, 1)]
+        );
+    }
+
+    #[test]
+    fn tiles_within_leaves_out_tiles_past_the_map() {
+        let end = (MAP_SIZE * TILE_SIZE) as f32;
+
+        assert!(tiles(Vec2::splat(-30.0), Vec2::splat(20.0)).is_empty());
+        assert_eq!(tiles(Vec2::splat(-10.0), Vec2::splat(20.0)), [(0, 0)]);
+        assert!(tiles(Vec2::splat(end + 5.0), Vec2::splat(20.0)).is_empty());
+        assert_eq!(
+            tiles(Vec2::new(end - 10.0, 0.0), Vec2::splat(20.0)),
+            [(MAP_SIZE - 1, 0)]
+        );
+    }
+
+    #[test]
+    fn at_finds_the_tile_under_a_position() {
+        let map_pos = Vec2::new(100.0, -100.0);
+        let grid = blocked(&[(3, 0)]);
+
+        assert!(grid.at(map_pos, map_pos + Vec2::new(61.0, 1.0)).blocked);
+        assert!(!grid.at(map_pos, map_pos + Vec2::new(59.0, 1.0)).blocked);
+        assert!(!grid.at(map_pos, map_pos - Vec2::splat(1.0)).blocked);
+        assert!(!grid.at(map_pos, map_pos + Vec2::splat(1000.0)).blocked);
+    }
+}
@@ -0,0 +1,473 @@
+use graphics::*;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::Path};
+use wgpu::{Backends, Dx12Compiler};
+
+/// Config file that gets loaded when `--config` is not passed in.
+pub const DEFAULT_CONFIG_PATH: &str = "demo.toml";
+
+const USAGE: &str = "Usage: demo [--config <file>] [--<setting> <value>]...
+Any setting within the config file can be overridden, for example:
+    --backend vulkan|dx12|metal|gl|primary|all|noop
+    --power high|low
+    --present-mode auto-vsync|auto-no-vsync|fifo|fifo-relaxed|immediate|mailbox
+    --dx12-compiler fxc|static-dxc|auto
+    --width 800 --height 600 --title Demo
+    --resizable true|false --maximize true|false
+    --log-level off|error|warn|info|debug|trace
+    --scene scenes/demo.ron
+    --headless --headless-frames 60 (still needs a display server, e.g. Xvfb)
+    --screenshot-frame 120 --screenshot-dir screenshots
+    --golden --golden-update --golden-dir golden --golden-tolerance 2
+    --tick-rate 60 --frame-limit --frame-wait-ms 20
+    --profile --profile-dump profile.toml
+    --bindings bindings.ron --gamepad-dead-zone 0.15
+    --record --replay --recording recording.ron
+    --map-file map.bin";
+
+/// Which backend the adapter is allowed to be picked from.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendChoice {
+    #[default]
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+    Primary,
+    All,
+    /// Stubs out the GPU completely. Only useful for headless runs.
+    Noop,
+}
+
+impl BackendChoice {
+    pub fn backends(self) -> Backends {
+        match self {
+            BackendChoice::Vulkan => Backends::VULKAN,
+            BackendChoice::Dx12 => Backends::DX12,
+            BackendChoice::Metal => Backends::METAL,
+            BackendChoice::Gl => Backends::GL,
+            BackendChoice::Primary => Backends::PRIMARY,
+            BackendChoice::All => Backends::all(),
+            BackendChoice::Noop => Backends::NOOP,
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerChoice {
+    #[default]
+    High,
+    Low,
+}
+
+impl From<PowerChoice> for AdapterPowerSettings {
+    fn from(power: PowerChoice) -> Self {
+        match power {
+            PowerChoice::High => AdapterPowerSettings::HighPower,
+            PowerChoice::Low => AdapterPowerSettings::LowPower,
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentChoice {
+    AutoVsync,
+    #[default]
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentChoice> for wgpu::PresentMode {
+    fn from(mode: PresentChoice) -> Self {
+        match mode {
+            PresentChoice::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentChoice::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentChoice::Fifo => wgpu::PresentMode::Fifo,
+            PresentChoice::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentChoice::Immediate => wgpu::PresentMode::Immediate,
+            PresentChoice::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dx12CompilerChoice {
+    #[default]
+    Fxc,
+    StaticDxc,
+    Auto,
+}
+
+impl From<Dx12CompilerChoice> for Dx12Compiler {
+    fn from(compiler: Dx12CompilerChoice) -> Self {
+        match compiler {
+            Dx12CompilerChoice::Fxc => Dx12Compiler::Fxc,
+            Dx12CompilerChoice::StaticDxc => Dx12Compiler::StaticDxc,
+            Dx12CompilerChoice::Auto => Dx12Compiler::Auto,
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevelChoice {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevelChoice> for LevelFilter {
+    fn from(level: LogLevelChoice) -> Self {
+        match level {
+            LogLevelChoice::Off => LevelFilter::Off,
+            LogLevelChoice::Error => LevelFilter::Error,
+            LogLevelChoice::Warn => LevelFilter::Warn,
+            LogLevelChoice::Info => LevelFilter::Info,
+            LogLevelChoice::Debug => LevelFilter::Debug,
+            LogLevelChoice::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Settings used to setup the Window, Instance and Adapter.
+/// Loaded from a TOML file and then overridden by the command line.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DemoConfig {
+    pub backend: BackendChoice,
+    pub power: PowerChoice,
+    pub present_mode: PresentChoice,
+    pub dx12_compiler: Dx12CompilerChoice,
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub resizable: bool,
+    /// If the maximize button is enabled.
+    pub maximize: bool,
+    pub log_level: LogLevelChoice,
+    /// RON scene file to build the State from.
+    pub scene: String,
+    /// Renders into an offscreen texture with a hidden window instead of
+    /// presenting to the screen. Exits after `headless_frames` frames. The
+    /// renderer is still built from that window so a display server such as
+    /// X11, Wayland or Xvfb is needed.
+    pub headless: bool,
+    pub headless_frames: u32,
+    /// Saves a screenshot of this frame. 0 disables it. Windowed runs exit
+    /// once it is saved while headless runs continue to `headless_frames`.
+    pub screenshot_frame: u32,
+    /// Folder screenshots are saved into.
+    pub screenshot_dir: String,
+    /// Runs headless on a software adapter with animations frozen and
+    /// compares the last frame against `<golden_dir>/<scene name>.png`.
+    pub golden: bool,
+    /// Overwrites the reference images instead of comparing against them.
+    /// Without it a missing reference fails the run.
+    pub golden_update: bool,
+    pub golden_dir: String,
+    /// How far apart each color channel can be before a pixel fails.
+    pub golden_tolerance: u8,
+    /// How many times per second the game logic is updated.
+    pub tick_rate: u32,
+    /// Waits `frame_wait_ms` between each rendered frame when enabled.
+    pub frame_limit: bool,
+    pub frame_wait_ms: u64,
+    /// Times each renderer's update and finalize on the CPU and each draw
+    /// group on the GPU when the adapter supports timestamp queries.
+    pub profile: bool,
+    /// File the profile is written to when the demo exits.
+    pub profile_dump: String,
+    /// RON file the controls are loaded from and saved to once rebound.
+    pub bindings: String,
+    /// How far a gamepad stick or trigger has to move before it counts.
+    pub gamepad_dead_zone: f32,
+    /// Writes every input event to `recording` every few seconds and on
+    /// exit.
+    pub record: bool,
+    /// Plays `recording` back one fixed update per frame instead of
+    /// reading the players input.
+    pub replay: bool,
+    pub recording: String,
+    /// Where the map is saved. Saved as RON when it ends in .ron and in the
+    /// binary format otherwise.
+    pub map_file: String,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendChoice::default(),
+            power: PowerChoice::default(),
+            present_mode: PresentChoice::default(),
+            dx12_compiler: Dx12CompilerChoice::default(),
+            width: 800,
+            height: 600,
+            title: String::from("Demo"),
+            resizable: true,
+            maximize: false,
+            log_level: LogLevelChoice::default(),
+            scene: String::from("scenes/demo.ron"),
+            headless: false,
+            headless_frames: 60,
+            screenshot_frame: 0,
+            screenshot_dir: String::from("screenshots"),
+            golden: false,
+            golden_update: false,
+            golden_dir: String::from("golden"),
+            golden_tolerance: 2,
+            tick_rate: 60,
+            frame_limit: false,
+            frame_wait_ms: crate::WAIT_TIME.as_millis() as u64,
+            profile: false,
+            profile_dump: String::from("profile.toml"),
+            bindings: String::from("bindings.ron"),
+            gamepad_dead_zone: 0.15,
+            record: false,
+            replay: false,
+            recording: String::from("recording.ron"),
+            map_file: String::from("map.bin"),
+        }
+    }
+}
+
+impl DemoConfig {
+    /// Loads the config file and applies the command line overrides from
+    /// the processes arguments.
+    pub fn load() -> Result<Self, GraphicsError> {
+        Self::from_args(
+            env::args().skip(1),
+            Some(Path::new(DEFAULT_CONFIG_PATH)),
+        )
+    }
+
+    /// Applies the command line arguments over the file passed with
+    /// `--config`. Without one the file at `default_path` is used if it
+    /// exists.
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+        default_path: Option<&Path>,
+    ) -> Result<Self, GraphicsError> {
+        let mut config_path = None;
+        let mut overrides = toml::Table::new();
+        let mut args = args.into_iter().peekable();
+        let defaults = default_table();
+
+        while let Some(arg) = args.next() {
+            let Some(key) = arg.strip_prefix("--") else {
+                return Err(usage_error(&format!("unexpected argument {arg}")));
+            };
+
+            if key == "help" {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+
+            // flags without a value are treated as being turned on.
+            let value = match args.peek() {
+                Some(next) if !next.starts_with("--") => args.next(),
+                _ => None,
+            };
+
+            if key == "config" {
+                config_path = Some(value.ok_or_else(|| {
+                    usage_error("--config requires a file path")
+                })?);
+                continue;
+            }
+
+            let key = key.replace('_', "-");
+            let value = match defaults.get(&key) {
+                Some(default) => parse_value(&key, default, value)?,
+                None => {
+                    return Err(usage_error(&format!(
+                        "unknown setting --{key}"
+                    )));
+                }
+            };
+
+            overrides.insert(key, value);
+        }
+
+        let config_path = config_path
+            .as_deref()
+            .map(Path::new)
+            .or(default_path.filter(|path| path.exists()));
+        let mut table = match config_path {
+            Some(path) => read_table(path)?,
+            None => toml::Table::new(),
+        };
+
+        table.extend(overrides);
+
+        let mut config: Self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| usage_error(&format!("invalid config: {e}")))?;
+
+        // Golden runs need to be repeatable so they never use the Surface.
+        if config.golden {
+            config.headless = true;
+        }
+
+        Ok(config)
+    }
+}
+
+// Every setting with its default value so command line values can be
+// parsed as the same type.
+fn default_table() -> toml::Table {
+    match toml::Value::try_from(DemoConfig::default()) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => unreachable!("DemoConfig always serializes into a table"),
+    }
+}
+
+// Parses a command line value as the same type as the setting's default.
+fn parse_value(
+    key: &str,
+    default: &toml::Value,
+    value: Option<String>,
+) -> Result<toml::Value, GraphicsError> {
+    let invalid = |kind: &str| usage_error(&format!("--{key} requires {kind}"));
+
+    match (default, value) {
+        (toml::Value::Boolean(_), None) => Ok(toml::Value::Boolean(true)),
+        (toml::Value::Boolean(_), Some(value)) => value
+            .parse()
+            .map(toml::Value::Boolean)
+            .map_err(|_| invalid("true or false")),
+        (toml::Value::Integer(_), Some(value)) => value
+            .parse()
+            .map(toml::Value::Integer)
+            .map_err(|_| invalid("a whole number")),
+        (toml::Value::Float(_), Some(value)) => value
+            .parse()
+            .map(toml::Value::Float)
+            .map_err(|_| invalid("a number")),
+        (toml::Value::String(_), Some(value)) => Ok(toml::Value::String(value)),
+        _ => Err(invalid("a value")),
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table, GraphicsError> {
+    let data = fs::read_to_string(path)?;
+
+    data.parse().map_err(|e| {
+        OtherError::new(&format!(
+            "failed to parse config {}: {e}",
+            path.display()
+        ))
+        .into()
+    })
+}
+
+fn usage_error(msg: &str) -> GraphicsError {
+    OtherError::new(&format!("{msg}\n{USAGE}")).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from(args: &[&str]) -> Result<DemoConfig, GraphicsError> {
+        DemoConfig::from_args(args.iter().map(|arg| arg.to_string()), None)
+    }
+
+    fn args(args: &[&str]) -> DemoConfig {
+        from(args).unwrap()
+    }
+
+    #[test]
+    fn numbers_become_integers() {
+        let config = args(&["--width", "1024", "--headless-frames", "3"]);
+
+        assert_eq!(config.width, 1024);
+        assert_eq!(config.headless_frames, 3);
+    }
+
+    #[test]
+    fn words_become_strings() {
+        let config = args(&["--backend", "gl", "--title", "Golden Demo"]);
+
+        assert_eq!(config.backend, BackendChoice::Gl);
+        assert_eq!(config.title, "Golden Demo");
+    }
+
+    #[test]
+    fn values_take_the_type_of_their_setting() {
+        let config = args(&["--title", "123", "--gamepad-dead-zone", "1"]);
+
+        assert_eq!(config.title, "123");
+        assert_eq!(config.gamepad_dead_zone, 1.0);
+    }
+
+    #[test]
+    fn bare_flags_become_true() {
+        let config = args(&["--profile", "--record", "--tick-rate", "30"]);
+
+        assert!(config.profile);
+        assert!(config.record);
+        assert_eq!(config.tick_rate, 30);
+    }
+
+    #[test]
+    fn underscores_match_dashes() {
+        let config = args(&["--frame_wait_ms", "5"]);
+
+        assert_eq!(config.frame_wait_ms, 5);
+    }
+
+    #[test]
+    fn golden_implies_headless() {
+        let config = args(&["--golden"]);
+
+        assert!(config.golden);
+        assert!(config.headless);
+    }
+
+    #[test]
+    fn arguments_override_the_config_file() {
+        let path = env::temp_dir()
+            .join(format!("demo_config_test_{}.toml", std::process::id()));
+
+        fs::write(&path, "width = 640\ntitle = \"From File\"\n").unwrap();
+
+        let config =
+            args(&["--config", path.to_str().unwrap(), "--width", "320"]);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.width, 320);
+        assert_eq!(config.title, "From File");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(from(&["demo.toml"]).is_err());
+        assert!(from(&["--width", "wide"]).is_err());
+        assert!(from(&["--width"]).is_err());
+        assert!(from(&["--profile", "maybe"]).is_err());
+        assert!(from(&["--not-a-setting", "1"]).is_err());
+        assert!(from(&["--config"]).is_err());
+    }
+}
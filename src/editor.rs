@@ -0,0 +1,863 @@
+use crate::{
+    mapfile::{Tilesheets, empty_tile},
+    scene::{MAP_LAYERS, MAP_SIZE, TILE_SIZE, TilesheetDesc},
+};
+use graphics::{
+    input::{InputEvent, Key},
+    *,
+};
+use log::info;
+use std::collections::VecDeque;
+use winit::{event::MouseButton, keyboard::NamedKey};
+
+/// Size of each cell of the panel. Tiles are drawn within them at their
+/// real size.
+const CELL: f32 = 24.0;
+/// Space between the edge of the panel and its cells.
+const PADDING: f32 = 8.0;
+/// Cells across the panel. Also the amount of layers there are.
+const COLUMNS: u32 = MAP_LAYERS;
+/// Rows of tiles shown at once. The rest are reached by paging.
+const PALETTE_ROWS: u32 = 7;
+const PAGE_SIZE: u32 = COLUMNS * PALETTE_ROWS;
+/// Rows of the panel from the bottom up. The palette sits above them.
+const ALPHA_ROW: u32 = 0;
+const COLOR_ROW: u32 = 1;
+const TOOL_ROW: u32 = 2;
+const LAYER_ROW: u32 = 3;
+const PALETTE_ROW: u32 = 4;
+const PANEL_SIZE: Vec2 = Vec2::new(
+    PADDING * 2.0 + COLUMNS as f32 * CELL,
+    PADDING * 2.0 + (PALETTE_ROW + PALETTE_ROWS) as f32 * CELL,
+);
+/// Space between the panel and the bottom right of the window.
+const MARGIN: f32 = 10.0;
+/// How many strokes can be undone.
+const UNDO_LIMIT: usize = 100;
+const COLORS: [[u8; 3]; 8] = [
+    [255, 255, 255],
+    [255, 80, 80],
+    [80, 255, 80],
+    [80, 120, 255],
+    [255, 255, 80],
+    [255, 160, 40],
+    [200, 80, 255],
+    [60, 60, 60],
+];
+const ALPHAS: [u8; 4] = [255, 192, 128, 64];
+const TOOLS: [EditorTool; 3] =
+    [EditorTool::Paint, EditorTool::Erase, EditorTool::Fill];
+
+/// What clicking on the map does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorTool {
+    /// Sets each tile the mouse is dragged over.
+    Paint,
+    /// Empties each tile the mouse is dragged over.
+    Erase,
+    /// Sets every touching tile that matches the clicked one.
+    Fill,
+}
+
+impl EditorTool {
+    // Shown within the tool's cell since there is no text in the panel.
+    fn color(self) -> Color {
+        match self {
+            EditorTool::Paint => Color::rgba(80, 200, 80, 255),
+            EditorTool::Erase => Color::rgba(200, 80, 80, 255),
+            EditorTool::Fill => Color::rgba(80, 120, 220, 255),
+        }
+    }
+}
+
+/// The parts of a TileData the editor sets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tile {
+    id: usize,
+    color: Color,
+    anim_time: u32,
+}
+
+impl Tile {
+    fn get(map: &Map, pos: UVec3) -> Self {
+        let data = map.get_tile(pos);
+
+        Self {
+            id: data.id,
+            color: data.color,
+            anim_time: data.anim_time,
+        }
+    }
+
+    fn data(self) -> TileData {
+        TileData {
+            id: self.id,
+            color: self.color,
+            anim_time: self.anim_time,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TileChange {
+    pos: UVec3,
+    before: Tile,
+    after: Tile,
+}
+
+/// A tilesheet uploaded into the image atlas so its tiles can be shown.
+#[derive(Clone, Debug)]
+struct PaletteSheet {
+    name: String,
+    texture: usize,
+    tile_size: u32,
+    columns: u32,
+    count: u32,
+    /// Given to each tile painted from the sheet.
+    anim_time: u32,
+}
+
+/// Something within the panel that can be clicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PanelCell {
+    Alpha(usize),
+    Color(usize),
+    Tool(EditorTool),
+    Layer(u32),
+    /// Index of the tile within the current page.
+    Tile(u32),
+}
+
+/// Paints the scene's map with the mouse. Drawn as a panel in the bottom
+/// right of the window with the tiles of a tilesheet to pick from and rows
+/// to pick the color, alpha, tool and layer.
+pub struct MapEditor {
+    pub open: bool,
+    tool: EditorTool,
+    layer: u32,
+    sheets: Vec<PaletteSheet>,
+    sheet: usize,
+    page: u32,
+    /// Index of the selected tile within the sheet.
+    tile: u32,
+    color: usize,
+    alpha: usize,
+    undo: VecDeque<Vec<TileChange>>,
+    redo: Vec<Vec<TileChange>>,
+    /// Changes made since the left button went down. Pushed onto `undo` once
+    /// it is released.
+    stroke: Vec<TileChange>,
+    painting: bool,
+    /// The tile last painted within the stroke so the tiles between it and
+    /// the next can be filled in when the mouse moves fast.
+    last_painted: Option<UVec2>,
+    ctrl: bool,
+    shift: bool,
+    /// Bottom left of the panel in window pixels with y going up.
+    origin: Vec2,
+    background: Rect,
+    swatches: Vec<(PanelCell, Rect)>,
+    /// Outlines around the selected tile, color, alpha, tool and layer.
+    markers: [Rect; 5],
+    tiles: Vec<Image>,
+    /// Palette tiles have their own renderer so they are drawn over the UI
+    /// and only within the main view.
+    pub tile_renderer: ImageRenderer,
+    /// The palette's Images need recreating before they are next rendered.
+    tiles_dirty: bool,
+}
+
+impl MapEditor {
+    /// Uploads each tilesheet that was loaded into the map into the image
+    /// atlas so its tiles can be shown within the palette.
+    pub fn new(
+        renderer: &mut GpuRenderer,
+        image_atlas: &mut AtlasSet,
+        descs: &[TilesheetDesc],
+        tilesheets: &Tilesheets,
+    ) -> Result<Self, GraphicsError> {
+        let mut sheets = Vec::with_capacity(descs.len());
+
+        for desc in descs {
+            let (width, height) =
+                image::image_dimensions(&desc.path).map_err(|e| {
+                    OtherError::new(&format!(
+                        "failed to read tilesheet {}: {e}",
+                        desc.path
+                    ))
+                })?;
+            let texture = Texture::from_file(&desc.path)?
+                .upload(&desc.name, image_atlas, renderer)
+                .ok_or_else(|| {
+                    OtherError::new(&format!(
+                        "failed to upload palette for tilesheet {}",
+                        desc.name
+                    ))
+                })?;
+            let columns = (width / desc.tile_size).max(1);
+            let rows = height / desc.tile_size;
+
+            sheets.push(PaletteSheet {
+                name: desc.name.clone(),
+                texture,
+                tile_size: desc.tile_size,
+                columns,
+                count: (columns * rows).min(tilesheets.len(&desc.name) as u32),
+                anim_time: desc.anim_time,
+            });
+        }
+
+        let mut background = Rect::new(
+            renderer,
+            Vec3::new(0.0, 0.0, 1.0),
+            PANEL_SIZE,
+            Color::rgba(0, 0, 0, 160),
+            0,
+        );
+
+        background
+            .set_border_width(1.0)
+            .set_border_color(Color::rgba(255, 255, 255, 120))
+            .set_camera_view(CameraView::SubView1);
+
+        let cells = ALPHAS
+            .iter()
+            .enumerate()
+            .map(|(i, &alpha)| {
+                (PanelCell::Alpha(i), Color::rgba(255, 255, 255, alpha))
+            })
+            .chain(COLORS.iter().enumerate().map(|(i, &[r, g, b])| {
+                (PanelCell::Color(i), Color::rgba(r, g, b, 255))
+            }))
+            .chain(
+                TOOLS
+                    .iter()
+                    .map(|&tool| (PanelCell::Tool(tool), tool.color())),
+            )
+            .chain((0..MAP_LAYERS).map(|layer| {
+                // Higher layers are lighter.
+                let shade = 60 + (layer * 160 / MAP_LAYERS) as u8;
+
+                (
+                    PanelCell::Layer(layer),
+                    Color::rgba(shade, shade, shade, 255),
+                )
+            }));
+        let swatches = cells
+            .map(|(cell, color)| {
+                let mut rect = Rect::new(
+                    renderer,
+                    Vec3::new(0.0, 0.0, 1.0),
+                    Vec2::splat(CELL - 6.0),
+                    color,
+                    0,
+                );
+
+                rect.set_camera_view(CameraView::SubView1);
+                (cell, rect)
+            })
+            .collect();
+        let markers = std::array::from_fn(|_| {
+            let mut rect = Rect::new(
+                renderer,
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec2::splat(CELL),
+                Color::rgba(0, 0, 0, 0),
+                1,
+            );
+
+            rect.set_border_width(2.0)
+                .set_border_color(Color::rgba(255, 255, 0, 255))
+                .set_camera_view(CameraView::SubView1);
+            rect
+        });
+
+        Ok(Self {
+            open: false,
+            tool: EditorTool::Paint,
+            layer: 0,
+            sheets,
+            sheet: 0,
+            page: 0,
+            tile: 0,
+            color: 0,
+            alpha: 0,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: Vec::new(),
+            painting: false,
+            last_painted: None,
+            ctrl: false,
+            shift: false,
+            origin: Vec2::NAN,
+            background,
+            swatches,
+            markers,
+            tiles: Vec::new(),
+            tile_renderer: ImageRenderer::new(renderer)?,
+            tiles_dirty: true,
+        })
+    }
+
+    /// Ends any stroke being painted when closed so it can be undone.
+    pub fn set_open(&mut self, open: bool) {
+        if !open {
+            self.end_stroke();
+        }
+
+        self.open = open;
+    }
+
+    /// Takes clicks on the map and panel along with the editor's shortcuts.
+    /// `cursor` is the mouse within the window and `world` where it is on
+    /// the map. Returns true when the event was used so nothing else gets
+    /// it.
+    pub fn handle_event(
+        &mut self,
+        event: &InputEvent,
+        cursor: Option<Vec2>,
+        world: Option<Vec2>,
+        screen_size: Vec2,
+        map: &mut Map,
+        tilesheets: &Tilesheets,
+    ) -> bool {
+        if !self.open {
+            return false;
+        }
+
+        self.layout(screen_size);
+
+        match event {
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: true,
+            } => {
+                // Window positions go down while the panel goes up.
+                let panel =
+                    cursor.map(|pos| Vec2::new(pos.x, screen_size.y - pos.y));
+
+                if let Some(cell) = panel.and_then(|pos| self.cell_at(pos)) {
+                    self.select(cell);
+                    return true;
+                }
+
+                if panel.is_some_and(|pos| self.panel_contains(pos)) {
+                    return true;
+                }
+
+                let Some(pos) = world.and_then(|world| tile_at(map.pos, world))
+                else {
+                    return false;
+                };
+
+                match self.tool {
+                    EditorTool::Paint | EditorTool::Erase => {
+                        self.painting = true;
+                        self.paint(map, pos, tilesheets);
+                    }
+                    EditorTool::Fill => {
+                        self.fill(map, pos, tilesheets);
+                        self.end_stroke();
+                    }
+                }
+
+                true
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: false,
+            } if self.painting => {
+                self.end_stroke();
+                true
+            }
+            // Positions are left for everything else since dragging and
+            // picking need them too.
+            InputEvent::MousePosition { .. } => {
+                if self.painting
+                    && let Some(pos) =
+                        world.and_then(|world| tile_at(map.pos, world))
+                {
+                    let from = self.last_painted.unwrap_or(pos);
+
+                    for pos in tile_line(from, pos) {
+                        self.paint(map, pos, tilesheets);
+                    }
+                }
+
+                false
+            }
+            InputEvent::KeyInput { key, pressed, .. } => {
+                self.handle_key(key, *pressed, map)
+            }
+            InputEvent::WindowFocused(false) => {
+                self.ctrl = false;
+                self.shift = false;
+                self.end_stroke();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Pushes the panel's rects. Only called while the editor is open.
+    /// Returns how many rects were pushed.
+    pub fn update_rects(
+        &mut self,
+        ui_renderer: &mut RectRenderer,
+        renderer: &mut GpuRenderer,
+        atlas: &mut AtlasSet,
+        screen_size: Vec2,
+    ) -> usize {
+        self.layout(screen_size);
+
+        ui_renderer.update(&mut self.background, renderer, atlas, 0);
+
+        for (_, rect) in self.swatches.iter_mut() {
+            ui_renderer.update(rect, renderer, atlas, 0);
+        }
+
+        let mut count = 1 + self.swatches.len();
+
+        let selected = [
+            Some(PanelCell::Alpha(self.alpha)),
+            Some(PanelCell::Color(self.color)),
+            Some(PanelCell::Tool(self.tool)),
+            Some(PanelCell::Layer(self.layer)),
+            self.tile
+                .checked_sub(self.page * PAGE_SIZE)
+                .filter(|&tile| tile < PAGE_SIZE)
+                .map(PanelCell::Tile),
+        ];
+
+        for (marker, cell) in self.markers.iter_mut().zip(selected) {
+            // The selected tile can be on another page.
+            let Some(cell) = cell else {
+                continue;
+            };
+            let pos = cell_pos(self.origin, cell);
+
+            if marker.pos.truncate() != pos {
+                marker.pos = pos.extend(marker.pos.z);
+                marker.changed = true;
+            }
+
+            ui_renderer.update(marker, renderer, atlas, 0);
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Pushes the palette's tiles into its own renderer. Returns how many
+    /// were pushed.
+    pub fn update_tiles(
+        &mut self,
+        renderer: &mut GpuRenderer,
+        atlas: &mut AtlasSet,
+    ) -> usize {
+        if self.tiles_dirty {
+            self.tiles = self.page_tiles(renderer);
+            self.tiles_dirty = false;
+        }
+
+        for tile in self.tiles.iter_mut() {
+            self.tile_renderer.update(tile, renderer, atlas, 0);
+        }
+
+        self.tile_renderer.finalize(renderer);
+        self.tiles.len()
+    }
+
+    // Moves everything so the panel stays in the bottom right of the window.
+    fn layout(&mut self, screen_size: Vec2) {
+        let origin = Vec2::new(screen_size.x - PANEL_SIZE.x - MARGIN, MARGIN)
+            .max(Vec2::ZERO);
+
+        if origin == self.origin {
+            return;
+        }
+
+        self.origin = origin;
+        self.background.pos = origin.extend(self.background.pos.z);
+        self.background.changed = true;
+
+        for (cell, rect) in self.swatches.iter_mut() {
+            let pos = cell_pos(origin, *cell) + Vec2::splat(3.0);
+
+            rect.pos = pos.extend(rect.pos.z);
+            rect.changed = true;
+        }
+
+        self.tiles_dirty = true;
+    }
+
+    // Creates an Image for each tile on the current page of the sheet.
+    fn page_tiles(&self, renderer: &mut GpuRenderer) -> Vec<Image> {
+        let Some(sheet) = self.sheets.get(self.sheet) else {
+            return Vec::new();
+        };
+        let start = self.page * PAGE_SIZE;
+        let end = (start + PAGE_SIZE).min(sheet.count);
+        let offset = Vec2::splat((CELL - TILE_SIZE as f32) * 0.5);
+
+        (start..end)
+            .map(|tile| {
+                let pos = cell_pos(self.origin, PanelCell::Tile(tile - start))
+                    + offset;
+                let uv = Vec4::new(
+                    (tile % sheet.columns * sheet.tile_size) as f32,
+                    (tile / sheet.columns * sheet.tile_size) as f32,
+                    sheet.tile_size as f32,
+                    sheet.tile_size as f32,
+                );
+                let mut image = Image::new(
+                    Some(sheet.texture),
+                    renderer,
+                    pos.extend(1.0),
+                    Vec2::splat(TILE_SIZE as f32),
+                    uv,
+                    1,
+                );
+
+                image.camera_view = CameraView::SubView1;
+                image
+            })
+            .collect()
+    }
+
+    fn handle_key(&mut self, key: &Key, pressed: bool, map: &mut Map) -> bool {
+        match key {
+            Key::Named(NamedKey::Control) => self.ctrl = pressed,
+            Key::Named(NamedKey::Shift) => self.shift = pressed,
+            _ => {}
+        }
+
+        if !pressed {
+            return false;
+        }
+
+        match key {
+            Key::Character(c) if self.ctrl => match c.to_ascii_lowercase() {
+                'z' if self.shift => self.redo(map),
+                'z' => self.undo(map),
+                'y' => self.redo(map),
+                _ => return false,
+            },
+            Key::Character('1') => self.select(PanelCell::Tool(TOOLS[0])),
+            Key::Character('2') => self.select(PanelCell::Tool(TOOLS[1])),
+            Key::Character('3') => self.select(PanelCell::Tool(TOOLS[2])),
+            Key::Character('[') => self.turn_page(-1),
+            Key::Character(']') => self.turn_page(1),
+            Key::Named(NamedKey::PageUp) => self
+                .select(PanelCell::Layer((self.layer + 1).min(MAP_LAYERS - 1))),
+            Key::Named(NamedKey::PageDown) => {
+                self.select(PanelCell::Layer(self.layer.saturating_sub(1)))
+            }
+            Key::Named(NamedKey::Tab) if !self.sheets.is_empty() => {
+                self.sheet = (self.sheet + 1) % self.sheets.len();
+                self.page = 0;
+                self.tile = 0;
+                self.tiles_dirty = true;
+                info!(
+                    "editor: using tilesheet {}",
+                    self.sheets[self.sheet].name
+                );
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn select(&mut self, cell: PanelCell) {
+        match cell {
+            PanelCell::Alpha(alpha) => self.alpha = alpha,
+            PanelCell::Color(color) => self.color = color,
+            PanelCell::Tool(tool) => {
+                self.tool = tool;
+                info!("editor: {tool:?} tool");
+            }
+            PanelCell::Layer(layer) => {
+                self.layer = layer;
+                info!("editor: editing layer {layer}");
+            }
+            PanelCell::Tile(tile) => self.tile = self.page * PAGE_SIZE + tile,
+        }
+    }
+
+    fn turn_page(&mut self, by: i32) {
+        let Some(sheet) = self.sheets.get(self.sheet) else {
+            return;
+        };
+        let pages = sheet.count.div_ceil(PAGE_SIZE).max(1);
+
+        self.page = self.page.saturating_add_signed(by).min(pages - 1);
+        self.tiles_dirty = true;
+    }
+
+    // What the current tool sets tiles to. None if there is no tile to
+    // paint with.
+    fn brush(&self, tilesheets: &Tilesheets) -> Option<Tile> {
+        if self.tool == EditorTool::Erase {
+            let empty = empty_tile();
+
+            return Some(Tile {
+                id: empty.id,
+                color: empty.color,
+                anim_time: empty.anim_time,
+            });
+        }
+
+        let sheet = self.sheets.get(self.sheet)?;
+        let [r, g, b] = COLORS[self.color];
+
+        Some(Tile {
+            id: tilesheets.atlas_id(&sheet.name, self.tile)?,
+            color: Color::rgba(r, g, b, ALPHAS[self.alpha]),
+            anim_time: sheet.anim_time,
+        })
+    }
+
+    fn paint(&mut self, map: &mut Map, pos: UVec2, tilesheets: &Tilesheets) {
+        if let Some(brush) = self.brush(tilesheets) {
+            self.set(map, pos.extend(self.layer), brush);
+        }
+
+        self.last_painted = Some(pos);
+    }
+
+    // Flood fills the tiles touching `pos` that match it on the same layer.
+    fn fill(&mut self, map: &mut Map, pos: UVec2, tilesheets: &Tilesheets) {
+        let Some(brush) = self.brush(tilesheets) else {
+            return;
+        };
+        let target = Tile::get(map, pos.extend(self.layer));
+
+        if target == brush {
+            return;
+        }
+
+        let mut open = vec![pos];
+
+        while let Some(pos) = open.pop() {
+            let tile = pos.extend(self.layer);
+
+            if Tile::get(map, tile) != target {
+                continue;
+            }
+
+            self.set(map, tile, brush);
+
+            if pos.x > 0 {
+                open.push(pos - UVec2::X);
+            }
+
+            if pos.y > 0 {
+                open.push(pos - UVec2::Y);
+            }
+
+            if pos.x + 1 < MAP_SIZE {
+                open.push(pos + UVec2::X);
+            }
+
+            if pos.y + 1 < MAP_SIZE {
+                open.push(pos + UVec2::Y);
+            }
+        }
+    }
+
+    fn set(&mut self, map: &mut Map, pos: UVec3, tile: Tile) {
+        let before = Tile::get(map, pos);
+
+        if before == tile {
+            return;
+        }
+
+        map.set_tile(pos, tile.data());
+        self.stroke.push(TileChange {
+            pos,
+            before,
+            after: tile,
+        });
+    }
+
+    fn end_stroke(&mut self) {
+        self.painting = false;
+        self.last_painted = None;
+
+        if self.stroke.is_empty() {
+            return;
+        }
+
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.pop_front();
+        }
+
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        self.redo.clear();
+    }
+
+    fn undo(&mut self, map: &mut Map) {
+        self.end_stroke();
+
+        let Some(stroke) = self.undo.pop_back() else {
+            return;
+        };
+
+        // Backwards so tiles changed twice end up how they started.
+        for change in stroke.iter().rev() {
+            map.set_tile(change.pos, change.before.data());
+        }
+
+        self.redo.push(stroke);
+    }
+
+    fn redo(&mut self, map: &mut Map) {
+        self.end_stroke();
+
+        let Some(stroke) = self.redo.pop() else {
+            return;
+        };
+
+        for change in stroke.iter() {
+            map.set_tile(change.pos, change.after.data());
+        }
+
+        self.undo.push_back(stroke);
+    }
+
+    fn panel_contains(&self, pos: Vec2) -> bool {
+        pos.cmpge(self.origin).all()
+            && pos.cmplt(self.origin + PANEL_SIZE).all()
+    }
+
+    // The cell under `pos`, which is within the window with y going up.
+    fn cell_at(&self, pos: Vec2) -> Option<PanelCell> {
+        let local = (pos - self.origin - PADDING) / CELL;
+
+        if local.x < 0.0 || local.y < 0.0 || local.x >= COLUMNS as f32 {
+            return None;
+        }
+
+        let column = local.x as u32;
+        let row = local.y as u32;
+
+        match row {
+            ALPHA_ROW => (column < ALPHAS.len() as u32)
+                .then_some(PanelCell::Alpha(column as usize)),
+            COLOR_ROW => (column < COLORS.len() as u32)
+                .then_some(PanelCell::Color(column as usize)),
+            TOOL_ROW => TOOLS
+                .get(column as usize)
+                .map(|&tool| PanelCell::Tool(tool)),
+            LAYER_ROW => Some(PanelCell::Layer(column)),
+            _ if row < PALETTE_ROW + PALETTE_ROWS => {
+                // The first tile is in the top left.
+                let tile =
+                    (PALETTE_ROW + PALETTE_ROWS - 1 - row) * COLUMNS + column;
+                let count =
+                    self.sheets.get(self.sheet).map_or(0, |sheet| sheet.count);
+
+                (self.page * PAGE_SIZE + tile < count)
+                    .then_some(PanelCell::Tile(tile))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Bottom left of a cell within the panel.
+fn cell_pos(origin: Vec2, cell: PanelCell) -> Vec2 {
+    let (column, row) = match cell {
+        PanelCell::Alpha(i) => (i as u32, ALPHA_ROW),
+        PanelCell::Color(i) => (i as u32, COLOR_ROW),
+        PanelCell::Tool(tool) => (
+            TOOLS.iter().position(|&t| t == tool).unwrap_or(0) as u32,
+            TOOL_ROW,
+        ),
+        PanelCell::Layer(layer) => (layer, LAYER_ROW),
+        PanelCell::Tile(tile) => (
+            tile % COLUMNS,
+            PALETTE_ROW + PALETTE_ROWS - 1 - tile / COLUMNS,
+        ),
+    };
+
+    origin + PADDING + Vec2::new(column as f32, row as f32) * CELL
+}
+
+// The tile within the map at the world position.
+fn tile_at(map_pos: Vec2, world: Vec2) -> Option<UVec2> {
+    let tile = ((world - map_pos) / TILE_SIZE as f32).floor();
+
+    (tile.cmpge(Vec2::ZERO).all()
+        && tile.cmplt(Vec2::splat(MAP_SIZE as f32)).all())
+    .then(|| tile.as_uvec2())
+}
+
+// Every tile on the line from `from` to `to` including both ends, stepping
+// to a neighbouring tile each time so none are skipped.
+fn tile_line(from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let (mut x, mut y) = (from.x as i32, from.y as i32);
+    let (end_x, end_y) = (to.x as i32, to.y as i32);
+    let dx = (end_x - x).abs();
+    let dy = -(end_y - y).abs();
+    let step_x = if x < end_x { 1 } else { -1 };
+    let step_y = if y < end_y { 1 } else { -1 };
+    let mut error = dx + dy;
+    let mut tiles = Vec::with_capacity((dx - dy) as usize + 1);
+
+    loop {
+        tiles.push(UVec2::new(x as u32, y as u32));
+
+        if x == end_x && y == end_y {
+            return tiles;
+        }
+
+        let doubled = error * 2;
+
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(from: (u32, u32), to: (u32, u32)) -> Vec<(u32, u32)> {
+        tile_line(UVec2::new(from.0, from.1), UVec2::new(to.0, to.1))
+            .into_iter()
+            .map(|tile| (tile.x, tile.y))
+            .collect()
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        assert_eq!(line((3, 3), (3, 3)), [(3, 3)]);
+        assert_eq!(line((0, 0), (3, 0)), [(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(line((2, 3), (2, 0)), [(2, 3), (2, 2), (2, 1), (2, 0)]);
+        assert_eq!(line((3, 3), (0, 0)), [(3, 3), (2, 2), (1, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn lines_never_skip_a_tile() {
+        let tiles = line((0, 5), (9, 1));
+
+        assert_eq!(tiles.first(), Some(&(0, 5)));
+        assert_eq!(tiles.last(), Some(&(9, 1)));
+
+        for pair in tiles.windows(2) {
+            let dx = pair[0].0.abs_diff(pair[1].0);
+            let dy = pair[0].1.abs_diff(pair[1].1);
+
+            assert!(dx <= 1 && dy <= 1, "{pair:?}");
+        }
+    }
+}
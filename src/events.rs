@@ -0,0 +1,256 @@
+use graphics::{input::InputEvent, *};
+use log::warn;
+
+/// The kinds of InputEvent handlers can be registered for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    MouseButtonAction,
+    MouseWheel,
+    MouseButton,
+    KeyInput,
+    MousePosition,
+    WindowFocused,
+    Modifier,
+    None,
+}
+
+impl EventKind {
+    /// Returns None for variants added to the input crate that we do not
+    /// know about yet.
+    pub fn of(event: &InputEvent) -> Option<Self> {
+        #[allow(unreachable_patterns)]
+        match event {
+            InputEvent::MouseButtonAction(_) => Some(Self::MouseButtonAction),
+            InputEvent::MouseWheel { .. } => Some(Self::MouseWheel),
+            InputEvent::MouseButton { .. } => Some(Self::MouseButton),
+            InputEvent::KeyInput { .. } => Some(Self::KeyInput),
+            InputEvent::MousePosition { .. } => Some(Self::MousePosition),
+            InputEvent::WindowFocused(_) => Some(Self::WindowFocused),
+            InputEvent::Modifier { .. } => Some(Self::Modifier),
+            InputEvent::None => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+/// What a handler did with an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Pass the event on to the next handler.
+    Continue,
+    /// Stop here so no later handler or the game sees it.
+    Consumed,
+}
+
+/// Names the context handlers are given along with each event. It usually
+/// borrows from whatever runs the dispatcher so it is named for any
+/// lifetime here rather than being a type of its own.
+pub trait HandlerContext {
+    type Context<'a>;
+}
+
+/// Returned when a handler is registered so it can be removed again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HandlerId(u64);
+
+type Handler<C> = Box<
+    dyn for<'a> FnMut(
+        &InputEvent,
+        &mut <C as HandlerContext>::Context<'a>,
+    ) -> Dispatch,
+>;
+
+/// Hands each InputEvent to the handlers registered for its kind in the
+/// order they were registered until one of them consumes it.
+pub struct EventDispatcher<C: HandlerContext> {
+    /// Handlers without a kind are run for every event.
+    handlers: Vec<(HandlerId, Option<EventKind>, Handler<C>)>,
+    next_id: u64,
+}
+
+impl<C: HandlerContext> Default for EventDispatcher<C> {
+    fn default() -> Self {
+        Self {
+            handlers: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<C: HandlerContext> EventDispatcher<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for a single kind of event.
+    pub fn on<H>(&mut self, kind: EventKind, handler: H) -> HandlerId
+    where
+        H: for<'a> FnMut(&InputEvent, &mut C::Context<'a>) -> Dispatch
+            + 'static,
+    {
+        self.add(Some(kind), Box::new(handler))
+    }
+
+    /// Registers a handler for every kind of event.
+    pub fn on_any<H>(&mut self, handler: H) -> HandlerId
+    where
+        H: for<'a> FnMut(&InputEvent, &mut C::Context<'a>) -> Dispatch
+            + 'static,
+    {
+        self.add(None, Box::new(handler))
+    }
+
+    /// Removes a handler so it is not run again. Returns false if it was
+    /// already removed.
+    pub fn off(&mut self, id: HandlerId) -> bool {
+        let count = self.handlers.len();
+
+        self.handlers.retain(|(handler_id, _, _)| *handler_id != id);
+        self.handlers.len() != count
+    }
+
+    fn add(
+        &mut self,
+        kind: Option<EventKind>,
+        handler: Handler<C>,
+    ) -> HandlerId {
+        let id = HandlerId(self.next_id);
+
+        self.next_id += 1;
+        self.handlers.push((id, kind, handler));
+        id
+    }
+
+    pub fn dispatch(
+        &mut self,
+        event: &InputEvent,
+        ctx: &mut C::Context<'_>,
+    ) -> Dispatch {
+        // Anything new from the input crate is skipped rather than crashing.
+        let Some(kind) = EventKind::of(event) else {
+            warn!("ignoring unknown input event {event:?}");
+            return Dispatch::Consumed;
+        };
+
+        for (_, handler_kind, handler) in &mut self.handlers {
+            if handler_kind.is_some_and(|handler_kind| handler_kind != kind) {
+                continue;
+            }
+
+            if handler(event, ctx) == Dispatch::Consumed {
+                return Dispatch::Consumed;
+            }
+        }
+
+        Dispatch::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Handlers note down what ran in order.
+    struct Log;
+
+    impl HandlerContext for Log {
+        type Context<'a> = Vec<&'static str>;
+    }
+
+    fn note(
+        name: &'static str,
+    ) -> impl FnMut(&InputEvent, &mut Vec<&'static str>) -> Dispatch {
+        move |_, log| {
+            log.push(name);
+            Dispatch::Continue
+        }
+    }
+
+    fn consume(
+        name: &'static str,
+    ) -> impl FnMut(&InputEvent, &mut Vec<&'static str>) -> Dispatch {
+        move |_, log| {
+            log.push(name);
+            Dispatch::Consumed
+        }
+    }
+
+    fn dispatch(
+        events: &mut EventDispatcher<Log>,
+        event: &InputEvent,
+    ) -> (Dispatch, Vec<&'static str>) {
+        let mut log = Vec::new();
+        let dispatch = events.dispatch(event, &mut log);
+
+        (dispatch, log)
+    }
+
+    #[test]
+    fn handlers_run_in_the_order_they_were_added() {
+        let mut events = EventDispatcher::<Log>::new();
+
+        events.on_any(note("first"));
+        events.on(EventKind::WindowFocused, note("second"));
+        events.on_any(note("third"));
+
+        assert_eq!(
+            dispatch(&mut events, &InputEvent::WindowFocused(true)),
+            (Dispatch::Continue, vec!["first", "second", "third"])
+        );
+    }
+
+    #[test]
+    fn handlers_only_see_their_kind() {
+        let mut events = EventDispatcher::<Log>::new();
+
+        events.on(EventKind::WindowFocused, note("focus"));
+        events.on(EventKind::MousePosition, note("mouse"));
+
+        assert_eq!(
+            dispatch(
+                &mut events,
+                &InputEvent::MousePosition { x: 1.0, y: 2.0 }
+            ),
+            (Dispatch::Continue, vec!["mouse"])
+        );
+    }
+
+    #[test]
+    fn consuming_stops_the_rest() {
+        let mut events = EventDispatcher::<Log>::new();
+
+        events.on_any(note("first"));
+        events.on_any(consume("consumer"));
+        events.on_any(note("never"));
+
+        assert_eq!(
+            dispatch(&mut events, &InputEvent::WindowFocused(false)),
+            (Dispatch::Consumed, vec!["first", "consumer"])
+        );
+    }
+
+    #[test]
+    fn removed_handlers_no_longer_run() {
+        let mut events = EventDispatcher::<Log>::new();
+
+        events.on_any(note("first"));
+        let consumer = events.on_any(consume("consumer"));
+        events.on_any(note("last"));
+
+        assert!(events.off(consumer));
+        assert!(!events.off(consumer));
+        assert_eq!(
+            dispatch(&mut events, &InputEvent::WindowFocused(true)),
+            (Dispatch::Continue, vec!["first", "last"])
+        );
+
+        // Ids are not handed out again after a removal.
+        let added = events.on_any(note("added"));
+
+        assert_ne!(added, consumer);
+        assert_eq!(
+            dispatch(&mut events, &InputEvent::WindowFocused(true)),
+            (Dispatch::Continue, vec!["first", "last", "added"])
+        );
+    }
+}
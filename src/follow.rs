@@ -0,0 +1,374 @@
+use crate::scene::CameraDesc;
+use graphics::*;
+use serde::{Deserialize, Serialize};
+
+/// How the world camera chases its target. Set within the scene's camera.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FollowSettings {
+    /// Index within the scene's sprites to keep on screen. The camera only
+    /// moves when panned if this is not set.
+    pub target: Option<usize>,
+    /// How quickly the camera catches up with the target each second.
+    /// Higher is snappier and 0.0 or less snaps straight to it.
+    pub lerp: f32,
+    /// Half the size of the box around the center of the screen the target
+    /// can move within without the camera moving.
+    pub dead_zone: Vec2,
+    /// Keeps the camera from showing anything past the edges of the map.
+    pub clamp_to_map: bool,
+    /// Furthest in pixels a full strength shake moves the camera.
+    pub shake_distance: f32,
+    /// How much of a full strength shake wears off each second.
+    pub shake_decay: f32,
+}
+
+impl Default for FollowSettings {
+    fn default() -> Self {
+        Self {
+            target: None,
+            lerp: 6.0,
+            dead_zone: Vec2::new(48.0, 32.0),
+            clamp_to_map: true,
+            shake_distance: 12.0,
+            shake_decay: 1.5,
+        }
+    }
+}
+
+/// Moves the FlatControls translation to follow a target while staying
+/// within the map. Updated at the game's fixed rate like everything else.
+pub struct CameraFollow {
+    settings: FollowSettings,
+    /// Bottom left of the view from the last two updates without any shake.
+    pos: Vec2,
+    prev_pos: Vec2,
+    /// Size of the window in pixels.
+    screen_size: Vec2,
+    zoom: f32,
+    /// How far the camera can be zoomed out and in.
+    zoom_limits: (f32, f32),
+    /// Bottom left and top right of the area the view is kept within.
+    bounds: Option<(Vec2, Vec2)>,
+    /// 0.0 to 1.0. The shake is this squared so it eases out.
+    trauma: f32,
+    /// Seconds the camera has been updated for. Drives the shake's wobble.
+    time: f32,
+    shake_offset: Vec2,
+}
+
+impl CameraFollow {
+    pub fn new(settings: FollowSettings, pos: Vec2, zoom: f32) -> Self {
+        Self {
+            settings,
+            pos,
+            prev_pos: pos,
+            screen_size: Vec2::ZERO,
+            zoom,
+            zoom_limits: (zoom, zoom),
+            bounds: None,
+            trauma: 0.0,
+            time: 0.0,
+            shake_offset: Vec2::ZERO,
+        }
+    }
+
+    /// Sets up the camera as described within a scene. `screen_size` is the
+    /// size in pixels of what the camera is drawn into.
+    pub fn from_desc(desc: &CameraDesc, screen_size: Vec2) -> Self {
+        let mut camera =
+            Self::new(desc.follow.clone(), desc.translation, desc.zoom);
+
+        camera.set_screen_size(screen_size);
+        camera.set_zoom_limits(desc.min_zoom, desc.max_zoom);
+        camera
+    }
+
+    pub fn settings(&self) -> &FollowSettings {
+        &self.settings
+    }
+
+    /// Keeps whatever was in the middle of the window there as it resizes.
+    pub fn set_screen_size(&mut self, size: Vec2) {
+        let center = self.pos + self.view_size() * 0.5;
+
+        self.screen_size = size;
+
+        if self.zoom > 0.0 && size != Vec2::ZERO {
+            self.pos = center - self.view_size() * 0.5;
+        }
+
+        self.snap_to_bounds();
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// The current zoom is always allowed even when outside of these.
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) {
+        self.zoom_limits = (min.min(self.zoom), max.max(self.zoom));
+    }
+
+    /// Multiplies the zoom by `factor` while keeping the world under the
+    /// window position `cursor` where it is. Zooms around the middle of the
+    /// window without a cursor.
+    pub fn zoom_at(&mut self, factor: f32, cursor: Option<Vec2>) {
+        let (min, max) = self.zoom_limits;
+        let zoom = (self.zoom * factor).clamp(min, max);
+
+        if zoom == self.zoom {
+            return;
+        }
+
+        // Window positions start at the top left while the world starts at
+        // the bottom left.
+        let screen = cursor
+            .map(|cursor| Vec2::new(cursor.x, self.screen_size.y - cursor.y))
+            .unwrap_or(self.screen_size * 0.5);
+        let world = self.pos + screen / self.zoom;
+
+        self.zoom = zoom;
+        self.pos = world - screen / zoom;
+        self.snap_to_bounds();
+    }
+
+    /// Sets the area the view is kept within. Ignored unless `clamp_to_map`
+    /// is set.
+    pub fn set_bounds(&mut self, min: Vec2, max: Vec2) {
+        self.bounds = self.settings.clamp_to_map.then_some((min, max));
+        self.snap_to_bounds();
+    }
+
+    /// Adds to the shake. `amount` of 1.0 is a full strength shake.
+    pub fn shake(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    /// How much of the world the window shows.
+    pub fn view_size(&self) -> Vec2 {
+        self.screen_size / self.zoom
+    }
+
+    /// Moves the camera straight away without smoothing between updates.
+    /// Used for dragging with the mouse so the world sticks to the cursor.
+    /// `delta` is in window pixels.
+    pub fn drag(&mut self, delta: Vec2) {
+        let pos = self.clamp(self.pos + delta / self.zoom);
+
+        self.prev_pos += pos - self.pos;
+        self.pos = pos;
+    }
+
+    /// Runs a single fixed step. `target` is the world position to follow
+    /// and `pan` how far to pan the camera by this step.
+    pub fn update(&mut self, target: Option<Vec2>, pan: Vec2, dt: f32) {
+        self.prev_pos = self.pos;
+
+        let mut pos = self.pos + pan;
+
+        if let Some(target) = target {
+            let center = pos + self.view_size() * 0.5;
+            let offset = target - center;
+            // Only move far enough to bring the target back into the dead
+            // zone.
+            let goal = pos + offset
+                - offset
+                    .clamp(-self.settings.dead_zone, self.settings.dead_zone);
+            let t = if self.settings.lerp <= 0.0 {
+                1.0
+            } else {
+                1.0 - (-self.settings.lerp * dt).exp()
+            };
+
+            pos = pos.lerp(goal, t);
+        }
+
+        self.pos = self.clamp(pos);
+        self.time += dt;
+        self.trauma = (self.trauma - self.settings.shake_decay * dt).max(0.0);
+        self.shake_offset =
+            Vec2::new(wobble(self.time, 0.0), wobble(self.time, 7.3))
+                * self.settings.shake_distance
+                * self.trauma
+                * self.trauma;
+    }
+
+    /// The translation for FlatControls `alpha` of the way between the last
+    /// two updates.
+    pub fn translation(&self, alpha: f32) -> Vec2 {
+        self.prev_pos.lerp(self.pos, alpha) + self.shake_offset
+    }
+
+    // Skips smoothing when the bounds or window change so the view never
+    // slides in from outside the map.
+    fn snap_to_bounds(&mut self) {
+        self.pos = self.clamp(self.pos);
+        self.prev_pos = self.pos;
+    }
+
+    fn clamp(&self, pos: Vec2) -> Vec2 {
+        let Some((min, max)) = self.bounds else {
+            return pos;
+        };
+        let view = self.view_size();
+        let clamp_axis = |pos: f32, min: f32, max: f32, view: f32| {
+            // Center the map when the view is bigger than it.
+            if view >= max - min {
+                min + (max - min - view) * 0.5
+            } else {
+                pos.clamp(min, max - view)
+            }
+        };
+
+        Vec2::new(
+            clamp_axis(pos.x, min.x, max.x, view.x),
+            clamp_axis(pos.y, min.y, max.y, view.y),
+        )
+    }
+}
+
+// A couple of sines at odd frequencies look random enough and replay the
+// same every time. Goes from -1.0 to 1.0.
+fn wobble(time: f32, seed: f32) -> f32 {
+    ((time * 31.0 + seed).sin() + (time * 47.0 + seed * 2.3).sin()) * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Vec2 = Vec2::new(200.0, 100.0);
+
+    // Starts with the middle of the window on the world's origin.
+    fn camera(settings: FollowSettings) -> CameraFollow {
+        let mut camera = CameraFollow::new(settings, Vec2::ZERO, 1.0);
+
+        camera.set_screen_size(SCREEN);
+        camera
+    }
+
+    fn snappy() -> FollowSettings {
+        FollowSettings {
+            lerp: 0.0,
+            dead_zone: Vec2::new(20.0, 10.0),
+            ..FollowSettings::default()
+        }
+    }
+
+    // The world position under a window position.
+    fn world_at(camera: &CameraFollow, cursor: Vec2) -> Vec2 {
+        camera.pos + Vec2::new(cursor.x, SCREEN.y - cursor.y) / camera.zoom
+    }
+
+    #[test]
+    fn targets_within_the_dead_zone_do_not_move_it() {
+        let mut camera = camera(snappy());
+
+        camera.update(Some(Vec2::new(15.0, -8.0)), Vec2::ZERO, 0.1);
+        assert_eq!(camera.translation(1.0), Vec2::new(-100.0, -50.0));
+
+        // Only far enough to bring the target back to the dead zone's edge.
+        camera.update(Some(Vec2::new(50.0, 0.0)), Vec2::ZERO, 0.1);
+        assert_eq!(camera.translation(1.0), Vec2::new(-70.0, -50.0));
+    }
+
+    #[test]
+    fn lerps_towards_the_target() {
+        let mut camera = camera(FollowSettings {
+            lerp: 2.0,
+            dead_zone: Vec2::ZERO,
+            ..FollowSettings::default()
+        });
+
+        camera.update(Some(Vec2::new(100.0, 0.0)), Vec2::ZERO, 0.5);
+
+        let moved = 100.0 * (1.0 - (-1.0f32).exp());
+
+        assert!((camera.translation(1.0).x - (moved - 100.0)).abs() < 1e-3);
+        // Between updates it is placed between the last two.
+        assert_eq!(camera.translation(0.0), Vec2::new(-100.0, -50.0));
+    }
+
+    #[test]
+    fn shakes_wear_off() {
+        let mut camera = camera(snappy());
+        let rest = camera.translation(1.0);
+
+        camera.shake(0.7);
+        camera.shake(0.7);
+        assert_eq!(camera.trauma, 1.0);
+
+        camera.update(None, Vec2::ZERO, 0.05);
+
+        let offset = camera.translation(1.0) - rest;
+
+        assert_ne!(offset, Vec2::ZERO);
+        assert!(offset.abs().max_element() <= camera.settings.shake_distance);
+
+        // A full shake is gone after 1.0 / shake_decay seconds.
+        for _ in 0..20 {
+            camera.update(None, Vec2::ZERO, 0.05);
+        }
+
+        assert_eq!(camera.translation(1.0), rest);
+    }
+
+    #[test]
+    fn centers_bounds_smaller_than_the_view() {
+        let mut camera = camera(snappy());
+
+        // Narrower than the window but taller.
+        camera.set_bounds(Vec2::ZERO, Vec2::new(100.0, 300.0));
+        assert_eq!(camera.translation(1.0), Vec2::new(-50.0, 0.0));
+
+        camera.update(None, Vec2::splat(500.0), 0.1);
+        assert_eq!(camera.translation(1.0), Vec2::new(-50.0, 200.0));
+
+        camera.drag(Vec2::splat(-1000.0));
+        assert_eq!(camera.translation(1.0), Vec2::new(-50.0, 0.0));
+    }
+
+    #[test]
+    fn bounds_are_ignored_unless_clamping() {
+        let mut camera = camera(FollowSettings {
+            clamp_to_map: false,
+            ..snappy()
+        });
+
+        camera.set_bounds(Vec2::ZERO, Vec2::new(100.0, 300.0));
+        assert_eq!(camera.translation(1.0), Vec2::new(-100.0, -50.0));
+    }
+
+    #[test]
+    fn zooming_keeps_the_world_under_the_cursor() {
+        let mut camera = camera(snappy());
+        let cursor = Vec2::new(50.0, 25.0);
+        let world = world_at(&camera, cursor);
+
+        camera.set_zoom_limits(0.5, 4.0);
+        camera.zoom_at(2.0, Some(cursor));
+
+        assert_eq!(camera.zoom(), 2.0);
+        assert_eq!(world_at(&camera, cursor), world);
+
+        // Without a cursor the middle of the window stays put.
+        let center = world_at(&camera, SCREEN * 0.5);
+
+        camera.zoom_at(0.5, None);
+        assert_eq!(camera.zoom(), 1.0);
+        assert_eq!(world_at(&camera, SCREEN * 0.5), center);
+    }
+
+    #[test]
+    fn zooming_stops_at_the_limits() {
+        let mut camera = camera(snappy());
+
+        camera.set_zoom_limits(0.5, 4.0);
+        camera.zoom_at(10.0, None);
+        assert_eq!(camera.zoom(), 4.0);
+
+        camera.zoom_at(0.01, None);
+        assert_eq!(camera.zoom(), 0.5);
+    }
+}
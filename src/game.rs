@@ -0,0 +1,303 @@
+use crate::{
+    bindings::GameInput,
+    collision::{AttributeGrid, attributes_at, move_box},
+    follow::CameraFollow,
+    gamestate::State,
+    scene::{MAP_SIZE, TILE_SIZE},
+    scheduler::{Scheduler, TimerContext},
+    world::World,
+};
+use graphics::*;
+use std::iter;
+
+/// Where the FPS text starts scrolling from.
+pub const TEXT_START: Vec3 = Vec3::new(-25.0, 0.0, 1.0);
+/// Pixels per second the FPS text scrolls.
+const TEXT_SCROLL_SPEED: f32 = 60.0;
+/// Degrees the directional lights are rotated by each second.
+const LIGHT_ROTATION: f32 = 10.0;
+/// Pixels per second the player moves at full tilt.
+const PLAYER_SPEED: f32 = 120.0;
+/// How much slower the player moves through water.
+const WATER_SPEED: f32 = 0.5;
+/// Pixels per second the camera pans at full tilt.
+const PAN_SPEED: f32 = 240.0;
+/// How much each notch of the mouse wheel zooms by.
+const ZOOM_STEP: f32 = 1.1;
+
+/// The demo's game logic. Only ever updated at a fixed rate.
+pub struct Game<Controls>
+where
+    Controls: camera::controls::Controls,
+{
+    /// Position of the text from the last two updates.
+    text_x: f32,
+    prev_text_x: f32,
+    /// Position of the player's sprite from the last two updates.
+    player_pos: Option<Vec2>,
+    prev_player_pos: Vec2,
+    /// Timers running on the game's own clock rather than the frame time so
+    /// they stay in step with the fixed updates.
+    pub timers: Scheduler<State<Controls>>,
+    /// Follows the player around the map and shakes the screen.
+    pub camera: CameraFollow,
+    /// A camera for each of the State's viewports.
+    pub viewport_cameras: Vec<CameraFollow>,
+    /// How many fixed updates have run. Recorded input is keyed by this.
+    frame: u64,
+}
+
+impl<Controls> Game<Controls>
+where
+    Controls: camera::controls::Controls + 'static,
+{
+    pub fn new(
+        state: &State<Controls>,
+        mut camera: CameraFollow,
+        mut viewport_cameras: Vec<CameraFollow>,
+    ) -> Self {
+        let mut timers = Scheduler::new();
+
+        timers.repeat(1.0, 1.0, rotate_lights);
+
+        let map_size = (MAP_SIZE * TILE_SIZE) as f32;
+        let mut min = state.map.pos;
+        let mut max = state.map.pos + Vec2::splat(map_size);
+
+        // Let the cameras roam the whole world and not just the scene's map.
+        if let Some((world_min, world_max)) =
+            state.world.as_ref().map(|world| world.bounds())
+        {
+            min = min.min(world_min);
+            max = max.max(world_max);
+        }
+
+        for camera in iter::once(&mut camera).chain(&mut viewport_cameras) {
+            camera.set_bounds(min, max);
+        }
+
+        Self {
+            text_x: TEXT_START.x,
+            prev_text_x: TEXT_START.x,
+            player_pos: None,
+            prev_player_pos: Vec2::ZERO,
+            timers,
+            camera,
+            viewport_cameras,
+            frame: 0,
+        }
+    }
+}
+
+impl<Controls> Game<Controls>
+where
+    Controls: camera::controls::Controls,
+{
+    /// Runs a single fixed step of game logic `dt` seconds long.
+    pub fn update(
+        &mut self,
+        state: &mut State<Controls>,
+        input: &GameInput,
+        dt: f32,
+    ) {
+        self.prev_text_x = self.text_x;
+        self.text_x += TEXT_SCROLL_SPEED * dt;
+
+        // Send the text back to the start after scrolling for a second.
+        if self.text_x - TEXT_START.x >= TEXT_SCROLL_SPEED {
+            self.text_x = TEXT_START.x;
+            self.prev_text_x = TEXT_START.x;
+        }
+
+        if let Some(sprite) = state.player.and_then(|i| state.sprites.get(i)) {
+            // Start from wherever the scene placed the player.
+            let pos = self.player_pos.unwrap_or(sprite.pos.truncate());
+            // World chunks count too so the player cannot walk through
+            // anything on the maps streamed in around the scene's own.
+            let grids: Vec<(Vec2, &AttributeGrid)> =
+                iter::once((state.map.pos, &state.attributes))
+                    .chain(state.world.iter().flat_map(World::attribute_grids))
+                    .collect();
+            let center = sprite.size * 0.5;
+            let speed = if attributes_at(&grids, pos + center).water {
+                PLAYER_SPEED * WATER_SPEED
+            } else {
+                PLAYER_SPEED
+            };
+            let mut next =
+                move_box(&grids, pos, sprite.size, input.movement * speed * dt);
+
+            self.prev_player_pos = pos;
+
+            // Only stepping onto a warp sends the player so landing on
+            // another one does not send them straight back. Jumps there
+            // instead of sliding across the map.
+            if let Some(warp) = attributes_at(&grids, next + center).warp
+                && attributes_at(&grids, pos + center).warp.is_none()
+            {
+                next = warp;
+                self.prev_player_pos = warp;
+            }
+
+            self.player_pos = Some(next);
+        }
+
+        let target = self.target_pos(state, self.camera.settings().target);
+        // Pan at the same speed on screen no matter how far we are zoomed.
+        let pan = input.pan * PAN_SPEED * dt / self.camera.zoom();
+
+        self.camera.update(target, pan, dt);
+
+        for i in 0..self.viewport_cameras.len() {
+            let target = self
+                .target_pos(state, self.viewport_cameras[i].settings().target);
+
+            self.viewport_cameras[i].update(target, Vec2::ZERO, dt);
+        }
+
+        self.timers.advance(dt, state);
+        self.frame += 1;
+    }
+
+    /// The frame the next update will run.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Moves the camera along with the mouse as it is dragged. Runs every
+    /// frame rather than at the fixed rate so it sticks to the cursor.
+    pub fn drag_camera(&mut self, delta: Vec2) {
+        if delta == Vec2::ZERO {
+            return;
+        }
+
+        // Moving the camera the opposite way makes the world follow the mouse.
+        self.camera.drag(-delta);
+    }
+
+    /// Zooms in or out around the cursor by the notches the mouse wheel was
+    /// scrolled. Runs every frame like dragging.
+    pub fn zoom_camera(&mut self, scroll: f32, cursor: Option<Vec2>) {
+        if scroll == 0.0 {
+            return;
+        }
+
+        self.camera.zoom_at(ZOOM_STEP.powf(scroll), cursor);
+    }
+
+    /// Shakes the screen. `amount` of 1.0 is a full strength shake.
+    pub fn shake(&mut self, amount: f32) {
+        self.camera.shake(amount);
+    }
+
+    /// Moves anything rendered to where it would be `alpha` of the way
+    /// between the last two updates.
+    pub fn interpolate(
+        &self,
+        state: &mut State<Controls>,
+        text: usize,
+        alpha: f32,
+    ) {
+        self.place_camera(state, alpha);
+        self.stream_world(state, alpha);
+
+        let x = self.prev_text_x + (self.text_x - self.prev_text_x) * alpha;
+
+        if let Some(text) = state.texts.get_mut(text) {
+            text.set_pos(Vec3::new(x, TEXT_START.y, TEXT_START.z));
+        }
+
+        if let Some(pos) = self.player_pos
+            && let Some(sprite) =
+                state.player.and_then(|i| state.sprites.get_mut(i))
+        {
+            let pos = self.prev_player_pos.lerp(pos, alpha);
+
+            if sprite.pos.truncate() != pos {
+                sprite.pos = pos.extend(sprite.pos.z);
+                sprite.changed = true;
+            }
+        }
+    }
+
+    // The center of the sprite to follow using where the player will be
+    // rather than where its sprite was last drawn.
+    fn target_pos(
+        &self,
+        state: &State<Controls>,
+        target: Option<usize>,
+    ) -> Option<Vec2> {
+        let i = target?;
+        let sprite = state.sprites.get(i)?;
+        let pos = match self.player_pos {
+            Some(pos) if state.player == Some(i) => pos,
+            _ => sprite.pos.truncate(),
+        };
+
+        Some(pos + sprite.size * 0.5)
+    }
+
+    // Sets the FlatControls translation to where the camera is `alpha` of
+    // the way between the last two updates.
+    fn place_camera(&self, state: &mut State<Controls>, alpha: f32) {
+        let pos = self.camera.translation(alpha);
+        let translation =
+            &mut state.system.controls_mut().inputs_mut().translation;
+
+        translation.x = pos.x;
+        translation.y = pos.y;
+        state.system.controls_mut().settings_mut().zoom = self.camera.zoom();
+
+        for (camera, viewport) in
+            self.viewport_cameras.iter().zip(&mut state.viewports)
+        {
+            let pos = camera.translation(alpha);
+            let controls = viewport.system.controls_mut();
+
+            controls.inputs_mut().translation.x = pos.x;
+            controls.inputs_mut().translation.y = pos.y;
+            controls.settings_mut().zoom = camera.zoom();
+        }
+    }
+
+    // Loads the world chunks around what the main camera and each visible
+    // viewport are looking at.
+    fn stream_world(&self, state: &mut State<Controls>, alpha: f32) {
+        let Some(world) = state.world.as_mut() else {
+            return;
+        };
+        let viewport_cameras = self
+            .viewport_cameras
+            .iter()
+            .zip(&state.viewports)
+            .filter(|(_, viewport)| viewport.visible)
+            .map(|(camera, _)| camera);
+        let views: Vec<(Vec2, Vec2)> = iter::once(&self.camera)
+            .chain(viewport_cameras)
+            .map(|camera| {
+                let pos = camera.translation(alpha);
+
+                (pos, pos + camera.view_size())
+            })
+            .collect();
+
+        world.stream(&views);
+    }
+}
+
+fn rotate_lights<Controls>(
+    state: &mut State<Controls>,
+    _: &mut TimerContext<State<Controls>>,
+) where
+    Controls: camera::controls::Controls,
+{
+    for (_, dir_light) in state.lights.directional_lights.iter_mut() {
+        dir_light.angle += LIGHT_ROTATION;
+
+        if dir_light.angle >= 360.0 {
+            dir_light.angle = 0.0;
+        }
+    }
+
+    state.lights.directionals_changed = true;
+}
@@ -0,0 +1,507 @@
+use crate::{Action, Axis, bindings::BindingsFile};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Gamepad buttons that can be bound to an Action. Triggers are read as
+/// PadAxis instead so they can be bound to an Axis.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Analog inputs on a gamepad. Sticks go from -1.0 to 1.0 with up being
+/// positive while triggers go from 0.0 to 1.0.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// How an Axis is read from the gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadAxisBinding {
+    /// Uses the value of a single stick or trigger.
+    Analog(PadAxis),
+    /// Goes from -1.0 while neg is fully down to 1.0 while pos is. Mostly
+    /// for binding a pair of triggers to one Axis.
+    Pair { pos: PadAxis, neg: PadAxis },
+}
+
+/// A single change reported by a gamepad backend.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GamepadEvent {
+    Button {
+        button: PadButton,
+        pressed: bool,
+    },
+    Axis {
+        axis: PadAxis,
+        value: f32,
+    },
+    /// Releases everything so nothing stays held once a pad is unplugged.
+    Disconnected,
+}
+
+/// Anything gamepad events can be read from.
+pub trait GamepadSource {
+    /// Returns the next pending event without blocking.
+    fn next_event(&mut self) -> Option<GamepadEvent>;
+}
+
+/// Hands out events that were pushed into it. Lets the gamepad be driven
+/// without any hardware, or without the gamepad feature at all.
+#[derive(Clone, Debug, Default)]
+pub struct FakeGamepad {
+    events: VecDeque<GamepadEvent>,
+}
+
+impl FakeGamepad {
+    pub fn new(events: impl IntoIterator<Item = GamepadEvent>) -> Self {
+        Self {
+            events: events.into_iter().collect(),
+        }
+    }
+
+    pub fn push(&mut self, event: GamepadEvent) {
+        self.events.push_back(event);
+    }
+}
+
+impl GamepadSource for FakeGamepad {
+    fn next_event(&mut self) -> Option<GamepadEvent> {
+        self.events.pop_front()
+    }
+}
+
+/// Reads gamepads through gilrs.
+#[cfg(feature = "gamepad")]
+pub struct GilrsSource {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+impl GilrsSource {
+    /// Returns None if gilrs could not start on this platform.
+    pub fn new() -> Option<Self> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => {
+                for (_, pad) in gilrs.gamepads() {
+                    info!("found gamepad {}", pad.name());
+                }
+
+                Some(Self { gilrs })
+            }
+            Err(e) => {
+                warn!("gamepads are unavailable: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadSource for GilrsSource {
+    fn next_event(&mut self) -> Option<GamepadEvent> {
+        use gilrs::{Button, EventType};
+
+        // Skip anything we do not use rather than returning None early.
+        while let Some(event) = self.gilrs.next_event() {
+            let event = match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    pad_button(button).map(|button| GamepadEvent::Button {
+                        button,
+                        pressed: true,
+                    })
+                }
+                EventType::ButtonReleased(button, _) => {
+                    pad_button(button).map(|button| GamepadEvent::Button {
+                        button,
+                        pressed: false,
+                    })
+                }
+                // Analog triggers are only reported as buttons by gilrs.
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) => {
+                    Some(GamepadEvent::Axis {
+                        axis: PadAxis::LeftTrigger,
+                        value,
+                    })
+                }
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+                    Some(GamepadEvent::Axis {
+                        axis: PadAxis::RightTrigger,
+                        value,
+                    })
+                }
+                EventType::AxisChanged(axis, value, _) => pad_axis(axis)
+                    .map(|axis| GamepadEvent::Axis { axis, value }),
+                EventType::Connected => {
+                    info!(
+                        "gamepad connected: {}",
+                        self.gilrs.gamepad(event.id).name()
+                    );
+                    None
+                }
+                EventType::Disconnected => Some(GamepadEvent::Disconnected),
+                _ => None,
+            };
+
+            if event.is_some() {
+                return event;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn pad_button(button: gilrs::Button) -> Option<PadButton> {
+    use gilrs::Button;
+
+    Some(match button {
+        Button::South => PadButton::South,
+        Button::East => PadButton::East,
+        Button::North => PadButton::North,
+        Button::West => PadButton::West,
+        Button::LeftTrigger => PadButton::LeftBumper,
+        Button::RightTrigger => PadButton::RightBumper,
+        Button::Select => PadButton::Select,
+        Button::Start => PadButton::Start,
+        Button::Mode => PadButton::Mode,
+        Button::LeftThumb => PadButton::LeftThumb,
+        Button::RightThumb => PadButton::RightThumb,
+        Button::DPadUp => PadButton::DPadUp,
+        Button::DPadDown => PadButton::DPadDown,
+        Button::DPadLeft => PadButton::DPadLeft,
+        Button::DPadRight => PadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "gamepad")]
+fn pad_axis(axis: gilrs::Axis) -> Option<PadAxis> {
+    use gilrs::Axis;
+
+    Some(match axis {
+        Axis::LeftStickX => PadAxis::LeftStickX,
+        Axis::LeftStickY => PadAxis::LeftStickY,
+        Axis::RightStickX => PadAxis::RightStickX,
+        Axis::RightStickY => PadAxis::RightStickY,
+        _ => return None,
+    })
+}
+
+/// Tracks what is held on any connected gamepad and maps it to the same
+/// Actions and Axes the keyboard and mouse use.
+pub struct Gamepad {
+    source: Option<Box<dyn GamepadSource>>,
+    buttons: HashSet<PadButton>,
+    axes: HashMap<PadAxis, f32>,
+    actions: Vec<(Action, Vec<PadButton>)>,
+    axis_bindings: Vec<(Axis, PadAxisBinding)>,
+    /// Analog values below this are read as 0.0.
+    dead_zone: f32,
+}
+
+impl Gamepad {
+    pub fn new(
+        source: Option<Box<dyn GamepadSource>>,
+        bindings: &BindingsFile,
+        dead_zone: f32,
+    ) -> Self {
+        Self {
+            source,
+            buttons: HashSet::new(),
+            axes: HashMap::new(),
+            actions: bindings.pad_actions.clone(),
+            axis_bindings: bindings.pad_axes.clone(),
+            dead_zone: dead_zone.clamp(0.0, 0.99),
+        }
+    }
+
+    /// Uses gilrs when the gamepad feature is enabled. Otherwise no
+    /// gamepad events are ever read.
+    pub fn from_system(bindings: &BindingsFile, dead_zone: f32) -> Self {
+        #[cfg(feature = "gamepad")]
+        let source = GilrsSource::new()
+            .map(|source| Box::new(source) as Box<dyn GamepadSource>);
+        #[cfg(not(feature = "gamepad"))]
+        let source = None;
+
+        Self::new(source, bindings, dead_zone)
+    }
+
+    pub fn set_bindings(&mut self, bindings: &BindingsFile) {
+        self.actions = bindings.pad_actions.clone();
+        self.axis_bindings = bindings.pad_axes.clone();
+    }
+
+    /// Reads every pending event from the source. Returns them so they
+    /// can be recorded.
+    pub fn update(&mut self) -> Vec<GamepadEvent> {
+        let Some(mut source) = self.source.take() else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+
+        while let Some(event) = source.next_event() {
+            self.handle_event(event);
+            events.push(event);
+        }
+
+        self.source = Some(source);
+        events
+    }
+
+    pub fn handle_event(&mut self, event: GamepadEvent) {
+        match event {
+            GamepadEvent::Button { button, pressed } => {
+                if pressed {
+                    self.buttons.insert(button);
+                } else {
+                    self.buttons.remove(&button);
+                }
+            }
+            GamepadEvent::Axis { axis, value } => {
+                self.axes.insert(axis, value);
+            }
+            GamepadEvent::Disconnected => {
+                self.buttons.clear();
+                self.axes.clear();
+            }
+        }
+    }
+
+    pub fn is_action_down(&self, action: &Action) -> bool {
+        self.actions
+            .iter()
+            .filter(|(a, _)| a == action)
+            .any(|(_, buttons)| {
+                buttons.iter().any(|b| self.buttons.contains(b))
+            })
+    }
+
+    /// Value of the Axis from -1.0 to 1.0 with the dead zone applied.
+    pub fn axis_value(&self, axis: &Axis) -> f32 {
+        let Some((_, binding)) =
+            self.axis_bindings.iter().find(|(a, _)| a == axis)
+        else {
+            return 0.0;
+        };
+
+        match *binding {
+            PadAxisBinding::Analog(axis) => self.analog(axis),
+            PadAxisBinding::Pair { pos, neg } => {
+                (self.analog(pos) - self.analog(neg)).clamp(-1.0, 1.0)
+            }
+        }
+    }
+
+    // Values within the dead zone read as 0.0 and the rest is rescaled so
+    // the axis still starts moving from 0.0 right past its edge.
+    fn analog(&self, axis: PadAxis) -> f32 {
+        let value = self.axes.get(&axis).copied().unwrap_or_default();
+
+        if value.abs() <= self.dead_zone {
+            return 0.0;
+        }
+
+        let scaled = (value.abs() - self.dead_zone) / (1.0 - self.dead_zone);
+
+        scaled.min(1.0).copysign(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(
+        pad_actions: Vec<(Action, Vec<PadButton>)>,
+        pad_axes: Vec<(Axis, PadAxisBinding)>,
+    ) -> BindingsFile {
+        BindingsFile {
+            pad_actions,
+            pad_axes,
+            ..BindingsFile::default()
+        }
+    }
+
+    fn gamepad(
+        events: Vec<GamepadEvent>,
+        bindings: &BindingsFile,
+        dead_zone: f32,
+    ) -> Gamepad {
+        let mut gamepad = Gamepad::new(
+            Some(Box::new(FakeGamepad::new(events))),
+            bindings,
+            dead_zone,
+        );
+
+        gamepad.update();
+        gamepad
+    }
+
+    fn stick(axis: PadAxis, value: f32) -> GamepadEvent {
+        GamepadEvent::Axis { axis, value }
+    }
+
+    fn button(button: PadButton, pressed: bool) -> GamepadEvent {
+        GamepadEvent::Button { button, pressed }
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {expected} but got {actual}"
+        );
+    }
+
+    #[test]
+    fn update_reads_every_event_from_the_source() {
+        let events = vec![
+            button(PadButton::South, true),
+            stick(PadAxis::LeftStickX, 0.5),
+        ];
+        let mut gamepad = Gamepad::new(
+            Some(Box::new(FakeGamepad::new(events.clone()))),
+            &bindings(vec![(Action::Quit, vec![PadButton::South])], vec![]),
+            0.0,
+        );
+
+        assert!(!gamepad.is_action_down(&Action::Quit));
+        assert_eq!(gamepad.update(), events);
+        assert!(gamepad.is_action_down(&Action::Quit));
+        assert!(gamepad.update().is_empty());
+    }
+
+    #[test]
+    fn analog_applies_and_rescales_the_dead_zone() {
+        let bindings = bindings(
+            vec![],
+            vec![(Axis::Forward, PadAxisBinding::Analog(PadAxis::LeftStickY))],
+        );
+        let read = |value| {
+            gamepad(vec![stick(PadAxis::LeftStickY, value)], &bindings, 0.2)
+                .axis_value(&Axis::Forward)
+        };
+
+        assert_eq!(read(0.1), 0.0);
+        assert_eq!(read(-0.2), 0.0);
+        assert_near(read(0.6), 0.5);
+        assert_near(read(-0.6), -0.5);
+        assert_near(read(1.0), 1.0);
+        assert_near(read(-1.0), -1.0);
+        // Axes nothing was ever read from sit at 0.0.
+        assert_eq!(
+            gamepad(vec![], &bindings, 0.2).axis_value(&Axis::Forward),
+            0.0
+        );
+    }
+
+    #[test]
+    fn pair_subtracts_and_clamps() {
+        let bindings = bindings(
+            vec![],
+            vec![
+                (
+                    Axis::Yaw,
+                    PadAxisBinding::Pair {
+                        pos: PadAxis::RightTrigger,
+                        neg: PadAxis::LeftTrigger,
+                    },
+                ),
+                (
+                    Axis::Pitch,
+                    PadAxisBinding::Pair {
+                        pos: PadAxis::RightStickX,
+                        neg: PadAxis::RightStickY,
+                    },
+                ),
+            ],
+        );
+        let gamepad = gamepad(
+            vec![
+                stick(PadAxis::RightTrigger, 0.75),
+                stick(PadAxis::LeftTrigger, 0.25),
+                stick(PadAxis::RightStickX, 1.0),
+                stick(PadAxis::RightStickY, -1.0),
+            ],
+            &bindings,
+            0.0,
+        );
+
+        assert_near(gamepad.axis_value(&Axis::Yaw), 0.5);
+        assert_eq!(gamepad.axis_value(&Axis::Pitch), 1.0);
+        assert_eq!(gamepad.axis_value(&Axis::Forward), 0.0);
+    }
+
+    #[test]
+    fn disconnecting_releases_everything() {
+        let bindings = bindings(
+            vec![(Action::Shake, vec![PadButton::East])],
+            vec![(Axis::Forward, PadAxisBinding::Analog(PadAxis::LeftStickY))],
+        );
+        let mut gamepad = gamepad(
+            vec![
+                button(PadButton::East, true),
+                stick(PadAxis::LeftStickY, 1.0),
+            ],
+            &bindings,
+            0.1,
+        );
+
+        assert!(gamepad.is_action_down(&Action::Shake));
+        assert_eq!(gamepad.axis_value(&Axis::Forward), 1.0);
+
+        gamepad.handle_event(GamepadEvent::Disconnected);
+
+        assert!(!gamepad.is_action_down(&Action::Shake));
+        assert_eq!(gamepad.axis_value(&Axis::Forward), 0.0);
+    }
+
+    #[test]
+    fn set_bindings_applies_right_away() {
+        let mut gamepad = gamepad(
+            vec![
+                button(PadButton::South, true),
+                stick(PadAxis::RightStickX, 1.0),
+            ],
+            &bindings(vec![(Action::Quit, vec![PadButton::North])], vec![]),
+            0.0,
+        );
+
+        assert!(!gamepad.is_action_down(&Action::Quit));
+        assert_eq!(gamepad.axis_value(&Axis::Sideward), 0.0);
+
+        gamepad.set_bindings(&bindings(
+            vec![(Action::Quit, vec![PadButton::North, PadButton::South])],
+            vec![(
+                Axis::Sideward,
+                PadAxisBinding::Analog(PadAxis::RightStickX),
+            )],
+        ));
+
+        assert!(gamepad.is_action_down(&Action::Quit));
+        assert_eq!(gamepad.axis_value(&Axis::Sideward), 1.0);
+    }
+}
@@ -1,3 +1,5 @@
 mod data;
+mod picking;
 
 pub use data::*;
+pub use picking::*;
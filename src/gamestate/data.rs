@@ -1,6 +1,17 @@
+use crate::{
+    collision::AttributeGrid,
+    editor::MapEditor,
+    mapfile::Tilesheets,
+    profiler::Profiler,
+    stats::{AtlasUsage, InstanceCounts, StatsOverlay},
+    textfield::TextField,
+    viewport::{Viewport, window_size},
+    world::World,
+};
 use cosmic_text::{CacheKey, FontSystem};
 use graphics::*;
 use std::collections::HashMap;
+use time::FrameTime;
 use winit::event::MouseButton;
 
 pub struct State<Controls>
@@ -9,13 +20,39 @@ where
 {
     /// World Camera Controls and time. Deturmines how the world is looked at.
     pub system: System<Controls>,
+    /// The matrix and scale each sub view was set to so window positions
+    /// can be mapped back through them.
+    pub sub_views: Vec<(CameraView, Mat4, f32)>,
+    /// Extra cameras drawn over parts of the window after the main view.
+    pub viewports: Vec<Viewport>,
     /// Data stores for render types
     pub sprites: Vec<Image>,
+    /// Index within `sprites` of the sprite the player moves around.
+    pub player: Option<usize>,
     pub lights: Lights,
-    pub animation: AnimImage,
+    pub animations: Vec<AnimImage>,
     pub map: Map,
-    pub mesh: [Mesh2D; 2],
-    pub rect: Rect,
+    /// Where each tile of the map's tilesheets is within `map_atlas`.
+    pub tilesheets: Tilesheets,
+    /// What each tile of the map does to anything moving over it.
+    pub attributes: AttributeGrid,
+    /// Shows the attributes over the map while `show_collision` is set.
+    pub collision_overlay: Mesh2D,
+    pub show_collision: bool,
+    /// Map chunks around the cameras when the scene has a world.
+    pub world: Option<World>,
+    pub mesh: Vec<Mesh2D>,
+    pub rects: Vec<Rect>,
+    pub texts: Vec<Text>,
+    pub text_fields: Vec<TextField>,
+    /// Frame stats overlay. Only rendered while visible.
+    pub stats: Option<StatsOverlay>,
+    /// What the last update_buffers pushed into the renderers.
+    pub counts: InstanceCounts,
+    /// Paints the map with the mouse. Only rendered while open.
+    pub editor: Option<MapEditor>,
+    /// CPU and GPU timings for each render stage when profiling.
+    pub profiler: Profiler,
     /// Atlas Groups for Textures in GPU
     pub image_atlas: AtlasSet,
     pub ui_atlas: AtlasSet,
@@ -32,60 +69,352 @@ where
     pub mesh_renderer: Mesh2DRenderer,
 }
 
-impl<Controls> Pass for State<Controls>
+impl<Controls> State<Controls>
 where
     Controls: camera::controls::Controls,
 {
-    fn render(
+    /// Updates the Camera and pushes all of our render types into their
+    /// renderers buffers so they are ready to be rendered.
+    pub fn update_buffers(
+        &mut self,
+        renderer: &mut GpuRenderer,
+        frame_time: &FrameTime,
+    ) -> Result<(), GraphicsError> {
+        let size = renderer.size();
+        let mut counts = InstanceCounts::default();
+
+        // Pick up the GPU timings from an earlier frame if they are ready.
+        self.profiler.poll_timestamps(renderer);
+
+        let start = self.profiler.start();
+        // update our systems data to the gpu. this is the Camera in the shaders.
+        self.system.update(renderer, frame_time);
+
+        // update our systems data to the gpu. this is the Screen in the shaders.
+        self.system
+            .update_screen(renderer, [size.width, size.height]);
+
+        for viewport in self.viewports.iter_mut().filter(|v| v.visible) {
+            viewport.update(renderer, frame_time);
+        }
+
+        self.profiler.record("system update", start);
+
+        // This adds the Image data to the Buffer for rendering.
+        let start = self.profiler.start();
+        self.sprites.iter_mut().for_each(|sprite| {
+            self.sprite_renderer.update(
+                sprite,
+                renderer,
+                &mut self.image_atlas,
+                0,
+            );
+        });
+        counts.sprites = self.sprites.len();
+        self.profiler.record("sprite update", start);
+
+        let start = self.profiler.start();
+        self.animations.iter_mut().for_each(|animation| {
+            self.animation_renderer.update(
+                animation,
+                renderer,
+                &mut self.image_atlas,
+                0,
+            );
+        });
+        counts.animations = self.animations.len();
+        self.profiler.record("animation update", start);
+
+        // this cycles all the Image's in the Image buffer by first putting them in rendering order
+        // and then uploading them to the GPU if they have moved or changed in any way. clears the
+        // Image buffer for the next render pass. Image buffer only holds the ID's and Sortign info
+        // of the finalized Indicies of each Image.
+        let start = self.profiler.start();
+        self.sprite_renderer.finalize(renderer);
+        self.profiler.record("sprite finalize", start);
+
+        let start = self.profiler.start();
+        self.animation_renderer.finalize(renderer);
+        self.profiler.record("animation finalize", start);
+
+        let start = self.profiler.start();
+
+        for text in self.texts.iter_mut() {
+            self.text_renderer.update(
+                text,
+                &mut self.text_atlas,
+                renderer,
+                0,
+            )?;
+        }
+
+        for field in self.text_fields.iter_mut() {
+            field.refresh_text();
+            self.text_renderer.update(
+                &mut field.text,
+                &mut self.text_atlas,
+                renderer,
+                0,
+            )?;
+        }
+
+        // Only the overlays text and graph are pushed when it is visible.
+        let mut stats = self.stats.as_mut().filter(|stats| stats.visible);
+
+        if let Some(stats) = stats.as_mut() {
+            self.text_renderer.update(
+                &mut stats.text,
+                &mut self.text_atlas,
+                renderer,
+                0,
+            )?;
+            counts.texts += 1;
+        }
+
+        counts.texts += self.texts.len() + self.text_fields.len();
+        self.profiler.record("text update", start);
+
+        let start = self.profiler.start();
+        self.text_renderer.finalize(renderer);
+        self.profiler.record("text finalize", start);
+
+        let start = self.profiler.start();
+        self.map_renderer.update(
+            &mut self.map,
+            renderer,
+            &mut self.map_atlas,
+            [0, 1],
+        );
+        counts.maps = 1;
+
+        if let Some(world) = self.world.as_mut() {
+            counts.maps += world.update(
+                renderer,
+                &mut self.map_renderer,
+                &mut self.map_atlas,
+                &self.tilesheets,
+            );
+        }
+
+        self.profiler.record("map update", start);
+
+        let start = self.profiler.start();
+        self.map_renderer.finalize(renderer);
+        self.profiler.record("map finalize", start);
+
+        let start = self.profiler.start();
+        self.light_renderer.update(&mut self.lights, renderer, 0);
+        counts.area_lights = self.lights.area_lights.len();
+        counts.directional_lights = self.lights.directional_lights.len();
+        self.profiler.record("light update", start);
+
+        let start = self.profiler.start();
+        self.light_renderer.finalize(renderer);
+        self.profiler.record("light finalize", start);
+
+        let start = self.profiler.start();
+        self.mesh.iter_mut().for_each(|mesh| {
+            self.mesh_renderer.update(mesh, renderer, 0);
+        });
+        counts.meshes = self.mesh.len();
+
+        if let Some(stats) = stats {
+            self.mesh_renderer.update(&mut stats.graph, renderer, 0);
+            counts.meshes += 1;
+        }
+
+        if self.show_collision {
+            self.mesh_renderer
+                .update(&mut self.collision_overlay, renderer, 0);
+            counts.meshes += 1;
+        }
+
+        self.profiler.record("mesh update", start);
+
+        let start = self.profiler.start();
+        self.mesh_renderer.finalize(renderer);
+        self.profiler.record("mesh finalize", start);
+
+        let start = self.profiler.start();
+        self.rects.iter_mut().for_each(|rect| {
+            self.ui_renderer
+                .update(rect, renderer, &mut self.ui_atlas, 0);
+        });
+        counts.rects = self.rects.len();
+
+        // The caret is placed from the text's layout which is only
+        // current once the text was updated above.
+        for field in self.text_fields.iter_mut() {
+            field.refresh_rects();
+            counts.rects += field.update_rects(
+                &mut self.ui_renderer,
+                renderer,
+                &mut self.ui_atlas,
+            );
+        }
+
+        if let Some(editor) = self.editor.as_mut().filter(|editor| editor.open)
+        {
+            counts.rects += editor.update_rects(
+                &mut self.ui_renderer,
+                renderer,
+                &mut self.ui_atlas,
+                Vec2::new(size.width, size.height),
+            );
+            counts.sprites +=
+                editor.update_tiles(renderer, &mut self.image_atlas);
+        }
+
+        self.profiler.record("ui update", start);
+
+        let start = self.profiler.start();
+        self.ui_renderer.finalize(renderer);
+        self.profiler.record("ui finalize", start);
+        self.counts = counts;
+
+        Ok(())
+    }
+
+    pub fn atlas_usage(&self) -> Vec<AtlasUsage> {
+        let usage = |name, atlas: &AtlasSet| AtlasUsage {
+            name,
+            layers: atlas.layers.len(),
+            allocations: atlas.store.len(),
+        };
+
+        vec![
+            usage("image", &self.image_atlas),
+            usage("map", &self.map_atlas),
+            usage("ui", &self.ui_atlas),
+            usage("mesh", &self.mesh_atlas),
+            AtlasUsage {
+                name: "text",
+                layers: self.text_atlas.text.layers.len(),
+                allocations: self.text_atlas.text.store.len(),
+            },
+            AtlasUsage {
+                name: "emoji",
+                layers: self.text_atlas.emoji.layers.len(),
+                allocations: self.text_atlas.emoji.store.len(),
+            },
+        ]
+    }
+
+    /// Rebuilds the stats overlay if it is visible.
+    pub fn refresh_stats(&mut self) -> Result<(), GraphicsError> {
+        let counts = self.counts;
+        let atlases = self.atlas_usage();
+
+        match self.stats.as_mut() {
+            Some(stats) if stats.visible => stats.refresh(&counts, &atlases),
+            _ => Ok(()),
+        }
+    }
+
+    /// Stops everything that animates over time so each render of the State
+    /// looks the same no matter when it happens.
+    pub fn freeze_animations(&mut self) {
+        self.animations
+            .iter_mut()
+            .for_each(|animation| animation.animate = false);
+
+        for (_, light) in self.lights.area_lights.iter_mut() {
+            light.animate = false;
+        }
+
+        for (_, light) in self.lights.directional_lights.iter_mut() {
+            light.animate = false;
+        }
+
+        self.lights.areas_changed = true;
+        self.lights.directionals_changed = true;
+    }
+
+    /// Renders the State into the given view. The view must be the same size
+    /// and format as the Surface since we share its depth buffer and pipelines.
+    pub fn render_to(
         &mut self,
         renderer: &GpuRenderer,
         encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
     ) {
-        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: renderer.frame_buffer().as_ref().expect("no frame view?"),
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.0,
-                        g: 0.25,
-                        b: 0.5,
-                        a: 1.0,
-                    }),
-                    store: wgpu::StoreOp::Store,
-                },
-                depth_slice: None,
-            })],
-            depth_stencil_attachment: Some(
-                wgpu::RenderPassDepthStencilAttachment {
-                    view: renderer.depth_buffer(),
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                },
-            ),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-            multiview_mask: None,
-        });
+        let mut pass = begin_pass(renderer, encoder, view, true);
+
+        self.bind_system(&mut pass, renderer, self.system.bind_group());
+
+        // Timestamps are written between each draw group when profiling.
+        self.profiler.write_timestamp(&mut pass, 0);
+        self.render_world(&mut pass, renderer, true);
+
+        pass.render_text(renderer, &self.text_renderer, &self.text_atlas, 0);
+        self.profiler.write_timestamp(&mut pass, 6);
+
+        pass.render_2dmeshs(renderer, &self.mesh_renderer, 0);
+        self.profiler.write_timestamp(&mut pass, 7);
+
+        pass.render_rects(renderer, &self.ui_renderer, &self.ui_atlas, 0);
+        self.profiler.write_timestamp(&mut pass, 8);
+
+        // The editor's palette goes over the UI.
+        if let Some(editor) = self.editor.as_ref().filter(|editor| editor.open)
+        {
+            pass.render_image(
+                renderer,
+                &editor.tile_renderer,
+                &self.image_atlas,
+                0,
+            );
+        }
 
-        // Lets set the System's Shader information here, mostly Camera, Size and Time
-        pass.set_bind_group(0, self.system.bind_group(), &[]);
-        // Lets set the Reusable Vertices and Indicies here.
-        // This is used for each Renderer, Should be more performant since it is shared.
+        // The pass has to end before its timestamps can be resolved.
+        drop(pass);
+        self.profiler.resolve_timestamps(encoder);
+
+        // Viewports are drawn over the main view in their own passes so each
+        // can use its own camera.
+        let window = window_size(renderer);
+
+        for viewport in self.viewports.iter().filter(|v| v.visible) {
+            let mut pass = begin_pass(renderer, encoder, view, false);
+
+            viewport.set_within(&mut pass, window);
+            self.bind_system(&mut pass, renderer, viewport.system.bind_group());
+            self.render_world(&mut pass, renderer, false);
+        }
+    }
+
+    // Sets the System's Shader information, mostly Camera, Size and Time, and
+    // the Reusable Vertices and Indicies shared by each Renderer.
+    fn bind_system(
+        &self,
+        pass: &mut wgpu::RenderPass,
+        renderer: &GpuRenderer,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        pass.set_bind_group(0, bind_group, &[]);
         pass.set_vertex_buffer(0, renderer.buffer_object.vertices());
         pass.set_index_buffer(
             renderer.buffer_object.indices(),
             wgpu::IndexFormat::Uint32,
         );
+    }
+
+    // Draws the map, images and lights. Viewports leave out the UI and skip
+    // the timestamps so the GPU timings only cover the main view.
+    fn render_world(
+        &self,
+        pass: &mut wgpu::RenderPass,
+        renderer: &GpuRenderer,
+        timestamps: bool,
+    ) {
+        let timestamp = |pass: &mut wgpu::RenderPass, index| {
+            if timestamps {
+                self.profiler.write_timestamp(pass, index);
+            }
+        };
 
         pass.render_map(renderer, &self.map_renderer, &self.map_atlas, 0);
+        timestamp(pass, 1);
 
         pass.render_image(
             renderer,
@@ -93,6 +422,7 @@ where
             &self.image_atlas,
             0,
         );
+        timestamp(pass, 2);
 
         pass.render_animated_image(
             renderer,
@@ -100,14 +430,78 @@ where
             &self.image_atlas,
             0,
         );
+        timestamp(pass, 3);
 
         pass.render_map(renderer, &self.map_renderer, &self.map_atlas, 1);
+        timestamp(pass, 4);
         pass.render_lights(renderer, &self.light_renderer, 0);
+        timestamp(pass, 5);
+    }
+}
 
-        pass.render_text(renderer, &self.text_renderer, &self.text_atlas, 0);
+// Starts a pass drawing into the view. Only the main view clears the color
+// while every pass clears the depth so each viewport is drawn on top of what
+// came before it.
+fn begin_pass<'a>(
+    renderer: &'a GpuRenderer,
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    clear: bool,
+) -> wgpu::RenderPass<'a> {
+    let load = if clear {
+        wgpu::LoadOp::Clear(wgpu::Color {
+            r: 0.0,
+            g: 0.25,
+            b: 0.5,
+            a: 1.0,
+        })
+    } else {
+        wgpu::LoadOp::Load
+    };
 
-        pass.render_2dmeshs(renderer, &self.mesh_renderer, 0);
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("render pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+            depth_slice: None,
+        })],
+        depth_stencil_attachment: Some(
+            wgpu::RenderPassDepthStencilAttachment {
+                view: renderer.depth_buffer(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Store,
+                }),
+            },
+        ),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+        multiview_mask: None,
+    })
+}
 
-        pass.render_rects(renderer, &self.ui_renderer, &self.ui_atlas, 0);
+impl<Controls> Pass for State<Controls>
+where
+    Controls: camera::controls::Controls,
+{
+    fn render(
+        &mut self,
+        renderer: &GpuRenderer,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.render_to(
+            renderer,
+            encoder,
+            renderer.frame_buffer().as_ref().expect("no frame view?"),
+        );
     }
 }
@@ -0,0 +1,445 @@
+use super::State;
+use crate::scene::{MAP_LAYERS, MAP_SIZE, TILE_SIZE};
+use graphics::*;
+
+/// Something within the State that can be picked with the mouse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickTarget {
+    /// Index within `sprites`.
+    Sprite(usize),
+    /// Index within `animations`.
+    Animation(usize),
+    /// Index within `rects`.
+    Rect(usize),
+    /// Index within `mesh`.
+    Mesh(usize),
+    /// x, y and layer of the tile within the map.
+    Tile(UVec3),
+    /// x, y and layer of the tile within the world's chunk at `chunk`.
+    WorldTile { chunk: UVec2, tile: UVec3 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickHit {
+    pub target: PickTarget,
+    /// Where the hit was in the world as seen through the target's view.
+    pub world: Vec2,
+}
+
+/// Orders hits by render layer, then z, then the order each render type is
+/// drawn in so the one on top wins.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+struct PickOrder {
+    render_layer: u32,
+    z: f32,
+    draw_group: u8,
+}
+
+/// The parts of a render type picking looks at.
+#[derive(Clone, Copy, Debug)]
+struct Pickable {
+    target: PickTarget,
+    pos: Vec3,
+    size: Vec2,
+    /// Degrees around the center.
+    rotation: f32,
+    render_layer: u32,
+    camera_view: CameraView,
+    /// Render types are drawn in this order within the same layer and z.
+    draw_group: u8,
+}
+
+impl<Controls> State<Controls>
+where
+    Controls: camera::controls::Controls,
+{
+    /// The matrix taking world positions into the given view.
+    pub fn view_matrix(&self, view: CameraView) -> Mat4 {
+        if view == CameraView::MainView {
+            return self.system.controls().view();
+        }
+
+        self.sub_views
+            .iter()
+            .find(|(sub_view, _, _)| *sub_view == view)
+            .map(|(_, mat, scale)| {
+                Mat4::from_scale(Vec3::new(*scale, *scale, 1.0)) * *mat
+            })
+            .unwrap_or(Mat4::IDENTITY)
+    }
+
+    /// Converts a window position into the world as seen through `view`.
+    pub fn screen_to_world(
+        &self,
+        screen: Vec2,
+        screen_size: Vec2,
+        view: CameraView,
+    ) -> Vec2 {
+        unproject(self.view_matrix(view), screen, screen_size)
+    }
+
+    /// Index within `viewports` of the topmost visible viewport drawn at the
+    /// window position.
+    pub fn viewport_at(
+        &self,
+        screen: Vec2,
+        screen_size: Vec2,
+    ) -> Option<usize> {
+        self.viewports
+            .iter()
+            .rposition(|v| v.visible && v.rect.contains(screen_size, screen))
+    }
+
+    /// Converts a world position as seen through `view` back into a window
+    /// position.
+    pub fn world_to_screen(
+        &self,
+        world: Vec2,
+        screen_size: Vec2,
+        view: CameraView,
+    ) -> Vec2 {
+        let projected =
+            self.view_matrix(view).transform_point3(world.extend(0.0));
+
+        Vec2::new(projected.x, screen_size.y - projected.y)
+    }
+
+    /// Returns the topmost sprite, animation, rect, mesh or map tile under
+    /// the window position. Tiles are only picked when nothing else is hit,
+    /// first from the scene's map and then from the world's loaded chunks.
+    /// Within a viewport only what it draws can be picked.
+    pub fn pick(&self, screen: Vec2, screen_size: Vec2) -> Option<PickHit> {
+        let viewport = self
+            .viewport_at(screen, screen_size)
+            .map(|i| &self.viewports[i]);
+        // Positions within a viewport start from its own top left.
+        let (screen, screen_size, main_view) = match viewport {
+            Some(viewport) => {
+                let (pos, size) = viewport.rect.to_pixels(screen_size);
+
+                (screen - pos, size, viewport.system.controls().view())
+            }
+            None => {
+                (screen, screen_size, self.view_matrix(CameraView::MainView))
+            }
+        };
+        let world = |view: CameraView| {
+            let mat = if view == CameraView::MainView {
+                main_view
+            } else {
+                self.view_matrix(view)
+            };
+
+            unproject(mat, screen, screen_size)
+        };
+        let sprites =
+            self.sprites.iter().enumerate().map(|(i, sprite)| Pickable {
+                target: PickTarget::Sprite(i),
+                pos: sprite.pos,
+                size: sprite.size,
+                rotation: sprite.rotation_angle,
+                render_layer: sprite.render_layer,
+                camera_view: sprite.camera_view,
+                draw_group: 0,
+            });
+        let animations =
+            self.animations
+                .iter()
+                .enumerate()
+                .map(|(i, animation)| Pickable {
+                    target: PickTarget::Animation(i),
+                    pos: animation.pos,
+                    size: animation.size,
+                    rotation: 0.0,
+                    render_layer: animation.render_layer,
+                    camera_view: animation.camera_view,
+                    draw_group: 1,
+                });
+        let meshes = self.mesh.iter().enumerate().map(|(i, mesh)| Pickable {
+            target: PickTarget::Mesh(i),
+            pos: mesh.pos,
+            size: mesh.size,
+            rotation: 0.0,
+            render_layer: mesh.render_layer,
+            camera_view: mesh.camera_view,
+            draw_group: 2,
+        });
+        let rects = self.rects.iter().enumerate().map(|(i, rect)| Pickable {
+            target: PickTarget::Rect(i),
+            pos: rect.pos,
+            size: rect.size,
+            rotation: 0.0,
+            render_layer: rect.render_layer,
+            camera_view: rect.camera_view,
+            draw_group: 3,
+        });
+        let hit = pick_topmost(
+            sprites.chain(animations).chain(meshes).chain(rects),
+            viewport.is_some(),
+            &world,
+        );
+
+        if hit.is_some() {
+            return hit;
+        }
+
+        let pos = world(CameraView::MainView);
+        let target = self.pick_tile(pos).map(PickTarget::Tile).or_else(|| {
+            self.world
+                .as_ref()
+                .and_then(|world| world.pick_tile(pos))
+                .map(|(chunk, tile)| PickTarget::WorldTile { chunk, tile })
+        });
+
+        target.map(|target| PickHit { target, world: pos })
+    }
+
+    /// The tile on the highest layer of the scene's map at the world
+    /// position.
+    pub fn pick_tile(&self, world: Vec2) -> Option<UVec3> {
+        top_tile(&self.map, world)
+    }
+}
+
+/// The tile on the highest layer of `map` at the world position.
+pub fn top_tile(map: &Map, world: Vec2) -> Option<UVec3> {
+    let tile = ((world - map.pos) / TILE_SIZE as f32).floor();
+
+    if tile.x < 0.0
+        || tile.y < 0.0
+        || tile.x >= MAP_SIZE as f32
+        || tile.y >= MAP_SIZE as f32
+    {
+        return None;
+    }
+
+    (0..MAP_LAYERS)
+        .rev()
+        .map(|layer| UVec3::new(tile.x as u32, tile.y as u32, layer))
+        .find(|&pos| map.get_tile(pos).id > 0)
+}
+
+// Returns the topmost of `pickables` under the window position, which
+// `world` converts into the world as seen through each view. Meshes and
+// rects are part of the UI which viewports leave out.
+fn pick_topmost(
+    pickables: impl IntoIterator<Item = Pickable>,
+    in_viewport: bool,
+    world: impl Fn(CameraView) -> Vec2,
+) -> Option<PickHit> {
+    let mut best: Option<(PickOrder, PickHit)> = None;
+
+    for pickable in pickables {
+        if in_viewport
+            && matches!(
+                pickable.target,
+                PickTarget::Mesh(_) | PickTarget::Rect(_)
+            )
+        {
+            continue;
+        }
+
+        let pos = world(pickable.camera_view);
+
+        if !contains(
+            pickable.pos.truncate(),
+            pickable.size,
+            pickable.rotation,
+            pos,
+        ) {
+            continue;
+        }
+
+        let order = PickOrder {
+            render_layer: pickable.render_layer,
+            z: pickable.pos.z,
+            draw_group: pickable.draw_group,
+        };
+
+        // Later hits of the same order are drawn over earlier ones.
+        if best.is_none_or(|(best, _)| order >= best) {
+            best = Some((
+                order,
+                PickHit {
+                    target: pickable.target,
+                    world: pos,
+                },
+            ));
+        }
+    }
+
+    best.map(|(_, hit)| hit)
+}
+
+// Converts a window position back through the view matrix. Window positions
+// start at the top left while the projection starts at the bottom left.
+fn unproject(view: Mat4, screen: Vec2, screen_size: Vec2) -> Vec2 {
+    let projected = Vec3::new(screen.x, screen_size.y - screen.y, 0.0);
+
+    view.inverse().transform_point3(projected).truncate()
+}
+
+// Checks if `point` is within the box at `pos` of `size` rotated by `angle`
+// degrees around its center.
+fn contains(pos: Vec2, size: Vec2, angle: f32, point: Vec2) -> bool {
+    let half = size * 0.5;
+    let local =
+        Vec2::from_angle(-angle.to_radians()).rotate(point - (pos + half));
+
+    local.x.abs() <= half.x && local.y.abs() <= half.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pickable(target: PickTarget, pos: Vec3, size: Vec2) -> Pickable {
+        Pickable {
+            target,
+            pos,
+            size,
+            rotation: 0.0,
+            render_layer: 0,
+            camera_view: CameraView::MainView,
+            draw_group: match target {
+                PickTarget::Sprite(_)
+                | PickTarget::Tile(_)
+                | PickTarget::WorldTile { .. } => 0,
+                PickTarget::Animation(_) => 1,
+                PickTarget::Mesh(_) => 2,
+                PickTarget::Rect(_) => 3,
+            },
+        }
+    }
+
+    fn pick_at(
+        pickables: &[Pickable],
+        in_viewport: bool,
+        point: Vec2,
+    ) -> Option<PickTarget> {
+        pick_topmost(pickables.iter().copied(), in_viewport, |_| point)
+            .map(|hit| hit.target)
+    }
+
+    #[test]
+    fn contains_rotates_around_the_center() {
+        let (pos, size) = (Vec2::ZERO, Vec2::new(20.0, 10.0));
+
+        // Past the top edge until turned on its side.
+        assert!(!contains(pos, size, 0.0, Vec2::new(10.0, 13.0)));
+        assert!(contains(pos, size, 90.0, Vec2::new(10.0, 13.0)));
+        // And the other way around for the right edge.
+        assert!(contains(pos, size, 0.0, Vec2::new(18.0, 5.0)));
+        assert!(!contains(pos, size, 90.0, Vec2::new(18.0, 5.0)));
+        // A corner of a box turned 45 degrees is cut off.
+        assert!(contains(pos, Vec2::splat(10.0), 0.0, Vec2::new(0.5, 0.5)));
+        assert!(!contains(pos, Vec2::splat(10.0), 45.0, Vec2::new(0.5, 0.5)));
+    }
+
+    #[test]
+    fn unproject_flips_the_window_and_undoes_the_view() {
+        let size = Vec2::new(800.0, 600.0);
+
+        assert_eq!(
+            unproject(Mat4::IDENTITY, Vec2::new(10.0, 20.0), size),
+            Vec2::new(10.0, 580.0)
+        );
+
+        let view = Mat4::from_scale(Vec3::new(2.0, 2.0, 1.0))
+            * Mat4::from_translation(Vec3::new(-100.0, -50.0, 0.0));
+        let world = unproject(view, Vec2::new(10.0, 20.0), size);
+
+        assert_eq!(world, Vec2::new(105.0, 340.0));
+
+        // Back through the view lands on the same window position.
+        let projected = view.transform_point3(world.extend(0.0));
+
+        assert_eq!(
+            Vec2::new(projected.x, size.y - projected.y),
+            Vec2::new(10.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn higher_render_layers_win_over_z() {
+        let size = Vec2::splat(10.0);
+        let mut below = pickable(PickTarget::Sprite(0), Vec3::Z * 5.0, size);
+        let above = Pickable {
+            render_layer: 1,
+            ..pickable(PickTarget::Sprite(1), Vec3::ZERO, size)
+        };
+
+        assert_eq!(
+            pick_at(&[below, above], false, Vec2::splat(5.0)),
+            Some(PickTarget::Sprite(1))
+        );
+
+        below.render_layer = 1;
+
+        assert_eq!(
+            pick_at(&[below, above], false, Vec2::splat(5.0)),
+            Some(PickTarget::Sprite(0))
+        );
+    }
+
+    #[test]
+    fn draw_order_breaks_ties() {
+        let size = Vec2::splat(10.0);
+        let rect = pickable(PickTarget::Rect(0), Vec3::ZERO, size);
+        let sprite = pickable(PickTarget::Sprite(0), Vec3::ZERO, size);
+        let later = pickable(PickTarget::Sprite(1), Vec3::ZERO, size);
+
+        // Rects are drawn after sprites.
+        assert_eq!(
+            pick_at(&[rect, sprite], false, Vec2::splat(5.0)),
+            Some(PickTarget::Rect(0))
+        );
+        // The later of two alike is drawn over the first.
+        assert_eq!(
+            pick_at(&[sprite, later], false, Vec2::splat(5.0)),
+            Some(PickTarget::Sprite(1))
+        );
+        // Nothing is hit outside of them.
+        assert_eq!(pick_at(&[rect, sprite], false, Vec2::splat(15.0)), None);
+    }
+
+    #[test]
+    fn viewports_leave_out_rects_and_meshes() {
+        let size = Vec2::splat(10.0);
+        let sprite = pickable(PickTarget::Sprite(0), Vec3::ZERO, size);
+        let rect = pickable(PickTarget::Rect(0), Vec3::Z, size);
+        let mesh = pickable(PickTarget::Mesh(0), Vec3::Z, size);
+        let point = Vec2::splat(5.0);
+
+        assert_eq!(
+            pick_at(&[sprite, rect, mesh], false, point),
+            Some(PickTarget::Rect(0))
+        );
+        assert_eq!(
+            pick_at(&[sprite, rect, mesh], true, point),
+            Some(PickTarget::Sprite(0))
+        );
+        assert_eq!(pick_at(&[rect, mesh], true, point), None);
+    }
+
+    #[test]
+    fn each_view_is_converted_on_its_own() {
+        let size = Vec2::splat(10.0);
+        let main = pickable(PickTarget::Sprite(0), Vec3::ZERO, size);
+        let sub = Pickable {
+            camera_view: CameraView::SubView1,
+            ..pickable(PickTarget::Sprite(1), Vec3::new(100.0, 0.0, 0.0), size)
+        };
+        let world = |view| {
+            if view == CameraView::MainView {
+                Vec2::splat(5.0)
+            } else {
+                Vec2::new(105.0, 5.0)
+            }
+        };
+        let hit = pick_topmost([main, sub], false, world).unwrap();
+
+        assert_eq!(hit.target, PickTarget::Sprite(1));
+        assert_eq!(hit.world, Vec2::new(105.0, 5.0));
+    }
+}
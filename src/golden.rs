@@ -0,0 +1,250 @@
+use crate::offscreen::OffscreenTarget;
+use graphics::*;
+use image::{Rgba, RgbaImage};
+use log::{error, info};
+use std::path::{Path, PathBuf};
+
+/// Result of comparing a render against its reference image.
+pub struct GoldenDiff {
+    /// Pixels where any channel was further apart than the tolerance.
+    pub failed_pixels: u64,
+    /// Failed pixels are red, everything else is a faded copy of the
+    /// reference so you can see where the difference is.
+    pub diff: RgbaImage,
+}
+
+/// Compares tightly packed RGBA8 pixels of a `width` by `height` render
+/// against the reference image. Fails if the sizes do not match.
+pub fn compare(
+    actual: &[u8],
+    width: u32,
+    height: u32,
+    reference: &RgbaImage,
+    tolerance: u8,
+) -> Result<GoldenDiff, GraphicsError> {
+    if reference.dimensions() != (width, height)
+        || actual.len() != width as usize * height as usize * 4
+    {
+        return Err(OtherError::new(&format!(
+            "golden: rendered at {width}x{height} but the reference is {}x{}",
+            reference.width(),
+            reference.height()
+        ))
+        .into());
+    }
+
+    let mut failed_pixels = 0;
+    let mut diff = RgbaImage::new(width, height);
+
+    for ((expected, actual), out) in reference
+        .pixels()
+        .zip(actual.chunks_exact(4))
+        .zip(diff.pixels_mut())
+    {
+        let failed = expected
+            .0
+            .iter()
+            .zip(actual)
+            .any(|(e, a)| e.abs_diff(*a) > tolerance);
+
+        *out = if failed {
+            failed_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([expected[0] / 4, expected[1] / 4, expected[2] / 4, 255])
+        };
+    }
+
+    Ok(GoldenDiff {
+        failed_pixels,
+        diff,
+    })
+}
+
+/// Golden references are rendered on a software adapter such as lavapipe
+/// or llvmpipe so they match on machines with any GPU or none at all.
+/// Returns the backends within `allowed` that have one so the adapter can
+/// only be picked from them.
+pub fn software_backends(
+    instance: &wgpu::Instance,
+    allowed: wgpu::Backends,
+) -> Result<wgpu::Backends, GraphicsError> {
+    let adapters =
+        futures::executor::block_on(instance.enumerate_adapters(allowed));
+    let backends = adapters
+        .iter()
+        .map(wgpu::Adapter::get_info)
+        .filter(|info| info.device_type == wgpu::DeviceType::Cpu)
+        .fold(wgpu::Backends::empty(), |backends, info| {
+            backends | wgpu::Backends::from(info.backend)
+        });
+
+    if backends.is_empty() {
+        return Err(OtherError::new(&format!(
+            "golden: no software adapter found within {allowed:?}, install \
+             Mesa's lavapipe or llvmpipe"
+        ))
+        .into());
+    }
+
+    Ok(backends)
+}
+
+/// Fails unless the renderer ended up on a software adapter.
+pub fn check_adapter(info: &wgpu::AdapterInfo) -> Result<(), GraphicsError> {
+    if info.device_type == wgpu::DeviceType::Cpu {
+        return Ok(());
+    }
+
+    Err(OtherError::new(&format!(
+        "golden: picked {} ({:?}) which is not a software adapter",
+        info.name, info.device_type
+    ))
+    .into())
+}
+
+/// Reads the rendered target back and checks it against `<dir>/<name>.png`.
+/// When `update` is set the render becomes the new reference instead. On
+/// failure the render and a diff image are written next to the reference.
+pub fn check_target(
+    target: &OffscreenTarget,
+    renderer: &GpuRenderer,
+    dir: impl AsRef<Path>,
+    name: &str,
+    tolerance: u8,
+    update: bool,
+) -> Result<(), GraphicsError> {
+    let dir = dir.as_ref();
+    let reference_path = dir.join(format!("{name}.png"));
+
+    if update {
+        target.save_png(renderer, &reference_path)?;
+        info!("golden: wrote reference {}", reference_path.display());
+        return Ok(());
+    }
+
+    if !reference_path.exists() {
+        target.save_png(renderer, output_path(dir, name, "actual"))?;
+
+        return Err(OtherError::new(&format!(
+            "golden: {} is missing, run with --golden-update to create it",
+            reference_path.display()
+        ))
+        .into());
+    }
+
+    let reference = image::open(&reference_path)
+        .map_err(|e| {
+            OtherError::new(&format!(
+                "golden: failed to open {}: {e}",
+                reference_path.display()
+            ))
+        })?
+        .to_rgba8();
+    let actual = target.read_pixels(renderer)?;
+    let result = match compare(
+        &actual,
+        target.width,
+        target.height,
+        &reference,
+        tolerance,
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            target.save_png(renderer, output_path(dir, name, "actual"))?;
+            return Err(e);
+        }
+    };
+
+    if result.failed_pixels == 0 {
+        info!("golden: {name} matched");
+        return Ok(());
+    }
+
+    let diff_path = output_path(dir, name, "diff");
+
+    result.diff.save(&diff_path).map_err(|e| {
+        OtherError::new(&format!(
+            "golden: failed to save {}: {e}",
+            diff_path.display()
+        ))
+    })?;
+    target.save_png(renderer, output_path(dir, name, "actual"))?;
+    error!(
+        "golden: {name} had {} pixels outside the tolerance of {tolerance}, see {}",
+        result.failed_pixels,
+        diff_path.display()
+    );
+
+    Err(OtherError::new(&format!(
+        "golden: {name} had {} mismatched pixels",
+        result.failed_pixels
+    ))
+    .into())
+}
+
+fn output_path(dir: &Path, name: &str, kind: &str) -> PathBuf {
+    dir.join(format!("{name}.{kind}.png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(pixels: &[[u8; 4]], width: u32) -> RgbaImage {
+        let height = pixels.len() as u32 / width;
+
+        RgbaImage::from_raw(width, height, pixels.concat()).unwrap()
+    }
+
+    #[test]
+    fn matches_within_tolerance() {
+        let reference = reference(&[[10, 20, 30, 255], [0, 0, 0, 255]], 2);
+        let actual = [12, 18, 30, 255, 0, 0, 2, 253];
+        let result = compare(&actual, 2, 1, &reference, 2).unwrap();
+
+        assert_eq!(result.failed_pixels, 0);
+        assert_eq!(result.diff.get_pixel(0, 0), &Rgba([2, 5, 7, 255]));
+    }
+
+    #[test]
+    fn fails_pixels_past_tolerance() {
+        let reference =
+            reference(&[[10, 20, 30, 255], [0, 0, 0, 255], [9, 9, 9, 255]], 3);
+        let actual = [13, 20, 30, 255, 0, 0, 0, 255, 9, 9, 9, 0];
+        let result = compare(&actual, 3, 1, &reference, 2).unwrap();
+
+        assert_eq!(result.failed_pixels, 2);
+        assert_eq!(result.diff.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(result.diff.get_pixel(1, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(result.diff.get_pixel(2, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn zero_tolerance_needs_exact_pixels() {
+        let reference = reference(&[[10, 20, 30, 255]], 1);
+
+        assert_eq!(
+            compare(&[10, 20, 30, 255], 1, 1, &reference, 0)
+                .unwrap()
+                .failed_pixels,
+            0
+        );
+        assert_eq!(
+            compare(&[10, 20, 31, 255], 1, 1, &reference, 0)
+                .unwrap()
+                .failed_pixels,
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_size_mismatch() {
+        let reference = reference(&[[0, 0, 0, 255]; 4], 2);
+
+        assert!(compare(&[0; 16], 4, 1, &reference, 2).is_err());
+        assert!(compare(&[0; 16], 1, 4, &reference, 2).is_err());
+        assert!(compare(&[0; 12], 2, 2, &reference, 2).is_err());
+        assert!(compare(&[0; 16], 2, 2, &reference, 2).is_ok());
+    }
+}
@@ -21,15 +21,15 @@ use graphics::{
     naga::{front::wgsl, valid::Validator},
     wgpu::NoopBackendOptions,
 };
-use log::{Level, LevelFilter, Metadata, Record, error, info, warn};
+use log::{Level, LevelFilter, Metadata, Record, debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::HashMap,
     fs::{self, File},
     io::{Read, Write, prelude::*},
-    iter, panic,
-    path::PathBuf,
+    iter, mem, panic,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::Arc,
     time::{Duration, Instant},
@@ -43,15 +43,68 @@ use winit::{
     dpi::PhysicalSize,
     event::*,
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
-    platform::windows::WindowAttributesExtWindows,
+    keyboard::NamedKey,
     window::{WindowAttributes, WindowButtons},
 };
+mod bindings;
+mod clipboard;
+mod collision;
+mod config;
+mod editor;
+mod events;
+mod follow;
+mod game;
+mod gamepad;
 mod gamestate;
+mod golden;
+mod mapfile;
+mod offscreen;
+mod profiler;
+mod rebind;
+mod replay;
+mod scene;
+mod scheduler;
+mod stats;
+mod textfield;
+mod tiled;
+mod timestep;
+mod viewport;
+mod world;
+use bindings::*;
+use clipboard::*;
+use collision::*;
+use config::*;
+use editor::*;
+use events::*;
+use follow::*;
+use game::*;
+use gamepad::*;
 use gamestate::*;
+use mapfile::*;
+use offscreen::*;
+use profiler::*;
+use rebind::*;
+use replay::*;
+use scene::*;
+use scheduler::*;
+use stats::*;
+use textfield::*;
+use tiled::*;
+use timestep::*;
+use viewport::*;
+use world::*;
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 enum Action {
     Quit,
+    Screenshot,
+    ToggleStats,
+    Rebind,
+    Shake,
+    ToggleViewports,
+    SaveMap,
+    ToggleEditor,
+    ToggleCollision,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -65,6 +118,16 @@ enum Axis {
 // creates a static global logger type for setting the logger
 static MY_LOGGER: MyLogger = MyLogger(Level::Debug);
 const WAIT_TIME: std::time::Duration = std::time::Duration::from_millis(20);
+/// Index of the FPS text within the State's texts.
+const FPS_TEXT: usize = 0;
+/// Index of the rebinding screen's text within the State's texts.
+const REBIND_TEXT: usize = 1;
+/// Time allowed between clicks for them to count as a double or triple click.
+const CLICK_TIMEOUT: Duration = Duration::from_millis(150);
+/// How often the stats overlay is rebuilt while visible.
+const STATS_REFRESH: f32 = 0.25;
+/// How hard the Shake action shakes the screen. 1.0 is full strength.
+const SHAKE_AMOUNT: f32 = 0.6;
 struct MyLogger(pub Level);
 
 impl log::Log for MyLogger {
@@ -95,38 +158,527 @@ impl log::Log for MyLogger {
     fn flush(&self) {}
 }
 
+// Window settings that only exist for some platforms. On Linux the name is
+// used as the X11 class and the Wayland app id so desktops can group the window.
+#[cfg(target_os = "linux")]
+fn platform_window_attributes(attrs: WindowAttributes) -> WindowAttributes {
+    use winit::platform::x11::WindowAttributesExtX11;
+
+    attrs.with_name("demo", "demo")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn platform_window_attributes(attrs: WindowAttributes) -> WindowAttributes {
+    attrs
+}
+
+/// Everything a handler is allowed to touch while events are dispatched.
+struct EventContext<'a> {
+    state: &'a mut State<FlatControls>,
+    mouse_drag: &'a mut MouseDrag,
+    held: &'a mut HeldInputs,
+    rebind: &'a mut RebindScreen,
+    bindings: &'a mut BindingsFile,
+    /// The most important outcome from the rebinding screen this frame.
+    rebind_outcome: &'a mut RebindOutcome,
+    clipboard: &'a mut Clipboard,
+    screen_size: Vec2,
+}
+
+/// Names the EventContext the demo's handlers are given.
+struct DemoEvents;
+
+impl HandlerContext for DemoEvents {
+    type Context<'a> = EventContext<'a>;
+}
+
+// Dispatches everything a replay recorded before the update of `frame`
+// through the same handlers as live input.
+fn play_replay(
+    replay: &mut Replay,
+    frame: u64,
+    events: &mut EventDispatcher<DemoEvents>,
+    gamepad: &mut Gamepad,
+    ctx: &mut EventContext<'_>,
+) {
+    for recorded in replay.advance(frame) {
+        if let RecordedEvent::Gamepad(event) = recorded {
+            gamepad.handle_event(event);
+        } else if let Some(event) = recorded.to_event() {
+            events.dispatch(&event, ctx);
+        }
+    }
+}
+
+/// The players own gamepad and bindings, put aside while a replay plays
+/// back with the recordings.
+struct PlayerInput {
+    gamepad: Gamepad,
+    bindings: BindingsFile,
+}
+
+/// What was asked of the demo this frame once any menu or text field had
+/// its say.
+#[derive(Default)]
+struct FrameInput {
+    game: GameInput,
+    /// Actions that went down this frame. Empty while the keyboard is
+    /// captured.
+    pressed: HashSet<Action>,
+    quit: bool,
+}
+
+// Plays back a replay, applies any rebinding and runs every action that
+// does not need the window. Windowed and headless runs share this so a
+// replay plays out the same in both.
+#[allow(clippy::too_many_arguments)]
+fn read_frame_input(
+    events: &mut EventDispatcher<DemoEvents>,
+    ctx: &mut EventContext<'_>,
+    game: &mut Game<FlatControls>,
+    gamepad: &mut Gamepad,
+    action_presses: &mut ActionPresses,
+    replay: &mut Option<Box<Replay>>,
+    player: &mut Option<Box<PlayerInput>>,
+    recorder: &mut Option<Box<Recorder>>,
+    config: &DemoConfig,
+) -> FrameInput {
+    let replaying = replay.as_ref().is_some_and(|replay| !replay.is_finished());
+
+    // Read any gamepad events since the last frame.
+    for event in gamepad.update() {
+        if let Some(recorder) = recorder {
+            recorder.record(game.frame(), RecordedEvent::Gamepad(event));
+        }
+    }
+
+    // Play back everything recorded before the next update.
+    if let Some(playing) = replay.as_mut()
+        && replaying
+    {
+        play_replay(playing, game.frame(), events, gamepad, ctx);
+
+        if playing.is_finished() {
+            info!("replay finished");
+            *replay = None;
+
+            // Hand control back to the player with their own gamepad and
+            // bindings.
+            if let Some(player) = player.take() {
+                let PlayerInput {
+                    gamepad: live,
+                    bindings,
+                } = *player;
+
+                *gamepad = live;
+                *ctx.bindings = bindings;
+            }
+
+            // Let go of anything the recording still held.
+            ctx.held
+                .handle_event(&input::InputEvent::WindowFocused(false));
+            ctx.mouse_drag
+                .handle_event(&input::InputEvent::WindowFocused(false));
+            ctx.held.set_bindings(ctx.bindings);
+            ctx.mouse_drag.set_bindings(ctx.bindings);
+            action_presses.clear();
+        }
+    }
+
+    match *ctx.rebind_outcome {
+        RebindOutcome::Ignored | RebindOutcome::Handled => {}
+        RebindOutcome::Changed | RebindOutcome::Closed => {
+            // Apply the new bindings right away.
+            ctx.held.set_bindings(ctx.bindings);
+            ctx.mouse_drag.set_bindings(ctx.bindings);
+            gamepad.set_bindings(ctx.bindings);
+            action_presses.clear();
+
+            // A replay rebinds through the recordings bindings which are
+            // not the players to save.
+            if *ctx.rebind_outcome == RebindOutcome::Closed
+                && !replaying
+                && let Err(e) = ctx.bindings.save(&config.bindings)
+            {
+                error!("failed to save bindings: {e}");
+            }
+        }
+    }
+
+    let actions: &dyn ActionInput = &*ctx.held;
+    let typing = ctx.state.text_fields.iter().any(TextField::is_focused);
+
+    // Checked before anything can capture the keyboard so actions released
+    // while it was captured are not left held.
+    let pressed = action_presses.update(ctx.bindings, actions, gamepad);
+
+    if pressed.contains(&Action::Rebind)
+        && !replaying
+        && !typing
+        && !ctx.rebind.is_open()
+    {
+        ctx.rebind.open();
+        *ctx.rebind_outcome = RebindOutcome::Handled;
+    }
+
+    if *ctx.rebind_outcome != RebindOutcome::Ignored {
+        ctx.state.texts[REBIND_TEXT].set_text(
+            &ctx.rebind.describe(ctx.bindings),
+            &Attrs::new(),
+            Shaping::Advanced,
+            Some(Align::Left),
+        );
+    }
+
+    // Actions and movement are paused while a menu or text field has the
+    // keyboard.
+    let captured = ctx.rebind.is_open() || typing;
+
+    if captured {
+        return FrameInput::default();
+    }
+
+    let state = &mut *ctx.state;
+
+    if pressed.contains(&Action::ToggleStats)
+        && let Some(stats) = state.stats.as_mut()
+    {
+        stats.visible = !stats.visible;
+
+        // Show it right away instead of waiting for the next refresh.
+        if let Err(e) = state.refresh_stats() {
+            warn!("failed to refresh stats overlay: {e}");
+        }
+    }
+
+    if pressed.contains(&Action::ToggleViewports) {
+        for viewport in state.viewports.iter_mut() {
+            viewport.visible = !viewport.visible;
+        }
+    }
+
+    if pressed.contains(&Action::ToggleEditor)
+        && let Some(editor) = state.editor.as_mut()
+    {
+        editor.set_open(!editor.open);
+    }
+
+    if pressed.contains(&Action::ToggleCollision) {
+        state.show_collision = !state.show_collision;
+    }
+
+    if pressed.contains(&Action::Shake) {
+        game.shake(SHAKE_AMOUNT);
+    }
+
+    if pressed.contains(&Action::SaveMap)
+        && let Err(e) =
+            MapFile::from_map(&state.map, &state.attributes, &state.tilesheets)
+                .save(&config.map_file)
+    {
+        error!("failed to save map: {e}");
+    }
+
+    let actions: &dyn ActionInput = &*ctx.held;
+
+    FrameInput {
+        game: read_game_input(actions, gamepad),
+        quit: actions.is_action_down(&Action::Quit)
+            || gamepad.is_action_down(&Action::Quit),
+        pressed,
+    }
+}
+
+// Runs `steps` fixed updates and everything else each frame needs before it
+// is rendered. Windowed and headless runs share this so their frames match.
+#[allow(clippy::too_many_arguments)]
+fn update_frame(
+    renderer: &mut GpuRenderer,
+    state: &mut State<FlatControls>,
+    game: &mut Game<FlatControls>,
+    timers: &mut Scheduler<State<FlatControls>>,
+    frame_time: &mut FrameTime,
+    mouse_drag: &mut MouseDrag,
+    game_input: &GameInput,
+    steps: u32,
+    dt: f32,
+    alpha: f32,
+    freeze_time: bool,
+) -> Result<(), GraphicsError> {
+    for _ in 0..steps {
+        game.update(state, game_input, dt);
+    }
+
+    game.drag_camera(mouse_drag.take());
+    game.zoom_camera(mouse_drag.take_scroll(), mouse_drag.position());
+
+    // Place anything that moves between the last two updates.
+    game.interpolate(state, FPS_TEXT, alpha);
+
+    // Golden runs keep the FrameTime where it started so anything animated
+    // by it like map tiles renders the same every run.
+    if !freeze_time {
+        frame_time.update_recent();
+    }
+
+    if let Some(stats) = state.stats.as_mut() {
+        stats.frames.record();
+    }
+
+    // Run anything scheduled for this frame like updating the FPS text.
+    timers.update(frame_time, state);
+    // Update the camera and push all our render types into the renderers buffers.
+    state.update_buffers(renderer, frame_time)
+}
+
+// Registers everything the demo does with each input event. Handlers run in
+// the order they are added and can consume an event to stop the rest.
+fn demo_events() -> EventDispatcher<DemoEvents> {
+    let mut events = EventDispatcher::<DemoEvents>::new();
+
+    // Nothing can consume an event before this sees it so a release is
+    // never missed.
+    events.on_any(|event, ctx| {
+        ctx.held.handle_event(event);
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::MouseButton, |event, ctx| {
+        ctx.mouse_drag.handle_event(event);
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::MousePosition, |event, ctx| {
+        ctx.mouse_drag.handle_event(event);
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::WindowFocused, |event, ctx| {
+        ctx.mouse_drag.handle_event(event);
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::MouseWheel, |event, ctx| {
+        ctx.mouse_drag.handle_event(event);
+        Dispatch::Continue
+    });
+
+    // The rebinding screen takes all key and mouse presses while open.
+    events.on_any(|event, ctx| {
+        match ctx.rebind.handle_event(event, ctx.bindings) {
+            RebindOutcome::Ignored => return Dispatch::Continue,
+            RebindOutcome::Handled => {
+                if *ctx.rebind_outcome == RebindOutcome::Ignored {
+                    *ctx.rebind_outcome = RebindOutcome::Handled;
+                }
+            }
+            outcome => {
+                // Keep Closed over Changed so the file still gets saved.
+                if *ctx.rebind_outcome != RebindOutcome::Closed {
+                    *ctx.rebind_outcome = outcome;
+                }
+            }
+        }
+
+        Dispatch::Consumed
+    });
+
+    // A focused text field takes the keyboard and clicks on any field
+    // focus it.
+    events.on_any(|event, ctx| {
+        let mouse = ctx.mouse_drag.position().map(|pos| {
+            ctx.state.screen_to_world(
+                pos,
+                ctx.screen_size,
+                CameraView::MainView,
+            )
+        });
+        let mut dispatch = Dispatch::Continue;
+
+        for field in ctx.state.text_fields.iter_mut() {
+            match field.handle_event(event, mouse, ctx.clipboard) {
+                TextFieldEvent::Ignored | TextFieldEvent::Blurred => {}
+                TextFieldEvent::Submitted(value) => {
+                    info!("text field submitted: {value}");
+                    dispatch = Dispatch::Consumed;
+                }
+                TextFieldEvent::Handled | TextFieldEvent::Changed => {
+                    dispatch = Dispatch::Consumed;
+                }
+            }
+        }
+
+        dispatch
+    });
+
+    // The map editor takes clicks on the map and its shortcuts while
+    // it is open.
+    events.on_any(|event, ctx| {
+        let cursor = ctx.mouse_drag.position();
+        let world = cursor.map(|pos| {
+            ctx.state.screen_to_world(
+                pos,
+                ctx.screen_size,
+                CameraView::MainView,
+            )
+        });
+        let state = &mut *ctx.state;
+        let Some(editor) = state.editor.as_mut() else {
+            return Dispatch::Continue;
+        };
+
+        if editor.handle_event(
+            event,
+            cursor,
+            world,
+            ctx.screen_size,
+            &mut state.map,
+            &state.tilesheets,
+        ) {
+            Dispatch::Consumed
+        } else {
+            Dispatch::Continue
+        }
+    });
+
+    events.on(EventKind::MouseButtonAction, |event, ctx| {
+        let input::InputEvent::MouseButtonAction(action) = event else {
+            return Dispatch::Continue;
+        };
+
+        match action {
+            input::MouseButtonAction::Single(_) => {
+                let hit = ctx
+                    .mouse_drag
+                    .position()
+                    .and_then(|pos| ctx.state.pick(pos, ctx.screen_size));
+
+                match hit {
+                    Some(hit) => info!(
+                        "Single Click on {:?} at {}",
+                        hit.target, hit.world
+                    ),
+                    None => info!("Single Click"),
+                }
+            }
+            input::MouseButtonAction::Double(_) => info!("Double Click"),
+            input::MouseButtonAction::Triple(_) => info!("Triple Click"),
+            other => debug!("ignoring mouse button action {other:?}"),
+        }
+
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::KeyInput, |event, _| {
+        if let input::InputEvent::KeyInput {
+            key,
+            location,
+            pressed,
+        } = event
+        {
+            info!(
+                "KeyInput press: {key:?}, location {location:?}, pressed {pressed}",
+            );
+        }
+
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::WindowFocused, |event, _| {
+        if let input::InputEvent::WindowFocused(b) = event {
+            info!("WindowFocused: focused: {b}");
+        }
+
+        Dispatch::Continue
+    });
+
+    events.on(EventKind::Modifier, |event, _| {
+        if let input::InputEvent::Modifier { modifier, pressed } = event {
+            info!("Modifier: mod: {modifier:?}, pressed {pressed}");
+        }
+
+        Dispatch::Continue
+    });
+
+    // Empty events carry nothing so there is nothing to do with them.
+    events.on(EventKind::None, |_, _| Dispatch::Consumed);
+
+    events
+}
+
 enum Runner {
-    Loading,
+    Loading(Box<DemoConfig>),
+    /// Setup failed so the event loop exits and main returns the error.
+    Failed(GraphicsError),
     Ready {
         input_handler: Box<InputHandler<Action, Axis>>,
         renderer: Box<GpuRenderer>,
         instance: Box<wgpu::Instance>,
         state: Box<State<FlatControls>>,
         frame_time: FrameTime,
-        time: f32,
-        time2: f32,
-        time3: f32,
-        fps: u32,
-        text: Box<Text>,
+        /// Frames rendered since the FPS text was last updated.
+        fps: Rc<Cell<u32>>,
+        /// Timers driven by the frame time for per frame work like the FPS.
+        timers: Scheduler<State<FlatControls>>,
+        game: Box<Game<FlatControls>>,
+        timestep: FixedTimestep,
+        /// When the next frame is allowed to render if frames are limited.
+        next_frame: Instant,
         size: PhysicalSize<f32>,
         keys_pressed: HashSet<Key>,
+        config: Box<DemoConfig>,
+        /// Used instead of the Surface when running headless.
+        offscreen: Option<Box<OffscreenTarget>>,
+        frame_count: u64,
+        /// Actions that were down last frame. Used so holding a key only
+        /// triggers its action once.
+        action_presses: ActionPresses,
+        /// Pans the camera while the mouse is dragged.
+        mouse_drag: MouseDrag,
+        /// The bound keys and buttons held down. Actions are read from this.
+        held: Box<HeldInputs>,
+        /// Gamepad buttons and sticks read alongside the InputHandler.
+        gamepad: Box<Gamepad>,
+        /// The bindings actions are read with. A replay's own while it runs.
+        bindings: Box<BindingsFile>,
+        rebind: RebindScreen,
+        /// Handlers each InputEvent is passed through.
+        events: EventDispatcher<DemoEvents>,
+        /// Used by text fields for copy and paste.
+        clipboard: Clipboard,
+        /// IME input is only allowed while a text field is focused.
+        ime_allowed: bool,
+        /// Collects every input event as it is read when recording.
+        recorder: Option<Box<Recorder>>,
+        /// Dispatched in place of the players input while it plays back.
+        replay: Option<Box<Replay>>,
+        /// Put back once the replay finishes.
+        player: Option<Box<PlayerInput>>,
+        /// Returned from main once the event loop exits.
+        exit_error: Option<GraphicsError>,
     },
 }
 
 impl winit::application::ApplicationHandler for Runner {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        if let Self::Loading = self {
+        if let Self::Loading(config) = self {
             info!("loading initiation");
             let win_attrs = WindowAttributes::default()
                 .with_active(false)
                 .with_visible(false)
-                .with_inner_size(PhysicalSize::new(800, 600))
-                .with_title("Demo")
+                .with_inner_size(PhysicalSize::new(config.width, config.height))
+                .with_title(config.title.as_str())
+                .with_resizable(config.resizable)
                 .with_enabled_buttons({
                     let mut buttons = WindowButtons::all();
-                    buttons.remove(WindowButtons::MAXIMIZE);
+
+                    if !config.maximize {
+                        buttons.remove(WindowButtons::MAXIMIZE);
+                    }
+
                     buttons
                 });
+            let win_attrs = platform_window_attributes(win_attrs);
 
             // Builds the Windows that will be rendered too.
             let window = Arc::new(
@@ -136,9 +688,9 @@ impl winit::application::ApplicationHandler for Runner {
             info!("after window initiation");
             // Generates an Instance for WGPU. Sets WGPU to be allowed on all possible supported backends
             // These are DX12, DX11, Vulkan, Metal and Gles. if none of these work on a system they cant
-            // play the game basically.
+            // play the game basically. The adapter is then limited to the configs backend.
             let instance = wgpu::Instance::new(InstanceDescriptor {
-                backends: Backends::all(),
+                backends: Backends::all() | config.backend.backends(),
                 flags: InstanceFlags::empty(),
                 backend_options: BackendOptions {
                     gl: wgpu::GlBackendOptions {
@@ -146,8 +698,9 @@ impl winit::application::ApplicationHandler for Runner {
                         fence_behavior: wgpu::GlFenceBehavior::AutoFinish,
                         debug_fns: GlDebugFns::Auto,
                     },
+                    #[cfg(target_os = "windows")]
                     dx12: wgpu::Dx12BackendOptions {
-                        shader_compiler: Dx12Compiler::Fxc,
+                        shader_compiler: config.dx12_compiler.into(),
                         presentation_system:
                             wgpu::wgt::Dx12SwapchainKind::DxgiFromHwnd,
                         latency_waitable_object:
@@ -155,7 +708,12 @@ impl winit::application::ApplicationHandler for Runner {
                         force_shader_model: ForceShaderModelToken::default(),
                         agility_sdk: None,
                     },
-                    noop: NoopBackendOptions::default(),
+                    // DX12 only exists on Windows so everywhere else gets the defaults.
+                    #[cfg(not(target_os = "windows"))]
+                    dx12: wgpu::Dx12BackendOptions::default(),
+                    noop: NoopBackendOptions {
+                        enable: config.backend == BackendChoice::Noop,
+                    },
                 },
                 memory_budget_thresholds: MemoryBudgetThresholds::default(),
                 display: Some(Box::new(event_loop.owned_display_handle())),
@@ -164,10 +722,54 @@ impl winit::application::ApplicationHandler for Runner {
             info!("after wgpu instance initiation");
 
             // This is used to ensure the GPU can load the correct.
-            let compatible_surface =
-                instance.create_surface(window.clone()).unwrap();
+            // The noop backend can not present so any adapter will do for it.
+            let compatible_surface = if config.backend == BackendChoice::Noop {
+                None
+            } else {
+                Some(instance.create_surface(window.clone()).unwrap())
+            };
 
             info!("after compatible initiation");
+
+            // Only ask for timestamp queries when every adapter we could end
+            // up with supports them, otherwise creating the device fails.
+            let required_features = if config.profile {
+                let adapters = futures::executor::block_on(
+                    instance.enumerate_adapters(config.backend.backends()),
+                );
+                let supported = adapters
+                    .iter()
+                    .fold(GPU_TIMING_FEATURES, |features, adapter| {
+                        features & adapter.features()
+                    });
+
+                if supported == GPU_TIMING_FEATURES {
+                    GPU_TIMING_FEATURES
+                } else {
+                    wgpu::Features::default()
+                }
+            } else {
+                wgpu::Features::default()
+            };
+
+            // Golden runs only pick from backends with a software adapter
+            // so their references match on any machine.
+            let allowed_backends = if config.golden {
+                match golden::software_backends(
+                    &instance,
+                    config.backend.backends(),
+                ) {
+                    Ok(backends) => backends,
+                    Err(e) => {
+                        *self = Self::Failed(e);
+                        event_loop.exit();
+                        return;
+                    }
+                }
+            } else {
+                config.backend.backends()
+            };
+
             println!("{compatible_surface:?}");
             // This creates the Window Struct and Device struct that holds all the rendering information
             // we need to render to the screen. Window holds most of the window information including
@@ -178,13 +780,13 @@ impl winit::application::ApplicationHandler for Runner {
                     window,
                     //used to find adapters
                     AdapterOptions {
-                        allowed_backends: Backends::VULKAN,
-                        power: AdapterPowerSettings::HighPower,
-                        compatible_surface: Some(compatible_surface),
+                        allowed_backends,
+                        power: config.power.into(),
+                        compatible_surface,
                     },
                     // used to deturmine which adapters support our special limits or features for our backends.
                     &wgpu::DeviceDescriptor {
-                        required_features: wgpu::Features::default(),
+                        required_features,
                         required_limits: wgpu::Limits::default(),
                         label: None,
                         memory_hints: wgpu::MemoryHints::Performance,
@@ -192,7 +794,7 @@ impl winit::application::ApplicationHandler for Runner {
                         experimental_features: ExperimentalFeatures::disabled(),
                     },
                     // How we are presenting the screen which causes it to either clip to a FPS limit or be unlimited.
-                    wgpu::PresentMode::AutoNoVsync,
+                    config.present_mode.into(),
                     EnabledPipelines::all(),
                 ))
                 .unwrap();
@@ -201,204 +803,38 @@ impl winit::application::ApplicationHandler for Runner {
             // we print the GPU it decided to use here for testing purposes.
             println!("{:?}", renderer.adapter().get_info());
 
-            // We generate Texture atlases to use with out types.
-            let mut atlases: Vec<AtlasSet> = iter::from_fn(|| {
-                Some(AtlasSet::new(
-                    &mut renderer,
-                    wgpu::TextureFormat::Rgba8UnormSrgb,
-                    true,
-                    2048,
-                ))
-            })
-            .take(4)
-            .collect();
-
-            // we generate the Text atlas seperatly since it contains a special texture that only has the red color to it.
-            // and another for emojicons.
-            let text_atlas = TextAtlas::new(&mut renderer, 512).unwrap();
-
-            #[derive(Debug, Hash, PartialEq, Eq)]
-            struct TestId<'a> {
-                table: &'a str,
-                id: usize,
-            }
-
-            #[derive(Debug, Hash, PartialEq, Eq)]
-            struct Testname<'a, 'b>
-            where
-                'a: 'b,
+            if config.golden
+                && let Err(e) =
+                    golden::check_adapter(&renderer.adapter().get_info())
             {
-                table: &'a str,
-                name: &'b str,
+                *self = Self::Failed(e);
+                event_loop.exit();
+                return;
             }
 
-            // This is how we load a image into a atlas/Texture. It returns the location of the image
-            // within the texture. its x, y, w, h.  Texture loads the file. group_uploads sends it to the Texture
-            // renderer is used to upload it to the GPU when done.
-            let _allocation = Texture::from_file("images/Female_1.png")
-                .unwrap()
-                .upload(
-                    Testname {
-                        table: "player",
-                        name: "test1",
-                    },
-                    &mut atlases[0],
-                    &renderer,
-                )
-                .ok_or_else(|| OtherError::new("failed to upload image"))
-                .unwrap();
+            // Load the scene description and build all our render types from it.
+            let scene = SceneDesc::from_file(&config.scene).unwrap();
+            let mut state = scene.build(&mut renderer).unwrap();
 
-            let mut sprites = Vec::with_capacity(2001);
-
-            let mut x = 0.0;
-            let y = 0.0;
-
-            for _i in 0..2 {
-                // I named this image simply because it can do a lot of different animations etc, but technically
-                // Image is sprite and I am thinking of renaming this to make it easier for you and others.
-                // Image is mostly the backend render type used to render it to the screen. Im unsure though how
-                // To name this atm to keep it seperated from Sprite that would contain most of the actual not rendering
-                // data needed.
-                let mut sprite = Image::new(
-                    Some(
-                        atlases[0]
-                            .lookup(&Testname {
-                                table: "player",
-                                name: "test1",
-                            })
-                            .unwrap(),
-                    ),
-                    &mut renderer,
-                    Vec3::new(x, y, 7.0),
-                    Vec2::new(48.0, 48.0),
-                    Vec4::new(48.0, 96.0, 48.0, 48.0),
-                    1,
-                );
-                sprite.color = Color::rgba(255, 255, 255, 255);
-                sprites.push(sprite);
-                x += 48.0;
+            if config.golden {
+                state.freeze_animations();
             }
 
-            sprites[0].pos.z = 7.0;
-            sprites[0].color = Color::rgba(255, 255, 255, 120);
-            sprites[1].camera_view = CameraView::SubView1;
-            sprites[0].camera_view = CameraView::MainView;
-            sprites[0].flip_style = FlipStyle::None;
-            sprites[0].rotation_angle = 45.0;
-
-            // We establish the different renderers here to load their data up to use them.
-            let mut text_renderer = TextRenderer::new(&mut renderer).unwrap();
-            let sprite_renderer = ImageRenderer::new(&renderer).unwrap();
-            let animation_renderer = AnimImageRenderer::new(&renderer).unwrap();
-            let mut map_renderer = MapRenderer::new(&mut renderer, 81).unwrap();
-            let mesh_renderer = Mesh2DRenderer::new(&renderer).unwrap();
-            let light_renderer = LightRenderer::new(&mut renderer).unwrap();
-            let ui_renderer = RectRenderer::new(&renderer).unwrap();
-
-            // get the screen size.
+            state.profiler = Profiler::new(&renderer, config.profile);
             let size = renderer.size();
-            let mat = Mat4::from_translation(Vec3 {
-                x: 0.0,
-                y: 0.0,
-                z: 0.0,
-            });
-
-            // setup our system which includes Camera and projection as well as our controls.
-            // for the camera.
-            let mut system = System::new(
-                &mut renderer,
-                Projection::Orthographic {
-                    left: 0.0,
-                    right: size.width,
-                    bottom: 0.0,
-                    top: size.height,
-                    near: 1.0,
-                    far: -100.0,
-                },
-                FlatControls::new(FlatSettings { zoom: 1.0 }),
-                [size.width, size.height],
-            );
 
-            system.controls_mut().inputs_mut().translation.x = 100.0;
-            system.set_view(CameraView::SubView1, mat, 1.0);
-            system.set_view(CameraView::SubView2, mat, 1.0);
-
-            // We make a new Map to render here.
-            let mut map = Map::new(
-                &mut renderer,
-                &mut map_renderer,
-                20,
-                Vec2::new(0.0, 0.0),
-                MapZLayers::default(),
-            )
-            .unwrap();
-
-            (0..32).for_each(|x| {
-                (0..32).for_each(|y| {
-                    map.set_tile(
-                        UVec3::new(x, y, 0),
-                        TileData {
-                            id: 1,
-                            color: Color::rgba(255, 255, 255, 255),
-                            anim_time: 250,
-                        },
-                    )
-                });
-            });
-
-            map.set_tile(
-                UVec3::new(2, 1, 1),
-                TileData {
-                    id: 2,
-                    color: Color::rgba(255, 255, 255, 255),
-                    anim_time: 250,
-                },
-            );
-            map.set_tile(
-                UVec3::new(1, 1, 6),
-                TileData {
-                    id: 2,
-                    color: Color::rgba(255, 255, 255, 230),
-                    anim_time: 250,
-                },
-            );
-            map.set_tile(
-                UVec3::new(0, 0, 1),
-                TileData {
-                    id: 2,
-                    color: Color::rgba(255, 255, 255, 255),
-                    anim_time: 250,
-                },
-            );
-            map.pos = Vec2::new(0.0, 0.0);
-
-            let _tilesheet = Texture::from_file("images/tiles/1.png")
-                .unwrap()
-                .new_tilesheet("1.png", &mut atlases[1], &renderer, 20)
-                .ok_or_else(|| OtherError::new("failed to upload tiles"))
-                .unwrap();
-
-            //println!("tilesheet: {:?}", tilesheet);
-
-            let allocation = Texture::from_file("images/anim/0.png")
-                .unwrap()
-                .upload("0.png", &mut atlases[0], &renderer)
-                .ok_or_else(|| OtherError::new("failed to upload image"))
-                .unwrap();
-
-            let mut animation = AnimImage::new(
-                Some(allocation),
-                &mut renderer,
-                Vec3::new(96.0, 300.0, 5.0),
-                Vec2::new(64.0, 64.0),
-                Vec4::new(0.0, 0.0, 64.0, 64.0),
-                2,
-            );
-
-            animation.color = Color::rgba(255, 255, 255, 255);
-            animation.frames = Vec2::new(8.0, 4.0);
-            animation.switch_time = 300;
-            animation.animate = true;
+            // The cameras follow their targets around the map from here on.
+            let window = Vec2::new(size.width, size.height);
+            let follow = CameraFollow::from_desc(&scene.camera, window);
+            let viewport_cameras = scene
+                .viewports
+                .iter()
+                .zip(&state.viewports)
+                .map(|(desc, viewport)| {
+                    CameraFollow::from_desc(&desc.camera, viewport.size(window))
+                })
+                .collect();
+            let game = Game::new(&state, follow, viewport_cameras);
 
             // get the Scale factor the pc currently is using for upscaling or downscaling the rendering.
             let scale = 1.0; //renderer.window().current_monitor().unwrap().scale_factor();
@@ -406,7 +842,7 @@ impl winit::application::ApplicationHandler for Runner {
             // create a Text rendering object.
             let mut text = Text::new_with_buffer(
                 &mut renderer,
-                &mut text_renderer,
+                &mut state.text_renderer,
                 Some(Metrics::new(16.0, 16.0).scale(scale)),
                 Vec3::new(-25.0, 0.0, 1.0),
                 Vec2::new(190.0 * scale, 32.0 * scale),
@@ -422,175 +858,202 @@ impl winit::application::ApplicationHandler for Runner {
                     600.0 * scale,
                 )))*/
                 .set_default_color(Color::rgba(255, 255, 255, 255));
+            state.texts.insert(FPS_TEXT, text);
 
-            // Start the process of building a shape.
-            let mut builder = Mesh2DBuilder::default();
-
-            builder
-                .circle(
-                    DrawMode::Fill(FillOptions::DEFAULT),
-                    Vec2::new(100.0, 100.0),
-                    60.0,
-                    0.5,
-                    1.0,
-                    Color::rgba(0, 0, 255, 255),
-                )
-                .unwrap();
-            builder
-                .circle(
-                    DrawMode::Stroke(StrokeOptions::DEFAULT),
-                    Vec2::new(100.0, 100.0),
-                    60.0,
-                    0.5,
-                    1.0,
-                    Color::rgba(255, 255, 255, 255),
-                )
-                .unwrap();
+            // Text for the rebinding screen. Stays empty until it is opened.
+            let mut text = Text::new_with_buffer(
+                &mut renderer,
+                &mut state.text_renderer,
+                Some(Metrics::new(16.0, 18.0).scale(scale)),
+                Vec3::new(200.0, 120.0, 1.0),
+                Vec2::new(420.0 * scale, 360.0 * scale),
+                1.0,
+                1,
+            );
 
-            let mut builder2 = Mesh2DBuilder::default();
+            text.set_buffer_size(Some(420.0 * scale), Some(360.0 * scale))
+                .set_default_color(Color::rgba(255, 255, 255, 255));
+            state.texts.insert(REBIND_TEXT, text);
 
-            builder2
-                .circle(
-                    DrawMode::Fill(FillOptions::DEFAULT),
-                    Vec2::new(200.0, 200.0),
-                    60.0,
-                    0.5,
-                    1.0,
-                    Color::rgba(0, 0, 255, 255),
-                )
-                .unwrap();
-            builder2
-                .circle(
-                    DrawMode::Stroke(StrokeOptions::DEFAULT),
-                    Vec2::new(200.0, 200.0),
-                    60.0,
-                    0.5,
-                    1.0,
-                    Color::rgba(255, 255, 255, 255),
-                )
-                .unwrap();
-            builder2
-                .polyline(
-                    DrawMode::Stroke(StrokeOptions::DEFAULT),
-                    &[Vec2::new(200.0, 200.0), Vec2::new(400.0, 400.0)],
-                    1.0,
-                    Color::rgba(255, 255, 255, 255),
-                )
-                .unwrap();
-            let mut mesh = [
-                Mesh2D::new(&mut renderer, Vec3::new(0.0, 0.0, 1.0), 1),
-                Mesh2D::new(&mut renderer, Vec3::new(0.0, 0.0, 1.0), 1),
-            ];
-            mesh[0].from_builder(builder.finalize());
-            mesh[1].from_builder(builder2.finalize());
-
-            let mut lights = Lights::new(
+            // Name entry box to show off editing and IME input.
+            let mut name_field = TextField::new(
                 &mut renderer,
-                0,
-                Vec3::new(0.0, 0.0, 1.0),
-                Vec2::new(size.width, size.height),
+                &mut state.text_renderer,
+                Vec3::new(120.0, 20.0, 1.0),
+                Vec2::new(260.0, 26.0),
             );
 
-            lights.world_color = Vec4::new(0.0, 0.0, 0.0, 0.7);
-            lights.enable_lights = true;
-
-            lights.insert_area_light(AreaLight {
-                pos: Vec2::new(24.0, 24.0),
-                color: Color::rgba(255, 255, 0, 20),
-                max_distance: 20.0,
-                animate: false,
-                anim_speed: 5.0,
-                dither: 0.5,
-                camera_view: CameraView::MainView,
-                visible: true,
-            });
+            name_field.set_value("生活,삶,जिंदगी");
+            state.text_fields.push(name_field);
 
-            lights.insert_area_light(AreaLight {
-                pos: Vec2::new(100.0, 100.0),
-                color: Color::rgba(255, 255, 0, 20),
-                max_distance: 20.0,
-                animate: true,
-                anim_speed: 5.0,
-                dither: 0.8,
-                camera_view: CameraView::MainView,
-                visible: true,
-            });
+            // Show the frames rendered each second.
+            let fps = Rc::new(Cell::new(0u32));
+            let mut timers = Scheduler::new();
+            let frames = fps.clone();
 
-            lights.insert_directional_light(DirectionalLight {
-                pos: Vec2::new(200.0, 400.0),
-                color: Color::rgba(255, 255, 0, 20),
-                max_distance: 90.0,
-                max_width: 5.0,
-                anim_speed: 2.0,
-                angle: 0.0,
-                dither: 5.0,
-                fade_distance: 4.0,
-                edge_fade_distance: 0.6,
-                animate: true,
-                camera_view: CameraView::MainView,
-                visible: true,
-            });
+            timers.repeat(
+                0.0,
+                1.0,
+                move |state: &mut State<FlatControls>,
+                      _: &mut TimerContext<_>| {
+                    state.texts[FPS_TEXT].set_text(
+                        &format!(
+                            "生活,삶,जिंदगी 😀 FPS: {} \n yhelloy",
+                            frames.get()
+                        ),
+                        &Attrs::new(),
+                        Shaping::Advanced,
+                        Some(Align::Left),
+                    );
+
+                    frames.set(0);
+                },
+            );
 
-            let mut rect = Rect::new(
+            state.stats = Some(StatsOverlay::new(
                 &mut renderer,
-                Vec3::new(40.0, 40.0, 1.0),
-                Vec2::new(32.0, 32.0),
-                Color::rgba(255, 255, 255, 255),
-                0,
-            );
+                &mut state.text_renderer,
+            ));
 
-            rect.set_radius(8.0)
-                .set_border_color(Color::rgba(0, 0, 0, 255))
-                .set_border_width(2.0)
-                .set_camera_view(CameraView::SubView1);
-
-            // add everything into our convience type for quicker access and passing.
-            let state = State {
-                system,
-                sprites,
-                animation,
-                image_atlas: atlases.remove(0),
-                map,
-                map_renderer,
-                map_atlas: atlases.remove(0),
-                sprite_renderer,
-                text_atlas,
-                text_renderer,
-                mesh,
-                mesh_atlas: atlases.remove(0),
-                mesh_renderer,
-                lights,
-                light_renderer,
-                ui_atlas: atlases.remove(0),
-                ui_renderer,
-                rect,
-                animation_renderer,
+            // The palette shows the scene's tilesheets. The demo runs
+            // without the editor if they can not be shown.
+            state.editor = match MapEditor::new(
+                &mut renderer,
+                &mut state.image_atlas,
+                &scene.tilesheets,
+                &state.tilesheets,
+            ) {
+                Ok(editor) => Some(editor),
+                Err(e) => {
+                    warn!("map editor disabled: {e}");
+                    None
+                }
             };
 
-            // Create the mouse/keyboard bindings for our stuff.
-            let mut bindings = Bindings::<Action, Axis>::new();
-            bindings
-                .insert_action(Action::Quit, vec![Key::Character('q').into()]);
+            timers.repeat(
+                0.0,
+                STATS_REFRESH,
+                |state: &mut State<FlatControls>, _: &mut TimerContext<_>| {
+                    if let Err(e) = state.refresh_stats() {
+                        warn!("failed to refresh stats overlay: {e}");
+                    }
+                },
+            );
+            timers.repeat(
+                1.0,
+                1.0,
+                |state: &mut State<FlatControls>, _: &mut TimerContext<_>| {
+                    state.profiler.log_recent();
+                },
+            );
+
+            // Load the players mouse/keyboard bindings or use the defaults.
+            let mut bindings = BindingsFile::load(&config.bindings);
+            let mut gamepad =
+                Gamepad::from_system(&bindings, config.gamepad_dead_zone);
+
+            // A replay brings its own bindings and update rate so it plays
+            // out exactly like it was recorded. The players bindings are
+            // left alone on disk.
+            let mut player = None;
+            let replay = config.replay.then(|| {
+                let recording =
+                    Recording::from_file(&config.recording).unwrap();
+
+                info!(
+                    "replaying {} events from {}",
+                    recording.events.len(),
+                    config.recording
+                );
+
+                if recording.scene != config.scene {
+                    warn!(
+                        "recording was made with scene {} not {}",
+                        recording.scene, config.scene
+                    );
+                }
+
+                // The players gamepad is kept aside so it is not read
+                // until the replay finishes.
+                let live = mem::replace(
+                    &mut gamepad,
+                    Gamepad::new(
+                        None,
+                        &recording.bindings,
+                        config.gamepad_dead_zone,
+                    ),
+                );
+
+                player = Some(Box::new(PlayerInput {
+                    gamepad: live,
+                    bindings: mem::replace(
+                        &mut bindings,
+                        recording.bindings.clone(),
+                    ),
+                }));
+                Box::new(Replay::new(recording))
+            });
+            let tick_rate =
+                replay.as_ref().map_or(config.tick_rate, |replay| {
+                    replay.recording().tick_rate
+                });
+            let recorder = if config.record && replay.is_some() {
+                warn!("not recording while a replay is playing");
+                None
+            } else {
+                config.record.then(|| {
+                    Box::new(Recorder::new(
+                        &config.recording,
+                        &config.scene,
+                        tick_rate,
+                        &bindings,
+                    ))
+                })
+            };
+            let held = HeldInputs::new(&bindings);
 
             // Allow the window to be seen. hiding it then making visible speeds up load times.
-            renderer.window().set_visible(true);
+            // When headless we keep it hidden and render into our own texture instead.
+            let offscreen = if config.headless {
+                info!("running headless for {} frames", config.headless_frames);
+                Some(Box::new(OffscreenTarget::new(&renderer)))
+            } else {
+                renderer.window().set_visible(true);
+                None
+            };
 
             *self = Self::Ready {
-                text: Box::new(text),
                 renderer: Box::new(renderer),
                 state: Box::new(state),
                 input_handler: Box::new(InputHandler::new(
-                    bindings,
-                    Duration::from_millis(150),
+                    bindings.to_bindings(),
+                    CLICK_TIMEOUT,
                 )),
+                gamepad: Box::new(gamepad),
+                bindings: Box::new(bindings),
+                rebind: RebindScreen::default(),
+                events: demo_events(),
+                clipboard: Clipboard::new(),
+                ime_allowed: false,
+                recorder,
+                replay,
+                player,
                 frame_time: FrameTime::new(),
-                time: 0.0f32,
-                time2: 0.0f32,
-                time3: 0.0f32,
-                fps: 0u32,
+                fps,
+                timers,
+                game: Box::new(game),
+                timestep: FixedTimestep::new(tick_rate),
+                next_frame: Instant::now(),
                 size,
                 keys_pressed: HashSet::new(),
                 instance: Box::new(instance),
+                config: config.clone(),
+                offscreen,
+                frame_count: 0,
+                action_presses: ActionPresses::default(),
+                mouse_drag: MouseDrag::new(&bindings),
+                held: Box::new(held),
+                exit_error: None,
             };
         }
     }
@@ -602,18 +1065,34 @@ impl winit::application::ApplicationHandler for Runner {
         event: WindowEvent,
     ) {
         if let Self::Ready {
-            text,
             renderer,
             state,
             input_handler,
             frame_time,
-            time,
-            time2,
-            time3,
             fps,
+            timers,
+            game,
+            timestep,
+            next_frame: _,
             size,
             keys_pressed: _,
             instance,
+            config,
+            offscreen,
+            frame_count,
+            action_presses,
+            mouse_drag,
+            held,
+            gamepad,
+            bindings,
+            rebind,
+            events,
+            clipboard,
+            ime_allowed,
+            recorder,
+            replay,
+            player,
+            exit_error: _,
         } = self
         {
             if window_id == renderer.window().id()
@@ -624,56 +1103,53 @@ impl winit::application::ApplicationHandler for Runner {
                 return;
             }
 
+            // IME composition never reaches the InputHandler so it goes
+            // straight to the text fields.
+            if let WindowEvent::Ime(ime) = &event {
+                for field in state.text_fields.iter_mut() {
+                    field.handle_ime(ime);
+                }
+            }
+
             // update our inputs.
             input_handler.window_updates(&event);
 
+            let mut rebind_outcome = RebindOutcome::Ignored;
+            let replaying =
+                replay.as_ref().is_some_and(|replay| !replay.is_finished());
+
             while let Some(input) = input_handler.pop_event() {
-                match input {
-                    input::InputEvent::MouseButtonAction(action) => {
-                        match action {
-                            input::MouseButtonAction::Single(_) => {
-                                info!("Single Click")
-                            }
-                            input::MouseButtonAction::Double(_) => {
-                                info!("Double Click")
-                            }
-                            input::MouseButtonAction::Triple(_) => {
-                                info!("Triple Click")
-                            }
-                            _ => panic!("No clicks?"),
-                        }
-                    }
-                    input::InputEvent::MouseWheel { amount: _, axis: _ } => {
-                        //info!("MouseWheel: {}, {:?}", amount, axis);
-                    }
-                    input::InputEvent::None => {
-                        info!("WTF")
-                    }
-                    input::InputEvent::MouseButton {
-                        button: _,
-                        pressed: _,
-                    } => {
-                        //info!("MouseButton press: {:?}, pressed {}", button, pressed)
-                    }
-                    input::InputEvent::KeyInput {
-                        key,
-                        location,
-                        pressed,
-                    } => {
-                        info!(
-                            "KeyInput press: {key:?}, location {location:?}, pressed {pressed}",
-                        )
-                    }
-                    input::InputEvent::MousePosition { x: _, y: _ } => {
-                        //info!("MousePosition: x: {}, y: {}", x, y)
-                    }
-                    input::InputEvent::WindowFocused(b) => {
-                        info!("WindowFocused: focused: {b}")
-                    }
-                    input::InputEvent::Modifier { modifier, pressed } => {
-                        info!("Modifier: mod: {modifier:?}, pressed {pressed}",)
-                    }
+                // The players input is ignored while a recording plays back.
+                if replaying {
+                    continue;
                 }
+
+                // Recorded before any handler can consume it so a replay
+                // dispatches exactly what we did.
+                if let Some(recorder) = recorder
+                    && let Some(recorded) = RecordedEvent::from_event(&input)
+                {
+                    recorder.record(game.frame(), recorded);
+                }
+
+                events.dispatch(
+                    &input,
+                    &mut EventContext {
+                        state: state.as_mut(),
+                        mouse_drag,
+                        held: held.as_mut(),
+                        rebind,
+                        bindings: bindings.as_mut(),
+                        rebind_outcome: &mut rebind_outcome,
+                        clipboard,
+                        screen_size: Vec2::new(size.width, size.height),
+                    },
+                );
+            }
+
+            // When headless we render from about_to_wait into our own texture.
+            if offscreen.is_some() {
+                return;
             }
 
             // update our renderer based on events here
@@ -688,88 +1164,101 @@ impl winit::application::ApplicationHandler for Runner {
                 *size = new_size;
 
                 // Reset screen size for the Surface here.
-                state.system.set_projection(Projection::Orthographic {
-                    left: 0.0,
-                    right: new_size.width,
-                    bottom: 0.0,
-                    top: new_size.height,
-                    near: 1.0,
-                    far: -100.0,
-                });
+                state.system.set_projection(world_projection(new_size));
 
                 renderer.update_depth_texture();
-            }
 
-            // check if out close action was hit for esc
-            if input_handler.is_action_down(&Action::Quit) {
-                println!("The close button was pressed; stopping");
-                event_loop.exit();
+                let window = Vec2::new(new_size.width, new_size.height);
+
+                game.camera.set_screen_size(window);
+
+                for (viewport, camera) in state
+                    .viewports
+                    .iter_mut()
+                    .zip(game.viewport_cameras.iter_mut())
+                {
+                    viewport.resize(window);
+                    camera.set_screen_size(viewport.size(window));
+                }
             }
 
-            frame_time.update_recent();
-            let seconds = frame_time.seconds();
-            // update our systems data to the gpu. this is the Camera in the shaders.
-            state.system.update(renderer, frame_time);
+            let frame = read_frame_input(
+                events,
+                &mut EventContext {
+                    state: state.as_mut(),
+                    mouse_drag,
+                    held: held.as_mut(),
+                    rebind,
+                    bindings: bindings.as_mut(),
+                    rebind_outcome: &mut rebind_outcome,
+                    clipboard,
+                    screen_size: Vec2::new(size.width, size.height),
+                },
+                game,
+                gamepad,
+                action_presses,
+                replay,
+                player,
+                recorder,
+                config,
+            );
+            let typing = state.text_fields.iter().any(TextField::is_focused);
 
-            // update our systems data to the gpu. this is the Screen in the shaders.
-            state
-                .system
-                .update_screen(renderer, [new_size.width, new_size.height]);
+            if typing != *ime_allowed {
+                renderer.window().set_ime_allowed(typing);
+                *ime_allowed = typing;
+            }
 
-            // This adds the Image data to the Buffer for rendering.
-            state.sprites.iter_mut().for_each(|sprite| {
-                state.sprite_renderer.update(
-                    sprite,
-                    renderer,
-                    &mut state.image_atlas,
-                    0,
+            // Keep the IME's candidate window next to the caret.
+            if let Some(field) =
+                state.text_fields.iter().find(|field| field.is_focused())
+            {
+                let pos = state.world_to_screen(
+                    field.caret_pos(),
+                    Vec2::new(size.width, size.height),
+                    CameraView::MainView,
                 );
-            });
 
-            state.animation_renderer.update(
-                &mut state.animation,
-                renderer,
-                &mut state.image_atlas,
-                0,
-            );
+                renderer.window().set_ime_cursor_area(
+                    winit::dpi::PhysicalPosition::new(pos.x, pos.y),
+                    PhysicalSize::new(1.0, 18.0),
+                );
+            }
 
-            // this cycles all the Image's in the Image buffer by first putting them in rendering order
-            // and then uploading them to the GPU if they have moved or changed in any way. clears the
-            // Image buffer for the next render pass. Image buffer only holds the ID's and Sortign info
-            // of the finalized Indicies of each Image.
-            state.sprite_renderer.finalize(renderer);
-            state.animation_renderer.finalize(renderer);
+            // check if out close action was hit for esc
+            if frame.quit {
+                info!("Quit was pressed; stopping");
+                event_loop.exit();
+            }
 
-            state
-                .text_renderer
-                .update(text, &mut state.text_atlas, renderer, 0)
-                .unwrap();
-            state.text_renderer.finalize(renderer);
+            // Run the game logic at a fixed rate no matter how often we render.
+            let steps = timestep.advance();
+            // Replays run one update per frame so none are skipped or doubled.
+            let (steps, alpha) = if replaying {
+                (1, 1.0)
+            } else {
+                (steps, timestep.alpha())
+            };
 
-            state.map_renderer.update(
-                &mut state.map,
+            update_frame(
                 renderer,
-                &mut state.map_atlas,
-                [0, 1],
-            );
-
-            state.map_renderer.finalize(renderer);
-
-            state.light_renderer.update(&mut state.lights, renderer, 0);
-            state.light_renderer.finalize(renderer);
-            state.mesh.iter_mut().for_each(|mesh| {
-                state.mesh_renderer.update(mesh, renderer, 0);
-            });
+                state,
+                game,
+                timers,
+                frame_time,
+                mouse_drag,
+                &frame.game,
+                steps,
+                timestep.dt(),
+                alpha,
+                false,
+            )
+            .unwrap();
 
-            state.mesh_renderer.finalize(renderer);
+            if let Some(recorder) = recorder {
+                recorder.flush();
+            }
 
-            state.ui_renderer.update(
-                &mut state.rect,
-                renderer,
-                &mut state.ui_atlas,
-                0,
-            );
-            state.ui_renderer.finalize(renderer);
             // Start encoding commands. this stores all the rendering calls for execution when
             // finish is called.
             let mut encoder = renderer.device().create_command_encoder(
@@ -784,50 +1273,36 @@ impl winit::application::ApplicationHandler for Runner {
             // Submit our command queue. for it to upload all the changes that were made.
             // Also tells the system to begin running the commands on the GPU.
             renderer.queue().submit(std::iter::once(encoder.finish()));
-
-            if *time3 < seconds {
-                let mut pos = text.pos;
-                pos.x += 1.0;
-
-                text.set_pos(pos);
-                *time3 = seconds + 0.0001;
-            }
-
-            if *time < seconds {
-                text.set_text(
-                    &format!("生活,삶,जिंदगी 😀 FPS: {fps} \n yhelloy"),
-                    &Attrs::new(),
-                    Shaping::Advanced,
-                    Some(Align::Left),
-                );
-                text.set_pos(Vec3::new(-25.0, 0.0, 1.0));
-
-                *fps = 0u32;
-                *time = seconds + 1.0;
-            }
-
-            if *time2 < seconds {
-                for (_, dir_light) in state.lights.directional_lights.iter_mut()
-                {
-                    dir_light.angle += 10.0;
-
-                    if dir_light.angle >= 360.0 {
-                        dir_light.angle = 0.0;
-                    }
+            state.profiler.map_timestamps();
+            *frame_count += 1;
+
+            // Render the same pass again into a texture we can read back and save.
+            let screenshot = frame.pressed.contains(&Action::Screenshot);
+            let capture_frame = config.screenshot_frame > 0
+                && *frame_count == u64::from(config.screenshot_frame);
+
+            if screenshot || capture_frame {
+                let path =
+                    screenshot_path(&config.screenshot_dir, *frame_count);
+                let target = render_offscreen(state.as_mut(), renderer);
+
+                match target.save_png(renderer, &path) {
+                    Ok(()) => info!("saved screenshot {}", path.display()),
+                    Err(e) => error!("screenshot failed: {e}"),
                 }
 
-                state.lights.directionals_changed = true;
-
-                *time2 = seconds + 1.0;
+                if capture_frame {
+                    event_loop.exit();
+                }
             }
 
-            *fps += 1;
+            fps.set(fps.get() + 1);
 
             renderer.present().unwrap();
 
             // These clear the Last used image tags.
             //Can be used later to auto unload things not used anymore if ram/gpu ram becomes a issue.
-            if *fps == 1 {
+            if fps.get() == 1 {
                 state.image_atlas.trim();
                 state.map_atlas.trim();
                 state.text_atlas.trim();
@@ -843,45 +1318,184 @@ impl winit::application::ApplicationHandler for Runner {
         event: DeviceEvent,
     ) {
         if let Self::Ready {
-            text: _,
             renderer: _,
             state: _,
             input_handler,
             frame_time: _,
-            time: _,
-            time2: _,
-            time3: _,
             fps: _,
+            timers: _,
+            game: _,
+            timestep: _,
+            next_frame: _,
             size: _,
             keys_pressed: _,
             instance: _,
+            config: _,
+            offscreen: _,
+            frame_count: _,
+            action_presses: _,
+            mouse_drag: _,
+            held: _,
+            gamepad: _,
+            bindings: _,
+            rebind: _,
+            events: _,
+            clipboard: _,
+            ime_allowed: _,
+            recorder: _,
+            replay: _,
+            player: _,
+            exit_error: _,
         } = self
         {
             input_handler.device_updates(&event);
         }
     }
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         if let Self::Ready {
-            text: _,
             renderer,
-            state: _,
+            state,
             input_handler: _,
-            frame_time: _,
-            time: _,
-            time2: _,
-            time3: _,
+            frame_time,
             fps: _,
-            size: _,
+            timers,
+            game,
+            timestep,
+            next_frame,
+            size,
             keys_pressed: _,
             instance: _,
+            config,
+            offscreen,
+            frame_count,
+            action_presses,
+            mouse_drag,
+            held,
+            gamepad,
+            bindings,
+            rebind,
+            events,
+            clipboard,
+            ime_allowed: _,
+            recorder,
+            replay,
+            player,
+            exit_error,
         } = self
         {
+            if let Some(target) = offscreen {
+                // Headless runs have no Surface events to render from so we
+                // run the same update and render sequence here instead.
+                // Each frame is exactly one game step so runs are repeatable.
+                let frame = read_frame_input(
+                    events,
+                    &mut EventContext {
+                        state: state.as_mut(),
+                        mouse_drag,
+                        held: held.as_mut(),
+                        rebind,
+                        bindings: bindings.as_mut(),
+                        rebind_outcome: &mut RebindOutcome::Ignored,
+                        clipboard,
+                        screen_size: Vec2::new(size.width, size.height),
+                    },
+                    game,
+                    gamepad,
+                    action_presses,
+                    replay,
+                    player,
+                    recorder,
+                    config,
+                );
+
+                if frame.quit {
+                    info!("Quit was pressed; stopping");
+                    event_loop.exit();
+                }
+
+                update_frame(
+                    renderer,
+                    state,
+                    game,
+                    timers,
+                    frame_time,
+                    mouse_drag,
+                    &frame.game,
+                    1,
+                    timestep.dt(),
+                    1.0,
+                    config.golden,
+                )
+                .unwrap();
+
+                let mut encoder = renderer.device().create_command_encoder(
+                    &wgpu::CommandEncoderDescriptor {
+                        label: Some("headless command encoder"),
+                    },
+                );
+
+                state.render_to(renderer, &mut encoder, &target.view);
+                renderer.queue().submit(std::iter::once(encoder.finish()));
+                state.profiler.map_timestamps();
+                *frame_count += 1;
+
+                if config.screenshot_frame > 0
+                    && *frame_count == u64::from(config.screenshot_frame)
+                {
+                    let path =
+                        screenshot_path(&config.screenshot_dir, *frame_count);
+
+                    match target.save_png(renderer, &path) {
+                        Ok(()) => info!("saved screenshot {}", path.display()),
+                        Err(e) => error!("screenshot failed: {e}"),
+                    }
+                }
+
+                if *frame_count >= u64::from(config.headless_frames) {
+                    info!("headless run finished {frame_count} frames");
+
+                    if config.golden {
+                        let name = Path::new(&config.scene)
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| String::from("scene"));
+
+                        if let Err(e) = golden::check_target(
+                            target,
+                            renderer,
+                            &config.golden_dir,
+                            &name,
+                            config.golden_tolerance,
+                            config.golden_update,
+                        ) {
+                            *exit_error = Some(e);
+                        }
+                    }
+
+                    event_loop.exit();
+                }
+
+                return;
+            }
+
+            //Enforce the Event to always trigger after a set time. Best for rendering.
+            if config.frame_limit {
+                let now = Instant::now();
+
+                if now < *next_frame {
+                    event_loop
+                        .set_control_flow(ControlFlow::WaitUntil(*next_frame));
+                    return;
+                }
+
+                *next_frame = now + Duration::from_millis(config.frame_wait_ms);
+                event_loop
+                    .set_control_flow(ControlFlow::WaitUntil(*next_frame));
+            }
+
             // If we are Ready then if there are no events then lets tell the system to prepare a redraw().
             renderer.window().request_redraw();
         }
-
-        //Enforce the Event to always trigger after a set time. Best for rendering.
-        //event_loop.set_control_flow(ControlFlow::Poll);
     }
 }
 
@@ -889,8 +1503,10 @@ impl winit::application::ApplicationHandler for Runner {
 async fn main() -> Result<(), GraphicsError> {
     // Create logger to output to a File
     log::set_logger(&MY_LOGGER).unwrap();
+    // Load our config file and command line overrides.
+    let config = DemoConfig::load()?;
     // Set the Max level we accept logging to the file for.
-    log::set_max_level(LevelFilter::Info);
+    log::set_max_level(config.log_level.into());
 
     info!("starting up");
     let updater = Updater::new().unwrap();
@@ -904,9 +1520,44 @@ async fn main() -> Result<(), GraphicsError> {
     // Starts an event gathering type for the window.
     let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
-    let mut runner = Runner::Loading;
+    let mut runner = Runner::Loading(Box::new(config));
     event_loop.run_app(&mut runner).unwrap();
     updater.stop().unwrap();
 
+    if let Runner::Failed(e) = runner {
+        return Err(e);
+    }
+
+    if let Runner::Ready {
+        config,
+        state,
+        frame_count,
+        recorder,
+        exit_error,
+        ..
+    } = runner
+    {
+        if config.profile {
+            state.profiler.write_report(&config.profile_dump)?;
+        }
+
+        if let Some(recorder) = recorder {
+            recorder.save()?;
+        }
+
+        if let Some(e) = exit_error {
+            return Err(e);
+        }
+
+        // Headless runs are used by CI so let it know if we never finished.
+        if config.headless && frame_count < u64::from(config.headless_frames) {
+            return Err(OtherError::new(&format!(
+                "headless run stopped after {frame_count} of {} frames",
+                config.headless_frames
+            ))
+            .into());
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,735 @@
+use crate::{
+    collision::{AttributeGrid, TileAttributes},
+    scene::{MAP_LAYERS, MAP_SIZE, TILE_SIZE, to_color},
+};
+use graphics::*;
+use log::{info, warn};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Bumped whenever the map file format changes so old files are refused
+/// instead of being loaded wrong. Version 2 added tile attributes.
+pub const MAP_FILE_VERSION: u32 = 2;
+/// The first bytes of every binary map file.
+const MAP_FILE_MAGIC: &[u8; 4] = b"DMAP";
+
+/// The atlas index of each tile of every tilesheet uploaded into the map
+/// atlas. Map files store tiles by tilesheet name and index instead of atlas
+/// index since that changes with the order tilesheets are uploaded in.
+#[derive(Clone, Debug, Default)]
+pub struct Tilesheets {
+    sheets: Vec<(String, Vec<usize>)>,
+}
+
+impl Tilesheets {
+    /// Map files find tiles by tilesheet name so each has to be unique.
+    pub fn insert(
+        &mut self,
+        name: &str,
+        tiles: Vec<usize>,
+    ) -> Result<(), GraphicsError> {
+        if self.tiles(name).is_some() {
+            return Err(OtherError::new(&format!(
+                "tilesheet {name} was already loaded"
+            ))
+            .into());
+        }
+
+        self.sheets.push((name.to_owned(), tiles));
+        Ok(())
+    }
+
+    /// Name of each tilesheet in the order they were uploaded.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sheets.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// How many tiles the named tilesheet has.
+    pub fn len(&self, name: &str) -> usize {
+        self.tiles(name).map_or(0, |tiles| tiles.len())
+    }
+
+    /// The atlas index of a tile within the named tilesheet.
+    pub fn atlas_id(&self, name: &str, tile: u32) -> Option<usize> {
+        self.tiles(name)?.get(tile as usize).copied()
+    }
+
+    /// The tilesheet name and tile within it an atlas index came from.
+    pub fn find(&self, atlas_id: usize) -> Option<(&str, u32)> {
+        self.sheets.iter().find_map(|(name, tiles)| {
+            tiles
+                .iter()
+                .position(|&id| id == atlas_id)
+                .map(|tile| (name.as_str(), tile as u32))
+        })
+    }
+
+    fn tiles(&self, name: &str) -> Option<&Vec<usize>> {
+        self.sheets
+            .iter()
+            .find(|(sheet, _)| sheet == name)
+            .map(|(_, tiles)| tiles)
+    }
+}
+
+/// A Map as stored on disk so it can be shared between the client and the
+/// server. Saved as RON when the path ends in .ron and as a compact binary
+/// otherwise.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapFile {
+    pub version: u32,
+    pub pos: Vec2,
+    /// Width and height in tiles.
+    pub size: UVec2,
+    pub tile_size: u32,
+    /// How many layers tiles can be set on.
+    pub layers: u32,
+    /// Every tilesheet the tiles use.
+    pub tilesheets: Vec<String>,
+    /// Only the tiles that are set. Every other tile is empty.
+    pub tiles: Vec<MapFileTile>,
+    /// Only the tiles with attributes. Missing from version 1 files.
+    #[serde(default)]
+    pub attributes: Vec<MapFileAttributes>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapFileTile {
+    /// x, y and layer of the tile.
+    pub pos: UVec3,
+    /// Index within `tilesheets`.
+    pub sheet: u16,
+    /// Index of the tile within its tilesheet.
+    pub tile: u32,
+    pub color: [u8; 4],
+    pub anim_time: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapFileAttributes {
+    pub pos: UVec2,
+    pub attributes: TileAttributes,
+}
+
+impl MapFile {
+    /// Collects every set tile within the map along with its attributes.
+    /// Tiles from a tilesheet that is not within `sheets` can not be saved
+    /// and are left out.
+    pub fn from_map(
+        map: &Map,
+        attributes: &AttributeGrid,
+        sheets: &Tilesheets,
+    ) -> Self {
+        let mut file = Self {
+            version: MAP_FILE_VERSION,
+            pos: map.pos,
+            size: UVec2::splat(MAP_SIZE),
+            tile_size: TILE_SIZE,
+            layers: MAP_LAYERS,
+            tilesheets: Vec::new(),
+            tiles: Vec::new(),
+            attributes: attributes
+                .iter()
+                .map(|(pos, attributes)| MapFileAttributes { pos, attributes })
+                .collect(),
+        };
+        let mut skipped = 0;
+
+        for layer in 0..MAP_LAYERS {
+            for y in 0..MAP_SIZE {
+                for x in 0..MAP_SIZE {
+                    let pos = UVec3::new(x, y, layer);
+                    let data = map.get_tile(pos);
+
+                    if data.id == 0 {
+                        continue;
+                    }
+
+                    let Some((name, tile)) = sheets.find(data.id) else {
+                        skipped += 1;
+                        continue;
+                    };
+                    let sheet = match file
+                        .tilesheets
+                        .iter()
+                        .position(|sheet| sheet == name)
+                    {
+                        Some(sheet) => sheet,
+                        None => {
+                            file.tilesheets.push(name.to_owned());
+                            file.tilesheets.len() - 1
+                        }
+                    };
+
+                    file.tiles.push(MapFileTile {
+                        pos,
+                        sheet: sheet as u16,
+                        tile,
+                        color: [
+                            data.color.r(),
+                            data.color.g(),
+                            data.color.b(),
+                            data.color.a(),
+                        ],
+                        anim_time: data.anim_time,
+                    });
+                }
+            }
+        }
+
+        if skipped > 0 {
+            warn!("left out {skipped} tiles not from a known tilesheet");
+        }
+
+        file
+    }
+
+    /// Clears the map and sets every tile from the file onto it. Fails
+    /// without touching the map if the file needs a tilesheet that was not
+    /// loaded or has a tile outside of its size and layers.
+    pub fn apply(
+        &self,
+        map: &mut Map,
+        sheets: &Tilesheets,
+    ) -> Result<(), GraphicsError> {
+        self.validate(sheets)?;
+        map.pos = self.pos;
+
+        for layer in 0..MAP_LAYERS {
+            for y in 0..MAP_SIZE {
+                for x in 0..MAP_SIZE {
+                    map.set_tile(UVec3::new(x, y, layer), empty_tile());
+                }
+            }
+        }
+
+        for tile in &self.tiles {
+            map.set_tile(
+                tile.pos,
+                TileData {
+                    id: self.atlas_id(tile, sheets)?,
+                    color: to_color(tile.color),
+                    anim_time: tile.anim_time,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    // Checks everything `apply` needs before the map is cleared.
+    fn validate(&self, sheets: &Tilesheets) -> Result<(), GraphicsError> {
+        if self.size.x > MAP_SIZE
+            || self.size.y > MAP_SIZE
+            || self.layers > MAP_LAYERS
+            || self.tile_size != TILE_SIZE
+        {
+            return Err(OtherError::new(&format!(
+                "map file is {}x{} tiles with {} layers of {} pixels which does not fit",
+                self.size.x, self.size.y, self.layers, self.tile_size
+            ))
+            .into());
+        }
+
+        for name in &self.tilesheets {
+            if sheets.len(name) == 0 {
+                return Err(OtherError::new(&format!(
+                    "map file uses tilesheet {name} which is not loaded"
+                ))
+                .into());
+            }
+        }
+
+        for tile in &self.tiles {
+            // Map::set_tile would place these on the wrong tile or panic.
+            if tile.pos.x >= self.size.x
+                || tile.pos.y >= self.size.y
+                || tile.pos.z >= self.layers
+            {
+                return Err(OtherError::new(&format!(
+                    "map file tile at {} is outside of its {}x{} tiles and {} layers",
+                    tile.pos, self.size.x, self.size.y, self.layers
+                ))
+                .into());
+            }
+
+            self.atlas_id(tile, sheets)?;
+        }
+
+        Ok(())
+    }
+
+    fn atlas_id(
+        &self,
+        tile: &MapFileTile,
+        sheets: &Tilesheets,
+    ) -> Result<usize, GraphicsError> {
+        let name =
+            self.tilesheets.get(tile.sheet as usize).ok_or_else(|| {
+                OtherError::new(&format!(
+                    "map file tile at {} uses a missing tilesheet",
+                    tile.pos
+                ))
+            })?;
+
+        sheets.atlas_id(name, tile.tile).ok_or_else(|| {
+            OtherError::new(&format!(
+                "map file uses tile {} of tilesheet {name} which only has {}",
+                tile.tile,
+                sheets.len(name)
+            ))
+            .into()
+        })
+    }
+
+    /// The attributes of every tile. Tiles outside of the map are left out.
+    pub fn attribute_grid(&self) -> AttributeGrid {
+        let mut grid = AttributeGrid::default();
+
+        for tile in &self.attributes {
+            grid.set(tile.pos, tile.attributes);
+        }
+
+        grid
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GraphicsError> {
+        let path = path.as_ref();
+        let file = if is_ron(path) {
+            let data = fs::read_to_string(path)?;
+
+            ron::from_str(&data).map_err(|e| e.to_string())
+        } else {
+            Self::from_bytes(&fs::read(path)?)
+        }
+        .map_err(|e| {
+            OtherError::new(&format!(
+                "failed to load map {}: {e}",
+                path.display()
+            ))
+        })?;
+
+        if !is_supported(file.version) {
+            return Err(OtherError::new(&format!(
+                "map {} is version {} but only up to version {} is supported",
+                path.display(),
+                file.version,
+                MAP_FILE_VERSION
+            ))
+            .into());
+        }
+
+        Ok(file)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GraphicsError> {
+        let path = path.as_ref();
+
+        if is_ron(path) {
+            let data =
+                ron::ser::to_string_pretty(self, PrettyConfig::default())
+                    .map_err(|e| {
+                        OtherError::new(&format!(
+                            "failed to serialize map: {e}"
+                        ))
+                    })?;
+
+            fs::write(path, data)?;
+        } else {
+            fs::write(path, self.to_bytes())?;
+        }
+
+        info!("saved {} tiles to {}", self.tiles.len(), path.display());
+        Ok(())
+    }
+
+    /// Everything is little endian. Positions are u8 since maps are far
+    /// smaller than 256 tiles across. Always written as the current version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.tiles.len() * 16);
+
+        bytes.extend_from_slice(MAP_FILE_MAGIC);
+        bytes.extend_from_slice(&MAP_FILE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.pos.x.to_le_bytes());
+        bytes.extend_from_slice(&self.pos.y.to_le_bytes());
+        bytes.extend_from_slice(&self.size.x.to_le_bytes());
+        bytes.extend_from_slice(&self.size.y.to_le_bytes());
+        bytes.extend_from_slice(&self.tile_size.to_le_bytes());
+        bytes.extend_from_slice(&self.layers.to_le_bytes());
+        bytes.extend_from_slice(&(self.tilesheets.len() as u16).to_le_bytes());
+
+        for name in &self.tilesheets {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.tiles.len() as u32).to_le_bytes());
+
+        for tile in &self.tiles {
+            bytes.push(tile.pos.x as u8);
+            bytes.push(tile.pos.y as u8);
+            bytes.push(tile.pos.z as u8);
+            bytes.extend_from_slice(&tile.sheet.to_le_bytes());
+            bytes.extend_from_slice(&tile.tile.to_le_bytes());
+            bytes.extend_from_slice(&tile.color);
+            bytes.extend_from_slice(&tile.anim_time.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.attributes.len() as u32).to_le_bytes());
+
+        for tile in &self.attributes {
+            let attributes = &tile.attributes;
+            let bits = u8::from(attributes.blocked)
+                | u8::from(attributes.water) << 1
+                | u8::from(attributes.warp.is_some()) << 2;
+
+            bytes.push(tile.pos.x as u8);
+            bytes.push(tile.pos.y as u8);
+            bytes.push(bits);
+            bytes.extend_from_slice(&attributes.flags.to_le_bytes());
+
+            if let Some(warp) = attributes.warp {
+                bytes.extend_from_slice(&warp.x.to_le_bytes());
+                bytes.extend_from_slice(&warp.y.to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != MAP_FILE_MAGIC {
+            return Err("not a map file".into());
+        }
+
+        let version = reader.u32()?;
+
+        // Later versions may lay the rest out differently.
+        if !is_supported(version) {
+            return Err(format!(
+                "version {version} is not supported, only up to version {MAP_FILE_VERSION} is"
+            ));
+        }
+
+        let pos = Vec2::new(reader.f32()?, reader.f32()?);
+        let size = UVec2::new(reader.u32()?, reader.u32()?);
+        let tile_size = reader.u32()?;
+        let layers = reader.u32()?;
+        let tilesheets = (0..reader.u16()?)
+            .map(|_| {
+                let len = reader.u16()? as usize;
+
+                String::from_utf8(reader.take(len)?.to_vec())
+                    .map_err(|e| e.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let tiles = (0..reader.u32()?)
+            .map(|_| {
+                let tile = MapFileTile {
+                    pos: UVec3::new(
+                        reader.u8()?.into(),
+                        reader.u8()?.into(),
+                        reader.u8()?.into(),
+                    ),
+                    sheet: reader.u16()?,
+                    tile: reader.u32()?,
+                    color: reader.color()?,
+                    anim_time: reader.u32()?,
+                };
+
+                if tile.sheet as usize >= tilesheets.len() {
+                    return Err(format!(
+                        "tile at {} uses tilesheet {} of {}",
+                        tile.pos,
+                        tile.sheet,
+                        tilesheets.len()
+                    ));
+                }
+
+                Ok(tile)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let attributes = if version >= 2 {
+            (0..reader.u32()?)
+                .map(|_| {
+                    let pos =
+                        UVec2::new(reader.u8()?.into(), reader.u8()?.into());
+                    let bits = reader.u8()?;
+                    let flags = reader.u32()?;
+                    let warp = if bits & 4 != 0 {
+                        Some(Vec2::new(reader.f32()?, reader.f32()?))
+                    } else {
+                        None
+                    };
+
+                    Ok(MapFileAttributes {
+                        pos,
+                        attributes: TileAttributes {
+                            blocked: bits & 1 != 0,
+                            water: bits & 2 != 0,
+                            warp,
+                            flags,
+                        },
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        } else {
+            Vec::new()
+        };
+
+        if !reader.bytes.is_empty() {
+            return Err(format!(
+                "{} bytes left over at the end",
+                reader.bytes.len()
+            ));
+        }
+
+        Ok(Self {
+            version,
+            pos,
+            size,
+            tile_size,
+            layers,
+            tilesheets,
+            tiles,
+            attributes,
+        })
+    }
+}
+
+/// What unset tiles are reset to.
+pub fn empty_tile() -> TileData {
+    TileData {
+        id: 0,
+        color: Color::rgba(255, 255, 255, 255),
+        anim_time: 0,
+    }
+}
+
+// Older versions are still read but always saved as the current one.
+fn is_supported(version: u32) -> bool {
+    (1..=MAP_FILE_VERSION).contains(&version)
+}
+
+fn is_ron(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ron"))
+}
+
+// Reads little endian values from the front of the bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("the file ends early".into());
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes([
+            self.u8()?,
+            self.u8()?,
+            self.u8()?,
+            self.u8()?,
+        ]))
+    }
+
+    fn color(&mut self) -> Result<[u8; 4], String> {
+        Ok([self.u8()?, self.u8()?, self.u8()?, self.u8()?])
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(pos: UVec3, sheet: u16, tile: u32) -> MapFileTile {
+        MapFileTile {
+            pos,
+            sheet,
+            tile,
+            color: [255, 128, 64, 255],
+            anim_time: 250,
+        }
+    }
+
+    fn sample() -> MapFile {
+        MapFile {
+            version: MAP_FILE_VERSION,
+            pos: Vec2::new(-40.0, 120.5),
+            size: UVec2::splat(MAP_SIZE),
+            tile_size: TILE_SIZE,
+            layers: MAP_LAYERS,
+            tilesheets: vec!["ground".into(), "walls".into()],
+            tiles: vec![
+                tile(UVec3::new(0, 0, 0), 0, 3),
+                tile(UVec3::new(31, 31, 1), 1, 0),
+                tile(UVec3::new(5, 7, MAP_LAYERS - 1), 0, 12),
+            ],
+            attributes: vec![
+                MapFileAttributes {
+                    pos: UVec2::new(1, 2),
+                    attributes: TileAttributes {
+                        blocked: true,
+                        flags: 7,
+                        ..TileAttributes::default()
+                    },
+                },
+                MapFileAttributes {
+                    pos: UVec2::new(3, 4),
+                    attributes: TileAttributes {
+                        water: true,
+                        warp: Some(Vec2::new(200.0, -20.0)),
+                        ..TileAttributes::default()
+                    },
+                },
+            ],
+        }
+    }
+
+    // Each test gets its own file so they can run at the same time.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("demo-mapfile-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let file = sample();
+
+        assert_eq!(MapFile::from_bytes(&file.to_bytes()), Ok(file));
+    }
+
+    #[test]
+    fn saves_and_loads_both_formats() {
+        let file = sample();
+
+        for name in ["map.ron", "map.bin"] {
+            let path = temp_path(name);
+
+            file.save(&path).unwrap();
+
+            let loaded = MapFile::load(&path);
+
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.unwrap(), file);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bytes = sample().to_bytes();
+
+        bytes[0] = b'X';
+        assert!(MapFile::from_bytes(&bytes).is_err());
+
+        let mut bytes = sample().to_bytes();
+
+        bytes[4..8].copy_from_slice(&(MAP_FILE_VERSION + 1).to_le_bytes());
+        assert!(MapFile::from_bytes(&bytes).is_err());
+
+        bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+        assert!(MapFile::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_and_trailing_bytes() {
+        let bytes = sample().to_bytes();
+
+        for len in [0, 3, 8, bytes.len() / 2, bytes.len() - 1] {
+            assert!(MapFile::from_bytes(&bytes[..len]).is_err(), "{len}");
+        }
+
+        let mut bytes = bytes;
+
+        bytes.push(0);
+        assert!(MapFile::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_sheets() {
+        let mut file = sample();
+
+        file.tiles.push(tile(UVec3::ZERO, 2, 0));
+        assert!(MapFile::from_bytes(&file.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn only_validates_tiles_within_the_map() {
+        let mut sheets = Tilesheets::default();
+
+        sheets.insert("ground", (1..=16).collect()).unwrap();
+        sheets.insert("walls", vec![17]).unwrap();
+        assert!(sample().validate(&sheets).is_ok());
+
+        let outside = [
+            UVec3::new(MAP_SIZE, 0, 0),
+            UVec3::new(0, MAP_SIZE, 0),
+            UVec3::new(0, 0, MAP_LAYERS),
+        ];
+
+        for pos in outside {
+            let mut file = sample();
+
+            file.tiles.push(tile(pos, 0, 0));
+            assert!(file.validate(&sheets).is_err(), "{pos}");
+        }
+
+        let mut file = sample();
+
+        file.tiles.push(tile(UVec3::ZERO, 1, 1));
+        assert!(file.validate(&sheets).is_err());
+        assert!(sample().validate(&Tilesheets::default()).is_err());
+    }
+
+    #[test]
+    fn loads_version_1_without_attributes() {
+        let mut file = sample();
+
+        file.attributes.clear();
+
+        // Version 1 files end right after their tiles.
+        let mut bytes = file.to_bytes();
+
+        bytes.truncate(bytes.len() - 4);
+        bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+
+        let loaded = MapFile::from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.version, 1);
+        assert!(loaded.attributes.is_empty());
+        assert_eq!(loaded.tiles, file.tiles);
+
+        let ron = ron::to_string(&file)
+            .unwrap()
+            .replace("version:2", "version:1")
+            .replace(",attributes:[]", "");
+        let loaded: MapFile = ron::from_str(&ron).unwrap();
+
+        assert_eq!(loaded.version, 1);
+        assert!(loaded.attributes.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::gamestate::State;
+use graphics::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A color texture we can render the State into instead of the Surface.
+/// Uses the same format as the Surface so our pipelines can draw into it.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OffscreenTarget {
+    /// Creates a new target the size of the renderers screen so the
+    /// renderers depth buffer can be shared with it.
+    pub fn new(renderer: &GpuRenderer) -> Self {
+        let size = renderer.size();
+        let width = (size.width as u32).max(1);
+        let height = (size.height as u32).max(1);
+
+        let texture =
+            renderer.device().create_texture(&wgpu::TextureDescriptor {
+                label: Some("offscreen target"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: renderer.surface_format(),
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            width,
+            height,
+        }
+    }
+
+    /// Copies the texture back from the GPU as tightly packed RGBA8 rows.
+    /// Blocks until the GPU has finished all submitted work.
+    pub fn read_pixels(
+        &self,
+        renderer: &GpuRenderer,
+    ) -> Result<Vec<u8>, GraphicsError> {
+        let format = self.texture.format();
+        let swap_red_blue = match format {
+            wgpu::TextureFormat::Rgba8Unorm
+            | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm
+            | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => {
+                return Err(OtherError::new(&format!(
+                    "can not read back texture format {format:?}"
+                ))
+                .into());
+            }
+        };
+
+        // Buffer rows must be aligned to 256 bytes so we pad them and strip
+        // the padding back out once read.
+        let row_bytes = self.width * 4;
+        let padded_row_bytes = row_bytes
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = renderer.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen readback buffer"),
+            size: u64::from(padded_row_bytes) * u64::from(self.height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = renderer.device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("offscreen readback encoder"),
+            },
+        );
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            self.texture.size(),
+        );
+
+        renderer.queue().submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+            let _ = sender.send(result);
+        });
+
+        renderer
+            .device()
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|e| OtherError::new(&format!("readback failed: {e}")))?;
+
+        receiver
+            .recv()
+            .map_err(|e| OtherError::new(&format!("readback failed: {e}")))?
+            .map_err(|e| OtherError::new(&format!("readback failed: {e}")))?;
+
+        let mut pixels = Vec::with_capacity((row_bytes * self.height) as usize);
+
+        {
+            let data = buffer.get_mapped_range(..);
+
+            for row in data.chunks_exact(padded_row_bytes as usize) {
+                pixels.extend_from_slice(&row[..row_bytes as usize]);
+            }
+        }
+
+        buffer.unmap();
+
+        if swap_red_blue {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        Ok(pixels)
+    }
+
+    /// Reads the texture back and saves it as a PNG.
+    pub fn save_png(
+        &self,
+        renderer: &GpuRenderer,
+        path: impl AsRef<Path>,
+    ) -> Result<(), GraphicsError> {
+        let path = path.as_ref();
+        let pixels = self.read_pixels(renderer)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        image::save_buffer(
+            path,
+            &pixels,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| {
+            OtherError::new(&format!("failed to save {}: {e}", path.display()))
+                .into()
+        })
+    }
+}
+
+/// Renders the State into a new offscreen target so it can be read back.
+/// Expects the State's buffers to already be updated for this frame.
+pub fn render_offscreen<Controls>(
+    state: &mut State<Controls>,
+    renderer: &GpuRenderer,
+) -> OffscreenTarget
+where
+    Controls: camera::controls::Controls,
+{
+    let target = OffscreenTarget::new(renderer);
+    let mut encoder = renderer.device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor {
+            label: Some("offscreen command encoder"),
+        },
+    );
+
+    state.render_to(renderer, &mut encoder, &target.view);
+    renderer.queue().submit(std::iter::once(encoder.finish()));
+    target
+}
+
+/// Gives each screenshot a unique name within the screenshot folder.
+pub fn screenshot_path(dir: impl AsRef<Path>, frame: u64) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+
+    dir.as_ref()
+        .join(format!("screenshot_{secs}_frame{frame}.png"))
+}
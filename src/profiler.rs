@@ -0,0 +1,411 @@
+use graphics::*;
+use log::info;
+use serde::Serialize;
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+/// Device features GPU timings need. Only requested when the adapter has them.
+pub const GPU_TIMING_FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY
+    .union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES);
+
+/// Draw groups within the render pass in the order they are drawn. A
+/// timestamp is written before the first group and after each one.
+pub const GPU_STAGES: [&str; 8] = [
+    "map lower",
+    "images",
+    "animations",
+    "map upper",
+    "lights",
+    "text",
+    "meshes",
+    "rects",
+];
+
+const QUERY_COUNT: u32 = GPU_STAGES.len() as u32 + 1;
+const QUERY_BUFFER_SIZE: u64 = QUERY_COUNT as u64 * wgpu::QUERY_SIZE as u64;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct StageTimes {
+    samples: u64,
+    total_ms: f64,
+    max_ms: f64,
+    /// Only what was recorded since the last time we logged.
+    recent_samples: u64,
+    recent_ms: f64,
+}
+
+impl StageTimes {
+    fn add(&mut self, ms: f64) {
+        self.samples += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+        self.recent_samples += 1;
+        self.recent_ms += ms;
+    }
+}
+
+/// A single stage within the profile dump.
+#[derive(Clone, Debug, Serialize)]
+pub struct StageReport {
+    pub name: String,
+    /// Either cpu or gpu.
+    pub kind: String,
+    pub samples: u64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Everything the profiler recorded over the run. Written out as TOML.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProfileReport {
+    pub gpu_timings: bool,
+    pub stages: Vec<StageReport>,
+}
+
+impl ProfileReport {
+    pub fn to_toml(&self) -> Result<String, GraphicsError> {
+        toml::to_string_pretty(self).map_err(|e| {
+            OtherError::new(&format!("failed to serialize profile: {e}")).into()
+        })
+    }
+}
+
+/// Timestamp queries written within the render pass and the buffers used to
+/// read them back without stalling the frame.
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Set by map_async once the readback buffer can be read.
+    mapped: Arc<AtomicBool>,
+    /// A copy into the readback buffer was recorded this frame.
+    copied: bool,
+    /// The readback buffer is in use until its timings are read.
+    pending: bool,
+}
+
+/// Records how long each update and finalize takes on the CPU and, when
+/// supported, how long each draw group takes on the GPU.
+#[derive(Default)]
+pub struct Profiler {
+    pub enabled: bool,
+    /// Kept in the order stages first ran so logs read like the frame.
+    cpu: Vec<(&'static str, StageTimes)>,
+    gpu: Vec<(&'static str, StageTimes)>,
+    gpu_timer: Option<GpuTimer>,
+}
+
+impl Profiler {
+    /// Creates the profiler. GPU timings are only recorded if the device
+    /// was created with GPU_TIMING_FEATURES.
+    pub fn new(renderer: &GpuRenderer, enabled: bool) -> Self {
+        let device = renderer.device();
+        let gpu_timer = (enabled
+            && device.features().contains(GPU_TIMING_FEATURES))
+        .then(|| GpuTimer {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("profiler timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERY_COUNT,
+            }),
+            resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("profiler resolve buffer"),
+                size: QUERY_BUFFER_SIZE,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE
+                    | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("profiler readback buffer"),
+                size: QUERY_BUFFER_SIZE,
+                usage: wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: renderer.queue().get_timestamp_period(),
+            mapped: Arc::new(AtomicBool::new(false)),
+            copied: false,
+            pending: false,
+        });
+
+        if enabled {
+            info!(
+                "profiling enabled, GPU timings {}",
+                if gpu_timer.is_some() {
+                    "enabled"
+                } else {
+                    "are not supported by this adapter"
+                }
+            );
+        }
+
+        Self {
+            enabled,
+            cpu: Vec::new(),
+            gpu: Vec::new(),
+            gpu_timer,
+        }
+    }
+
+    /// Starts timing a CPU scope. Returns None when disabled.
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+
+    /// Ends a scope started with `start`.
+    pub fn record(&mut self, name: &'static str, start: Option<Instant>) {
+        if let Some(start) = start {
+            add_time(
+                &mut self.cpu,
+                name,
+                start.elapsed().as_secs_f64() * 1000.0,
+            );
+        }
+    }
+
+    /// Writes timestamp `index` within the pass if GPU timings are enabled.
+    pub fn write_timestamp(&self, pass: &mut wgpu::RenderPass, index: u32) {
+        if let Some(timer) = &self.gpu_timer {
+            pass.write_timestamp(&timer.query_set, index);
+        }
+    }
+
+    /// Resolves the pass's timestamps and copies them for reading back.
+    /// Skipped while an earlier frame's timings are still being read.
+    pub fn resolve_timestamps(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(timer) = &mut self.gpu_timer else {
+            return;
+        };
+
+        if timer.pending || timer.copied {
+            return;
+        }
+
+        encoder.resolve_query_set(
+            &timer.query_set,
+            0..QUERY_COUNT,
+            &timer.resolve,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &timer.resolve,
+            0,
+            &timer.readback,
+            0,
+            QUERY_BUFFER_SIZE,
+        );
+        timer.copied = true;
+    }
+
+    /// Starts mapping the timestamps copied this frame. Must be called after
+    /// the commands using the readback buffer were submitted.
+    pub fn map_timestamps(&mut self) {
+        let Some(timer) = &mut self.gpu_timer else {
+            return;
+        };
+
+        if !timer.copied {
+            return;
+        }
+
+        let mapped = timer.mapped.clone();
+
+        timer
+            .readback
+            .map_async(wgpu::MapMode::Read, .., move |result| {
+                mapped.store(result.is_ok(), Ordering::Release);
+            });
+        timer.copied = false;
+        timer.pending = true;
+    }
+
+    /// Reads the GPU timings back if the GPU has finished with them.
+    pub fn poll_timestamps(&mut self, renderer: &GpuRenderer) {
+        let Some(timer) = &mut self.gpu_timer else {
+            return;
+        };
+
+        if !timer.pending {
+            return;
+        }
+
+        let _ = renderer.device().poll(wgpu::PollType::Poll);
+
+        if !timer.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+
+        let ticks: Vec<u64> = {
+            let data = timer.readback.get_mapped_range(..);
+
+            data.chunks_exact(wgpu::QUERY_SIZE as usize)
+                .map(|bytes| {
+                    u64::from_le_bytes(bytes.try_into().unwrap_or_default())
+                })
+                .collect()
+        };
+
+        timer.readback.unmap();
+        timer.pending = false;
+
+        for (name, ms) in stage_times(&ticks, timer.period) {
+            add_time(&mut self.gpu, name, ms);
+        }
+    }
+
+    /// Logs the average of each stage since the last call.
+    pub fn log_recent(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        for (kind, stages) in [("cpu", &mut self.cpu), ("gpu", &mut self.gpu)] {
+            for (name, times) in stages.iter_mut() {
+                if times.recent_samples == 0 {
+                    continue;
+                }
+
+                info!(
+                    "profile {kind} {name}: {:.3} ms",
+                    times.recent_ms / times.recent_samples as f64
+                );
+                times.recent_samples = 0;
+                times.recent_ms = 0.0;
+            }
+        }
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let stages = [("cpu", &self.cpu), ("gpu", &self.gpu)]
+            .into_iter()
+            .flat_map(|(kind, stages)| {
+                stages.iter().map(move |(name, times)| StageReport {
+                    name: name.to_string(),
+                    kind: kind.to_string(),
+                    samples: times.samples,
+                    avg_ms: times.total_ms / times.samples.max(1) as f64,
+                    max_ms: times.max_ms,
+                })
+            })
+            .collect();
+
+        ProfileReport {
+            gpu_timings: self.gpu_timer.is_some(),
+            stages,
+        }
+    }
+
+    /// Writes the report as TOML so it can be compared between runs.
+    pub fn write_report(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), GraphicsError> {
+        let path = path.as_ref();
+
+        fs::write(path, self.report().to_toml()?)?;
+        info!("profile written to {}", path.display());
+        Ok(())
+    }
+}
+
+fn add_time(
+    stages: &mut Vec<(&'static str, StageTimes)>,
+    name: &'static str,
+    ms: f64,
+) {
+    match stages.iter_mut().find(|(stage, _)| *stage == name) {
+        Some((_, times)) => times.add(ms),
+        None => {
+            let mut times = StageTimes::default();
+
+            times.add(ms);
+            stages.push((name, times));
+        }
+    }
+}
+
+// Milliseconds each GPU stage took from the timestamps written around them.
+// `period` is the nanoseconds per tick.
+fn stage_times(
+    ticks: &[u64],
+    period: f32,
+) -> impl Iterator<Item = (&'static str, f64)> + '_ {
+    let period = f64::from(period);
+
+    GPU_STAGES
+        .into_iter()
+        .zip(ticks.windows(2))
+        .map(move |(name, pair)| {
+            // The counter can wrap around between two timestamps.
+            (name, pair[1].wrapping_sub(pair[0]) as f64 * period / 1e6)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_ticks_by_the_period() {
+        let ticks = [1_000, 3_000_000, 3_000_000, 3_400_000];
+        let times: Vec<_> = stage_times(&ticks, 2.5).collect();
+
+        assert_eq!(
+            times,
+            [("map lower", 7.4975), ("images", 0.0), ("animations", 1.0)]
+        );
+    }
+
+    #[test]
+    fn handles_the_counter_wrapping() {
+        let times: Vec<_> =
+            stage_times(&[u64::MAX - 999, 1_000], 1.0).collect();
+
+        assert_eq!(times, [("map lower", 0.002)]);
+    }
+
+    #[test]
+    fn only_times_stages_with_both_timestamps() {
+        assert_eq!(stage_times(&[], 1.0).count(), 0);
+        assert_eq!(stage_times(&[0], 1.0).count(), 0);
+        assert_eq!(stage_times(&[0; 20], 1.0).count(), GPU_STAGES.len());
+    }
+
+    #[test]
+    fn writes_the_report_as_toml() {
+        let mut profiler = Profiler::default();
+
+        add_time(&mut profiler.cpu, "sprite update", 1.0);
+        add_time(&mut profiler.cpu, "sprite update", 2.0);
+        add_time(&mut profiler.gpu, "images", 0.5);
+
+        assert_eq!(
+            profiler.report().to_toml().unwrap(),
+            r#"gpu_timings = false
+
+[[stages]]
+name = "sprite update"
+kind = "cpu"
+samples = 2
+avg_ms = 1.5
+max_ms = 2.0
+
+[[stages]]
+name = "images"
+kind = "gpu"
+samples = 1
+avg_ms = 0.5
+max_ms = 0.5
+"#
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{
+    Action, Axis,
+    bindings::{BindingInput, BindingsFile},
+};
+use graphics::input::{InputEvent, Key};
+use std::fmt::Write;
+use winit::keyboard::NamedKey;
+
+/// A single row of the rebinding screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RebindTarget {
+    Action(Action),
+    AxisPos(Axis),
+    AxisNeg(Axis),
+}
+
+/// What the rebinding screen did with an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebindOutcome {
+    /// The event was not used by the screen.
+    Ignored,
+    /// The event was used but nothing changed.
+    Handled,
+    /// A binding was changed and the InputHandler needs rebuilding.
+    Changed,
+    /// The screen was closed and the bindings should be saved.
+    Closed,
+}
+
+/// Lists every action and axis so the player can pick one and bind it to the
+/// next key or mouse button they press.
+#[derive(Clone, Debug, Default)]
+pub struct RebindScreen {
+    open: bool,
+    selected: usize,
+    /// Waiting on the next press to bind the selected row to.
+    capturing: bool,
+}
+
+impl RebindScreen {
+    pub fn open(&mut self) {
+        self.open = true;
+        self.capturing = false;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn handle_event(
+        &mut self,
+        event: &InputEvent,
+        bindings: &mut BindingsFile,
+    ) -> RebindOutcome {
+        if !self.open {
+            return RebindOutcome::Ignored;
+        }
+
+        let rows = targets(bindings);
+
+        if self.capturing {
+            let Some(input) = BindingInput::from_event(event) else {
+                return RebindOutcome::Handled;
+            };
+
+            self.capturing = false;
+
+            // Escape backs out without changing anything.
+            if input == BindingInput::Named(NamedKey::Escape) {
+                return RebindOutcome::Handled;
+            }
+
+            return match rows.get(self.selected) {
+                Some(target) => {
+                    bind(bindings, target, input);
+                    RebindOutcome::Changed
+                }
+                None => RebindOutcome::Handled,
+            };
+        }
+
+        let InputEvent::KeyInput {
+            key: Key::Named(named),
+            pressed: true,
+            ..
+        } = event
+        else {
+            return RebindOutcome::Handled;
+        };
+
+        match named {
+            NamedKey::ArrowUp => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            NamedKey::ArrowDown => {
+                self.selected =
+                    (self.selected + 1).min(rows.len().saturating_sub(1));
+            }
+            NamedKey::Enter => self.capturing = true,
+            NamedKey::Escape => {
+                self.open = false;
+                return RebindOutcome::Closed;
+            }
+            _ => {}
+        }
+
+        RebindOutcome::Handled
+    }
+
+    /// Text shown while the screen is open. Empty when it is closed.
+    pub fn describe(&self, bindings: &BindingsFile) -> String {
+        if !self.open {
+            return String::new();
+        }
+
+        let mut text = String::from(
+            "Rebind controls\nUp/Down select, Enter rebind, Esc save and close\n\n",
+        );
+
+        for (i, target) in targets(bindings).iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            let current = if i == self.selected && self.capturing {
+                String::from("press a key or mouse button...")
+            } else {
+                current_binding(bindings, target)
+            };
+            let name = match target {
+                RebindTarget::Action(action) => format!("{action:?}"),
+                RebindTarget::AxisPos(axis) => format!("{axis:?} +"),
+                RebindTarget::AxisNeg(axis) => format!("{axis:?} -"),
+            };
+
+            let _ = writeln!(text, "{marker} {name}: {current}");
+        }
+
+        text
+    }
+}
+
+fn targets(bindings: &BindingsFile) -> Vec<RebindTarget> {
+    let actions = bindings
+        .actions
+        .iter()
+        .map(|(action, _)| RebindTarget::Action(action.clone()));
+    let axes = bindings.axes.iter().flat_map(|(axis, _)| {
+        [
+            RebindTarget::AxisPos(axis.clone()),
+            RebindTarget::AxisNeg(axis.clone()),
+        ]
+    });
+
+    actions.chain(axes).collect()
+}
+
+fn bind(
+    bindings: &mut BindingsFile,
+    target: &RebindTarget,
+    input: BindingInput,
+) {
+    match target {
+        RebindTarget::Action(action) => {
+            if let Some((_, inputs)) =
+                bindings.actions.iter_mut().find(|(a, _)| a == action)
+            {
+                *inputs = vec![input];
+            }
+        }
+        RebindTarget::AxisPos(axis) | RebindTarget::AxisNeg(axis) => {
+            if let Some((_, binding)) =
+                bindings.axes.iter_mut().find(|(a, _)| a == axis)
+            {
+                if matches!(target, RebindTarget::AxisPos(_)) {
+                    binding.pos = input;
+                } else {
+                    binding.neg = input;
+                }
+            }
+        }
+    }
+}
+
+fn current_binding(bindings: &BindingsFile, target: &RebindTarget) -> String {
+    match target {
+        RebindTarget::Action(action) => bindings
+            .actions
+            .iter()
+            .find(|(a, _)| a == action)
+            .map(|(_, inputs)| {
+                inputs
+                    .iter()
+                    .map(|input| input.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_default(),
+        RebindTarget::AxisPos(axis) | RebindTarget::AxisNeg(axis) => bindings
+            .axes
+            .iter()
+            .find(|(a, _)| a == axis)
+            .map(|(_, binding)| {
+                if matches!(target, RebindTarget::AxisPos(_)) {
+                    binding.pos.to_string()
+                } else {
+                    binding.neg.to_string()
+                }
+            })
+            .unwrap_or_default(),
+    }
+}
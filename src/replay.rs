@@ -0,0 +1,432 @@
+use crate::{bindings::BindingsFile, gamepad::GamepadEvent};
+use graphics::{
+    input::{InputEvent, Key, MouseAxis, MouseButtonAction},
+    *,
+};
+use log::{error, info};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use winit::{
+    event::MouseButton,
+    keyboard::{KeyLocation, NamedKey},
+};
+
+/// Bumped whenever the recording format changes so old files are refused
+/// instead of being replayed wrong.
+pub const RECORDING_VERSION: u32 = 2;
+/// How often a recording with new events is written out while it runs so
+/// a crash does not lose it.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A key as the InputHandler reported it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordedKey {
+    Character(char),
+    Named(NamedKey),
+}
+
+/// An InputEvent as it came from the InputHandler before any handler saw
+/// it. Modifier events are left out as everything reads the modifiers from
+/// their KeyInput events instead.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Key {
+        key: RecordedKey,
+        location: KeyLocation,
+        pressed: bool,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// A single, double or triple click.
+    Click {
+        button: MouseButton,
+        clicks: u8,
+    },
+    MousePosition {
+        x: f32,
+        y: f32,
+    },
+    /// Notches the mouse wheel was scrolled.
+    Wheel {
+        amount: f32,
+        vertical: bool,
+    },
+    Focused(bool),
+    Gamepad(GamepadEvent),
+}
+
+impl RecordedEvent {
+    /// Returns None for events that carry nothing worth playing back.
+    pub fn from_event(event: &InputEvent) -> Option<Self> {
+        match event {
+            InputEvent::KeyInput {
+                key,
+                location,
+                pressed,
+            } => {
+                let key = match key {
+                    Key::Character(c) => RecordedKey::Character(*c),
+                    Key::Named(named) => RecordedKey::Named(*named),
+                    _ => return None,
+                };
+
+                Some(Self::Key {
+                    key,
+                    location: *location,
+                    pressed: *pressed,
+                })
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                Some(Self::MouseButton {
+                    button: *button,
+                    pressed: *pressed,
+                })
+            }
+            InputEvent::MouseButtonAction(action) => {
+                let (button, clicks) = match action {
+                    MouseButtonAction::Single(button) => (button, 1),
+                    MouseButtonAction::Double(button) => (button, 2),
+                    MouseButtonAction::Triple(button) => (button, 3),
+                    _ => return None,
+                };
+
+                Some(Self::Click {
+                    button: *button,
+                    clicks,
+                })
+            }
+            InputEvent::MousePosition { x, y } => {
+                Some(Self::MousePosition { x: *x, y: *y })
+            }
+            InputEvent::MouseWheel { amount, axis } => Some(Self::Wheel {
+                amount: *amount,
+                vertical: *axis == MouseAxis::Vertical,
+            }),
+            InputEvent::WindowFocused(focused) => Some(Self::Focused(*focused)),
+            _ => None,
+        }
+    }
+
+    /// The InputEvent this was recorded from. None for gamepad events as
+    /// those go straight to the Gamepad.
+    pub fn to_event(&self) -> Option<InputEvent> {
+        Some(match self {
+            Self::Key {
+                key,
+                location,
+                pressed,
+            } => InputEvent::KeyInput {
+                key: match key {
+                    RecordedKey::Character(c) => Key::Character(*c),
+                    RecordedKey::Named(named) => Key::Named(*named),
+                },
+                location: *location,
+                pressed: *pressed,
+            },
+            Self::MouseButton { button, pressed } => InputEvent::MouseButton {
+                button: *button,
+                pressed: *pressed,
+            },
+            Self::Click { button, clicks } => {
+                InputEvent::MouseButtonAction(match clicks {
+                    1 => MouseButtonAction::Single(*button),
+                    2 => MouseButtonAction::Double(*button),
+                    _ => MouseButtonAction::Triple(*button),
+                })
+            }
+            Self::MousePosition { x, y } => {
+                InputEvent::MousePosition { x: *x, y: *y }
+            }
+            Self::Wheel { amount, vertical } => InputEvent::MouseWheel {
+                amount: *amount,
+                axis: if *vertical {
+                    MouseAxis::Vertical
+                } else {
+                    MouseAxis::Horizontal
+                },
+            },
+            Self::Focused(focused) => InputEvent::WindowFocused(*focused),
+            Self::Gamepad(_) => return None,
+        })
+    }
+}
+
+/// Everything needed to play a run back exactly as it was recorded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub scene: String,
+    /// The game has to update at the same rate for the replay to match.
+    pub tick_rate: u32,
+    /// The bindings in use when the recording started. Any rebinding after
+    /// that is replayed through the rebinding screen like the rest.
+    pub bindings: BindingsFile,
+    /// Each event with the fixed update frame it was read before.
+    pub events: Vec<(u64, RecordedEvent)>,
+}
+
+impl Recording {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, GraphicsError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+        let recording: Self = ron::from_str(&data).map_err(|e| {
+            OtherError::new(&format!(
+                "failed to parse recording {}: {e}",
+                path.display()
+            ))
+        })?;
+
+        if recording.version != RECORDING_VERSION {
+            return Err(OtherError::new(&format!(
+                "recording {} is version {} but only version {} is supported",
+                path.display(),
+                recording.version,
+                RECORDING_VERSION
+            ))
+            .into());
+        }
+
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GraphicsError> {
+        let data = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| {
+                OtherError::new(&format!("failed to serialize recording: {e}"))
+            })?;
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+/// Collects every input event as it is read and writes them out every few
+/// seconds and on exit.
+pub struct Recorder {
+    recording: Recording,
+    path: PathBuf,
+    /// If events were recorded since the file was last written.
+    unsaved: bool,
+    last_save: Instant,
+}
+
+impl Recorder {
+    pub fn new(
+        path: impl Into<PathBuf>,
+        scene: &str,
+        tick_rate: u32,
+        bindings: &BindingsFile,
+    ) -> Self {
+        Self {
+            recording: Recording {
+                version: RECORDING_VERSION,
+                scene: scene.to_owned(),
+                tick_rate,
+                bindings: bindings.clone(),
+                events: Vec::new(),
+            },
+            path: path.into(),
+            unsaved: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, event: RecordedEvent) {
+        self.recording.events.push((frame, event));
+        self.unsaved = true;
+    }
+
+    /// Writes the recording out if it has new events and was last written
+    /// long enough ago. Called every frame.
+    pub fn flush(&mut self) {
+        if !self.unsaved || self.last_save.elapsed() < FLUSH_INTERVAL {
+            return;
+        }
+
+        if let Err(e) = self.recording.save(&self.path) {
+            error!("failed to save recording: {e}");
+        }
+
+        self.unsaved = false;
+        self.last_save = Instant::now();
+    }
+
+    pub fn save(&self) -> Result<(), GraphicsError> {
+        self.recording.save(&self.path)?;
+        info!(
+            "saved {} recorded events to {}",
+            self.recording.events.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Plays a Recording back one fixed update at a time. Its events are
+/// dispatched in place of the players while it runs.
+pub struct Replay {
+    recording: Recording,
+    /// Index of the next event to play.
+    next: usize,
+    /// The frame last passed to `advance`.
+    frame: u64,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            next: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// True once every event has been played and the frame of the last one
+    /// has been updated.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.events.len()
+            && self
+                .recording
+                .events
+                .last()
+                .is_none_or(|(frame, _)| self.frame > *frame)
+    }
+
+    /// Returns every event recorded before the update of `frame` that has
+    /// not been played yet.
+    pub fn advance(&mut self, frame: u64) -> Vec<RecordedEvent> {
+        let start = self.next;
+
+        self.frame = frame;
+
+        while let Some((event_frame, _)) = self.recording.events.get(self.next)
+            && *event_frame <= frame
+        {
+            self.next += 1;
+        }
+
+        self.recording.events[start..self.next]
+            .iter()
+            .map(|(_, event)| event.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char, pressed: bool) -> RecordedEvent {
+        RecordedEvent::Key {
+            key: RecordedKey::Character(c),
+            location: KeyLocation::Standard,
+            pressed,
+        }
+    }
+
+    // Each test gets its own file so they can run at the same time.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("demo-replay-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn replays_what_was_recorded() {
+        let path = temp_path("recording.ron");
+        let mut recorder = Recorder::new(
+            &path,
+            "scenes/demo.ron",
+            30,
+            &BindingsFile::default(),
+        );
+        let moved = RecordedEvent::MousePosition { x: 10.0, y: 20.0 };
+
+        recorder.record(0, key('w', true));
+        recorder.record(0, moved.clone());
+        recorder.record(2, key('w', false));
+        recorder.save().unwrap();
+
+        let recording = Recording::from_file(&path);
+
+        fs::remove_file(&path).unwrap();
+
+        let recording = recording.unwrap();
+
+        assert_eq!(recording.scene, "scenes/demo.ron");
+        assert_eq!(recording.tick_rate, 30);
+
+        let mut replay = Replay::new(recording);
+
+        assert!(!replay.is_finished());
+        assert_eq!(replay.advance(0), vec![key('w', true), moved]);
+        assert!(replay.advance(1).is_empty());
+        assert_eq!(replay.advance(2), vec![key('w', false)]);
+        // The last event's frame still has to be updated.
+        assert!(!replay.is_finished());
+        assert!(replay.advance(3).is_empty());
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn empty_recordings_are_finished_right_away() {
+        let recorder =
+            Recorder::new("unused.ron", "scene", 30, &BindingsFile::default());
+        let replay = Replay::new(recorder.recording.clone());
+
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let path = temp_path("old.ron");
+        let mut recording =
+            Recorder::new(&path, "scene", 30, &BindingsFile::default())
+                .recording;
+
+        recording.version = RECORDING_VERSION - 1;
+        recording.save(&path).unwrap();
+
+        let loaded = Recording::from_file(&path);
+
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn events_convert_back_to_what_was_read() {
+        let events = [
+            key('a', true),
+            RecordedEvent::MouseButton {
+                button: MouseButton::Right,
+                pressed: false,
+            },
+            RecordedEvent::Click {
+                button: MouseButton::Left,
+                clicks: 2,
+            },
+            RecordedEvent::MousePosition { x: 1.5, y: -3.0 },
+            RecordedEvent::Wheel {
+                amount: -1.0,
+                vertical: true,
+            },
+            RecordedEvent::Focused(false),
+        ];
+
+        for recorded in events {
+            let event = recorded.to_event().unwrap();
+
+            assert_eq!(RecordedEvent::from_event(&event), Some(recorded));
+        }
+    }
+}
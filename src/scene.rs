@@ -0,0 +1,813 @@
+use crate::{
+    collision::{AttributeGrid, TileAttributes},
+    follow::FollowSettings,
+    gamestate::State,
+    mapfile::{MapFile, Tilesheets},
+    profiler::Profiler,
+    stats::InstanceCounts,
+    tiled::TiledMap,
+    viewport::{Viewport, ViewportDesc},
+    world::{World, WorldDesc},
+};
+use camera::{
+    Projection,
+    controls::{Controls, FlatControls, FlatSettings},
+};
+use graphics::*;
+use serde::{Deserialize, Serialize};
+use std::{fs, iter, path::Path};
+use winit::dpi::PhysicalSize;
+
+/// The size of each tile within the map tilesheets.
+pub const TILE_SIZE: u32 = 20;
+/// The amount of tiles wide and high a single map is.
+pub const MAP_SIZE: u32 = 32;
+/// The amount of layers tiles can be set on within a map.
+pub const MAP_LAYERS: u32 = 9;
+/// How many maps the MapRenderer has room for.
+pub const MAP_COUNT: u32 = 81;
+
+/// The projection for the world camera. One world pixel is one window pixel
+/// before zooming so this has to be reset whenever the window resizes.
+pub fn world_projection(size: PhysicalSize<f32>) -> Projection {
+    Projection::Orthographic {
+        left: 0.0,
+        right: size.width,
+        bottom: 0.0,
+        top: size.height,
+        near: 1.0,
+        far: -100.0,
+    }
+}
+
+/// Describes everything the demo loads into its State at startup.
+/// This lets us change the layout of the scene without recompiling.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneDesc {
+    /// Where the world camera starts out at.
+    pub camera: CameraDesc,
+    /// Images uploaded into the sprite atlas. Sprites reference these by name.
+    pub images: Vec<ImageDesc>,
+    /// Tilesheets uploaded into the map atlas.
+    pub tilesheets: Vec<TilesheetDesc>,
+    pub sprites: Vec<SpriteDesc>,
+    /// Index within `sprites` of the sprite the player moves around.
+    pub player: Option<usize>,
+    pub animations: Vec<AnimationDesc>,
+    pub map: MapDesc,
+    /// Map chunks streamed in around the cameras as they move.
+    pub world: Option<WorldDesc>,
+    pub lights: LightsDesc,
+    pub meshes: Vec<MeshDesc>,
+    pub rects: Vec<RectDesc>,
+    /// Extra cameras drawn over parts of the window in order.
+    pub viewports: Vec<ViewportDesc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraDesc {
+    pub translation: Vec2,
+    pub zoom: f32,
+    /// How far the mouse wheel can zoom out and in.
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    pub follow: FollowSettings,
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self {
+            translation: Vec2::ZERO,
+            zoom: 1.0,
+            min_zoom: 0.5,
+            max_zoom: 4.0,
+            follow: FollowSettings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageDesc {
+    /// Name used to look the image back up within the atlas.
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TilesheetDesc {
+    pub name: String,
+    pub path: String,
+    #[serde(default = "default_tile_size")]
+    pub tile_size: u32,
+    /// Milliseconds each frame is shown for when the map editor paints an
+    /// animated tile from the sheet. 0 when its tiles are single frames.
+    #[serde(default)]
+    pub anim_time: u32,
+}
+
+/// Serializable version of CameraView since we only use a few of them.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum ViewDesc {
+    #[default]
+    MainView,
+    SubView1,
+    SubView2,
+}
+
+impl From<ViewDesc> for CameraView {
+    fn from(view: ViewDesc) -> Self {
+        match view {
+            ViewDesc::MainView => CameraView::MainView,
+            ViewDesc::SubView1 => CameraView::SubView1,
+            ViewDesc::SubView2 => CameraView::SubView2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpriteDesc {
+    /// Name of the image within `images` to use.
+    pub image: String,
+    pub pos: Vec3,
+    pub size: Vec2,
+    /// x, y, w, h within the image to render.
+    pub uv: Vec4,
+    #[serde(default = "default_color")]
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub render_layer: u32,
+    #[serde(default)]
+    pub camera_view: ViewDesc,
+    #[serde(default)]
+    pub rotation_angle: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationDesc {
+    pub image: String,
+    pub pos: Vec3,
+    pub size: Vec2,
+    pub uv: Vec4,
+    #[serde(default = "default_color")]
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub render_layer: u32,
+    /// Number of frames across and down within the image.
+    pub frames: Vec2,
+    /// Milliseconds each frame is shown for.
+    pub switch_time: u32,
+    #[serde(default = "default_true")]
+    pub animate: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapDesc {
+    pub pos: Vec2,
+    /// A .tmj or .tmx map made in Tiled to import before the fills and
+    /// tiles are applied.
+    pub tiled: Option<String>,
+    /// A map saved by the demo to load after the Tiled map. Either a .ron
+    /// file or the binary format.
+    pub file: Option<String>,
+    /// Tiles are applied in order so later entries overwrite earlier ones.
+    pub fills: Vec<TileFillDesc>,
+    pub tiles: Vec<TileDesc>,
+    /// Applied after the map file so they can add to its attributes.
+    pub attributes: Vec<AttributeFillDesc>,
+}
+
+/// Fills a square area of a single layer with the same tile.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileFillDesc {
+    pub layer: u32,
+    /// Inclusive start of the area.
+    pub min: UVec2,
+    /// Exclusive end of the area.
+    pub max: UVec2,
+    pub id: usize,
+    #[serde(default = "default_color")]
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub anim_time: u32,
+}
+
+/// Sets the attributes of a square area of the map.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AttributeFillDesc {
+    /// Inclusive start of the area.
+    pub min: UVec2,
+    /// Exclusive end of the area.
+    pub max: UVec2,
+    pub attributes: TileAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileDesc {
+    /// x, y and layer of the tile.
+    pub pos: UVec3,
+    pub id: usize,
+    #[serde(default = "default_color")]
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub anim_time: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightsDesc {
+    pub enable: bool,
+    pub render_layer: u32,
+    pub pos: Vec3,
+    pub world_color: Vec4,
+    pub area: Vec<AreaLightDesc>,
+    pub directional: Vec<DirectionalLightDesc>,
+}
+
+impl Default for LightsDesc {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            render_layer: 0,
+            pos: Vec3::new(0.0, 0.0, 1.0),
+            world_color: Vec4::new(0.0, 0.0, 0.0, 0.0),
+            area: Vec::new(),
+            directional: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AreaLightDesc {
+    pub pos: Vec2,
+    pub color: [u8; 4],
+    pub max_distance: f32,
+    #[serde(default)]
+    pub animate: bool,
+    #[serde(default)]
+    pub anim_speed: f32,
+    #[serde(default)]
+    pub dither: f32,
+    #[serde(default)]
+    pub camera_view: ViewDesc,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirectionalLightDesc {
+    pub pos: Vec2,
+    pub color: [u8; 4],
+    pub max_distance: f32,
+    pub max_width: f32,
+    #[serde(default)]
+    pub anim_speed: f32,
+    #[serde(default)]
+    pub angle: f32,
+    #[serde(default)]
+    pub dither: f32,
+    #[serde(default)]
+    pub fade_distance: f32,
+    #[serde(default)]
+    pub edge_fade_distance: f32,
+    #[serde(default)]
+    pub animate: bool,
+    #[serde(default)]
+    pub camera_view: ViewDesc,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeshDesc {
+    pub pos: Vec3,
+    #[serde(default)]
+    pub render_layer: u32,
+    pub shapes: Vec<ShapeDesc>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum DrawModeDesc {
+    Fill,
+    Stroke,
+}
+
+impl From<DrawModeDesc> for DrawMode {
+    fn from(mode: DrawModeDesc) -> Self {
+        match mode {
+            DrawModeDesc::Fill => DrawMode::Fill(FillOptions::DEFAULT),
+            DrawModeDesc::Stroke => DrawMode::Stroke(StrokeOptions::DEFAULT),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ShapeDesc {
+    Circle {
+        mode: DrawModeDesc,
+        center: Vec2,
+        radius: f32,
+        tolerance: f32,
+        z: f32,
+        color: [u8; 4],
+    },
+    Polyline {
+        mode: DrawModeDesc,
+        points: Vec<Vec2>,
+        z: f32,
+        color: [u8; 4],
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RectDesc {
+    pub pos: Vec3,
+    pub size: Vec2,
+    #[serde(default = "default_color")]
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub render_layer: u32,
+    #[serde(default)]
+    pub radius: f32,
+    #[serde(default)]
+    pub border_color: [u8; 4],
+    #[serde(default)]
+    pub border_width: f32,
+    #[serde(default)]
+    pub camera_view: ViewDesc,
+}
+
+fn default_tile_size() -> u32 {
+    TILE_SIZE
+}
+
+fn default_color() -> [u8; 4] {
+    [255, 255, 255, 255]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub fn to_color(color: [u8; 4]) -> Color {
+    Color::rgba(color[0], color[1], color[2], color[3])
+}
+
+impl SceneDesc {
+    /// Loads a RON scene file from disk.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, GraphicsError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+
+        Self::from_ron(&data).map_err(|e| {
+            OtherError::new(&format!(
+                "failed to load scene {}: {e}",
+                path.display()
+            ))
+            .into()
+        })
+    }
+
+    /// Parses a RON scene and checks it can be built.
+    pub fn from_ron(data: &str) -> Result<Self, String> {
+        let scene: Self = ron::from_str(data).map_err(|e| e.to_string())?;
+
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Checks the map tiles fit within the map since Map::set_tile would
+    /// place them on the wrong tile or panic.
+    pub fn validate(&self) -> Result<(), String> {
+        for fill in &self.map.fills {
+            if fill.layer >= MAP_LAYERS {
+                return Err(format!(
+                    "map fill of tile {} is on layer {} but the map only has {MAP_LAYERS}",
+                    fill.id, fill.layer
+                ));
+            }
+        }
+
+        for tile in &self.map.tiles {
+            if tile.pos.x >= MAP_SIZE
+                || tile.pos.y >= MAP_SIZE
+                || tile.pos.z >= MAP_LAYERS
+            {
+                return Err(format!(
+                    "map tile at {} is outside of the {MAP_SIZE}x{MAP_SIZE} tiles and {MAP_LAYERS} layers",
+                    tile.pos
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads all the textures and builds the render types declared within
+    /// the scene into a new State.
+    pub fn build(
+        &self,
+        renderer: &mut GpuRenderer,
+    ) -> Result<State<FlatControls>, GraphicsError> {
+        self.validate()
+            .map_err(|e| OtherError::new(&format!("invalid scene: {e}")))?;
+
+        // We generate Texture atlases to use with out types.
+        let mut atlases: Vec<AtlasSet> = iter::from_fn(|| {
+            Some(AtlasSet::new(
+                renderer,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                true,
+                2048,
+            ))
+        })
+        .take(4)
+        .collect();
+
+        // we generate the Text atlas seperatly since it contains a special texture that only has the red color to it.
+        // and another for emojicons.
+        let text_atlas = TextAtlas::new(renderer, 512)?;
+
+        // This is how we load a image into a atlas/Texture. It returns the location of the image
+        // within the texture. The name is what we use to look it back up later.
+        for image in &self.images {
+            Texture::from_file(&image.path)?
+                .upload(image.name.as_str(), &mut atlases[0], renderer)
+                .ok_or_else(|| {
+                    OtherError::new(&format!(
+                        "failed to upload image {}",
+                        image.name
+                    ))
+                })?;
+        }
+
+        let mut tilesheets = Tilesheets::default();
+
+        for sheet in &self.tilesheets {
+            let tilesheet = Texture::from_file(&sheet.path)?
+                .new_tilesheet(
+                    sheet.name.as_str(),
+                    &mut atlases[1],
+                    renderer,
+                    sheet.tile_size,
+                )
+                .ok_or_else(|| {
+                    OtherError::new(&format!(
+                        "failed to upload tilesheet {}",
+                        sheet.name
+                    ))
+                })?;
+
+            tilesheets.insert(
+                &sheet.name,
+                tilesheet.tiles.iter().map(|tile| tile.tex).collect(),
+            )?;
+        }
+
+        let lookup = |atlas: &AtlasSet, name: &str| {
+            atlas.lookup(&name).ok_or_else(|| {
+                OtherError::new(&format!("scene image {name} was not loaded"))
+            })
+        };
+
+        let mut sprites = Vec::with_capacity(self.sprites.len());
+
+        for desc in &self.sprites {
+            let mut sprite = Image::new(
+                Some(lookup(&atlases[0], &desc.image)?),
+                renderer,
+                desc.pos,
+                desc.size,
+                desc.uv,
+                desc.render_layer,
+            );
+
+            sprite.color = to_color(desc.color);
+            sprite.camera_view = desc.camera_view.into();
+            sprite.rotation_angle = desc.rotation_angle;
+            sprites.push(sprite);
+        }
+
+        let mut animations = Vec::with_capacity(self.animations.len());
+
+        for desc in &self.animations {
+            let mut animation = AnimImage::new(
+                Some(lookup(&atlases[0], &desc.image)?),
+                renderer,
+                desc.pos,
+                desc.size,
+                desc.uv,
+                desc.render_layer,
+            );
+
+            animation.color = to_color(desc.color);
+            animation.frames = desc.frames;
+            animation.switch_time = desc.switch_time;
+            animation.animate = desc.animate;
+            animations.push(animation);
+        }
+
+        // We establish the different renderers here to load their data up to use them.
+        let text_renderer = TextRenderer::new(renderer)?;
+        let sprite_renderer = ImageRenderer::new(renderer)?;
+        let animation_renderer = AnimImageRenderer::new(renderer)?;
+        let mut map_renderer = MapRenderer::new(renderer, MAP_COUNT)?;
+        let mesh_renderer = Mesh2DRenderer::new(renderer)?;
+        let light_renderer = LightRenderer::new(renderer)?;
+        let ui_renderer = RectRenderer::new(renderer)?;
+
+        // get the screen size.
+        let size = renderer.size();
+        let mat = Mat4::from_translation(Vec3::ZERO);
+
+        // setup our system which includes Camera and projection as well as our controls.
+        // for the camera.
+        let mut system = System::new(
+            renderer,
+            world_projection(size),
+            FlatControls::new(FlatSettings {
+                zoom: self.camera.zoom,
+            }),
+            [size.width, size.height],
+        );
+
+        system.controls_mut().inputs_mut().translation.x =
+            self.camera.translation.x;
+        system.controls_mut().inputs_mut().translation.y =
+            self.camera.translation.y;
+        let sub_views = vec![
+            (CameraView::SubView1, mat, 1.0),
+            (CameraView::SubView2, mat, 1.0),
+        ];
+
+        for (view, mat, scale) in &sub_views {
+            system.set_view(*view, *mat, *scale);
+        }
+
+        let viewports = self
+            .viewports
+            .iter()
+            .map(|desc| Viewport::new(renderer, desc, &sub_views))
+            .collect();
+
+        let mut map = Map::new(
+            renderer,
+            &mut map_renderer,
+            TILE_SIZE,
+            self.map.pos,
+            MapZLayers::default(),
+        )
+        .ok_or_else(|| OtherError::new("failed to create map"))?;
+
+        if let Some(path) = &self.map.tiled {
+            TiledMap::from_file(path)?.apply(
+                &mut map,
+                &mut atlases[1],
+                &mut tilesheets,
+                renderer,
+            )?;
+        }
+
+        for fill in &self.map.fills {
+            (fill.min.x..fill.max.x.min(MAP_SIZE)).for_each(|x| {
+                (fill.min.y..fill.max.y.min(MAP_SIZE)).for_each(|y| {
+                    map.set_tile(
+                        UVec3::new(x, y, fill.layer),
+                        TileData {
+                            id: fill.id,
+                            color: to_color(fill.color),
+                            anim_time: fill.anim_time,
+                        },
+                    )
+                });
+            });
+        }
+
+        for desc in &self.map.tiles {
+            map.set_tile(
+                desc.pos,
+                TileData {
+                    id: desc.id,
+                    color: to_color(desc.color),
+                    anim_time: desc.anim_time,
+                },
+            );
+        }
+
+        let mut attributes = AttributeGrid::default();
+
+        // A saved map replaces the tiles set above since it was saved with
+        // them and any edits made since.
+        if let Some(path) = &self.map.file {
+            let file = MapFile::load(path)?;
+
+            file.apply(&mut map, &tilesheets)?;
+            attributes = file.attribute_grid();
+        }
+
+        for fill in &self.map.attributes {
+            (fill.min.x..fill.max.x.min(MAP_SIZE)).for_each(|x| {
+                (fill.min.y..fill.max.y.min(MAP_SIZE)).for_each(|y| {
+                    attributes.set(UVec2::new(x, y), fill.attributes)
+                });
+            });
+        }
+
+        let collision_overlay =
+            attributes.debug_overlay(renderer, self.map.pos, 1)?;
+
+        let mut mesh = Vec::with_capacity(self.meshes.len());
+
+        for desc in &self.meshes {
+            // Start the process of building a shape.
+            let mut builder = Mesh2DBuilder::default();
+
+            for shape in &desc.shapes {
+                match shape {
+                    ShapeDesc::Circle {
+                        mode,
+                        center,
+                        radius,
+                        tolerance,
+                        z,
+                        color,
+                    } => {
+                        builder.circle(
+                            (*mode).into(),
+                            *center,
+                            *radius,
+                            *tolerance,
+                            *z,
+                            to_color(*color),
+                        )?;
+                    }
+                    ShapeDesc::Polyline {
+                        mode,
+                        points,
+                        z,
+                        color,
+                    } => {
+                        builder.polyline(
+                            (*mode).into(),
+                            points,
+                            *z,
+                            to_color(*color),
+                        )?;
+                    }
+                }
+            }
+
+            let mut shape = Mesh2D::new(renderer, desc.pos, desc.render_layer);
+            shape.from_builder(builder.finalize());
+            mesh.push(shape);
+        }
+
+        let mut lights = Lights::new(
+            renderer,
+            self.lights.render_layer,
+            self.lights.pos,
+            Vec2::new(size.width, size.height),
+        );
+
+        lights.world_color = self.lights.world_color;
+        lights.enable_lights = self.lights.enable;
+
+        for desc in &self.lights.area {
+            lights.insert_area_light(AreaLight {
+                pos: desc.pos,
+                color: to_color(desc.color),
+                max_distance: desc.max_distance,
+                animate: desc.animate,
+                anim_speed: desc.anim_speed,
+                dither: desc.dither,
+                camera_view: desc.camera_view.into(),
+                visible: true,
+            });
+        }
+
+        for desc in &self.lights.directional {
+            lights.insert_directional_light(DirectionalLight {
+                pos: desc.pos,
+                color: to_color(desc.color),
+                max_distance: desc.max_distance,
+                max_width: desc.max_width,
+                anim_speed: desc.anim_speed,
+                angle: desc.angle,
+                dither: desc.dither,
+                fade_distance: desc.fade_distance,
+                edge_fade_distance: desc.edge_fade_distance,
+                animate: desc.animate,
+                camera_view: desc.camera_view.into(),
+                visible: true,
+            });
+        }
+
+        let mut rects = Vec::with_capacity(self.rects.len());
+
+        for desc in &self.rects {
+            let mut rect = Rect::new(
+                renderer,
+                desc.pos,
+                desc.size,
+                to_color(desc.color),
+                desc.render_layer,
+            );
+
+            rect.set_radius(desc.radius)
+                .set_border_color(to_color(desc.border_color))
+                .set_border_width(desc.border_width)
+                .set_camera_view(desc.camera_view.into());
+            rects.push(rect);
+        }
+
+        // add everything into our convience type for quicker access and passing.
+        Ok(State {
+            system,
+            sub_views,
+            viewports,
+            sprites,
+            animations,
+            image_atlas: atlases.remove(0),
+            map,
+            tilesheets,
+            attributes,
+            collision_overlay,
+            show_collision: false,
+            world: self.world.as_ref().map(World::new),
+            map_renderer,
+            map_atlas: atlases.remove(0),
+            sprite_renderer,
+            text_atlas,
+            text_renderer,
+            mesh,
+            mesh_atlas: atlases.remove(0),
+            mesh_renderer,
+            lights,
+            light_renderer,
+            ui_atlas: atlases.remove(0),
+            ui_renderer,
+            rects,
+            texts: Vec::new(),
+            text_fields: Vec::new(),
+            stats: None,
+            counts: InstanceCounts::default(),
+            editor: None,
+            player: self.player.filter(|&index| index < self.sprites.len()),
+            profiler: Profiler::default(),
+            animation_renderer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"(
+        images: [(name: "player", path: "images/player.png")],
+        sprites: [(
+            image: "player",
+            pos: (10.0, 20.0, 5.0),
+            size: (16.0, 16.0),
+            uv: (0.0, 0.0, 16.0, 16.0),
+        )],
+        player: Some(0),
+        map: (
+            fills: [(layer: 0, min: (0, 0), max: (40, 40), id: 1)],
+            tiles: [(pos: (31, 31, 8), id: 2)],
+        ),
+    )"#;
+
+    #[test]
+    fn parses_a_scene() {
+        let scene = SceneDesc::from_ron(SCENE).unwrap();
+
+        assert_eq!(scene.sprites.len(), 1);
+        assert_eq!(scene.sprites[0].color, [255, 255, 255, 255]);
+        assert_eq!(scene.player, Some(0));
+        assert_eq!(scene.camera.zoom, 1.0);
+        assert_eq!(scene.map.fills[0].max, UVec2::new(40, 40));
+        assert_eq!(scene.map.tiles[0].pos, UVec3::new(31, 31, 8));
+    }
+
+    #[test]
+    fn parses_the_demo_scene() {
+        SceneDesc::from_file("scenes/demo.ron").unwrap();
+    }
+
+    #[test]
+    fn rejects_fills_past_the_last_layer() {
+        let scene = SCENE.replace("layer: 0", "layer: 9");
+
+        assert!(SceneDesc::from_ron(&scene).is_err());
+    }
+
+    #[test]
+    fn rejects_tiles_outside_of_the_map() {
+        for pos in ["(32, 31, 8)", "(31, 32, 8)", "(31, 31, 9)"] {
+            let scene = SCENE.replace("(31, 31, 8)", pos);
+
+            assert!(SceneDesc::from_ron(&scene).is_err(), "{pos}");
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use time::FrameTime;
+
+/// Handle to a scheduled timer so it can be cancelled later.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct TimerId(u64);
+
+type TimerCallback<T> = Box<dyn FnMut(&mut T, &mut TimerContext<T>)>;
+
+struct Timer<T> {
+    id: TimerId,
+    /// Clock time in seconds the timer fires at next.
+    due: f32,
+    /// None for timers that only fire once.
+    interval: Option<f32>,
+    callback: TimerCallback<T>,
+}
+
+/// Passed to each callback along with the target so a timer can cancel
+/// itself or others and schedule follow up work. Timers added from a
+/// callback first run on a later update even if they are already due.
+pub struct TimerContext<T> {
+    /// The timer whose callback is running.
+    current: TimerId,
+    next_id: u64,
+    now: f32,
+    added: Vec<Timer<T>>,
+    cancelled: Vec<TimerId>,
+}
+
+impl<T> TimerContext<T> {
+    /// The timer whose callback is running.
+    pub fn id(&self) -> TimerId {
+        self.current
+    }
+
+    /// Current clock time in seconds.
+    pub fn now(&self) -> f32 {
+        self.now
+    }
+
+    /// Stops the running timer from firing again.
+    pub fn stop(&mut self) {
+        self.cancelled.push(self.current);
+    }
+
+    /// Stops another timer from firing again, even if it is also due now.
+    pub fn cancel(&mut self, id: TimerId) {
+        self.cancelled.push(id);
+    }
+
+    /// Calls `callback` once after `delay` seconds.
+    pub fn once(
+        &mut self,
+        delay: f32,
+        callback: impl FnMut(&mut T, &mut TimerContext<T>) + 'static,
+    ) -> TimerId {
+        self.insert(delay, None, Box::new(callback))
+    }
+
+    /// Calls `callback` after `delay` seconds and then every `interval`
+    /// seconds after that until cancelled.
+    pub fn repeat(
+        &mut self,
+        delay: f32,
+        interval: f32,
+        callback: impl FnMut(&mut T, &mut TimerContext<T>) + 'static,
+    ) -> TimerId {
+        self.insert(delay, Some(interval.max(0.0)), Box::new(callback))
+    }
+
+    fn insert(
+        &mut self,
+        delay: f32,
+        interval: Option<f32>,
+        callback: TimerCallback<T>,
+    ) -> TimerId {
+        let id = TimerId(self.next_id);
+
+        self.next_id += 1;
+        self.added.push(Timer {
+            id,
+            due: self.now + delay.max(0.0),
+            interval,
+            callback,
+        });
+
+        id
+    }
+}
+
+/// Runs callbacks against `T` once their timer is due. Use this for periodic
+/// or delayed work instead of keeping a time field around for each one.
+pub struct Scheduler<T> {
+    timers: Vec<Timer<T>>,
+    /// Holds the clock and collects changes made from within callbacks.
+    context: TimerContext<T>,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Scheduler<T> {
+    pub fn new() -> Self {
+        Self {
+            timers: Vec::new(),
+            context: TimerContext {
+                current: TimerId(0),
+                next_id: 0,
+                now: 0.0,
+                added: Vec::new(),
+                cancelled: Vec::new(),
+            },
+        }
+    }
+
+    /// Calls `callback` once after `delay` seconds.
+    pub fn once(
+        &mut self,
+        delay: f32,
+        callback: impl FnMut(&mut T, &mut TimerContext<T>) + 'static,
+    ) -> TimerId {
+        let id = self.context.once(delay, callback);
+
+        self.timers.append(&mut self.context.added);
+        id
+    }
+
+    /// Calls `callback` after `delay` seconds and then every `interval`
+    /// seconds after that until cancelled.
+    pub fn repeat(
+        &mut self,
+        delay: f32,
+        interval: f32,
+        callback: impl FnMut(&mut T, &mut TimerContext<T>) + 'static,
+    ) -> TimerId {
+        let id = self.context.repeat(delay, interval, callback);
+
+        self.timers.append(&mut self.context.added);
+        id
+    }
+
+    /// Stops the timer from firing again. Returns false if it already
+    /// finished or was cancelled.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let len = self.timers.len();
+
+        self.timers.retain(|timer| timer.id != id);
+        self.timers.len() != len
+    }
+
+    /// If the timer has not yet finished or been cancelled.
+    pub fn is_pending(&self, id: TimerId) -> bool {
+        self.timers.iter().any(|timer| timer.id == id)
+    }
+
+    /// Cancels every timer.
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+
+    /// Current clock time in seconds.
+    pub fn now(&self) -> f32 {
+        self.context.now
+    }
+
+    /// Moves the clock to the FrameTime's seconds and runs any timers that
+    /// are now due.
+    pub fn update(&mut self, frame_time: &FrameTime, target: &mut T) {
+        self.context.now = frame_time.seconds();
+        self.run_due(target);
+    }
+
+    /// Moves the clock forward by `dt` seconds and runs any timers that are
+    /// now due. Used by the fixed rate game logic so its timers are
+    /// repeatable no matter how long a frame took.
+    pub fn advance(&mut self, dt: f32, target: &mut T) {
+        self.context.now += dt;
+        self.run_due(target);
+    }
+
+    fn run_due(&mut self, target: &mut T) {
+        let context = &mut self.context;
+        let now = context.now;
+
+        // Anything a callback adds or cancels is collected within the
+        // context and applied once every due timer has run.
+        self.timers.retain_mut(|timer| {
+            if context.cancelled.contains(&timer.id) {
+                return false;
+            }
+
+            if timer.due > now {
+                return true;
+            }
+
+            context.current = timer.id;
+            (timer.callback)(target, context);
+
+            if context.cancelled.contains(&timer.id) {
+                return false;
+            }
+
+            match timer.interval {
+                Some(interval) => {
+                    timer.due += interval;
+
+                    // Skip any we missed rather than firing them all at once.
+                    if timer.due <= now {
+                        timer.due = now + interval;
+                    }
+
+                    true
+                }
+                None => false,
+            }
+        });
+
+        let cancelled = std::mem::take(&mut context.cancelled);
+
+        self.timers.append(&mut context.added);
+        self.timers.retain(|timer| !cancelled.contains(&timer.id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Vec<&'static str>;
+
+    fn push(
+        name: &'static str,
+    ) -> impl FnMut(&mut Log, &mut TimerContext<Log>) {
+        move |log, _| log.push(name)
+    }
+
+    #[test]
+    fn once_fires_after_its_delay() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+        let id = timers.once(1.0, push("once"));
+
+        timers.advance(0.5, &mut log);
+        assert!(log.is_empty());
+        assert!(timers.is_pending(id));
+
+        timers.advance(0.5, &mut log);
+        timers.advance(5.0, &mut log);
+        assert_eq!(log, ["once"]);
+        assert!(!timers.is_pending(id));
+    }
+
+    #[test]
+    fn repeat_fires_every_interval() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+
+        timers.repeat(0.0, 1.0, push("tick"));
+
+        for _ in 0..4 {
+            timers.advance(0.5, &mut log);
+        }
+
+        // Fires at 0.5 since it had no delay, then at 1.0 and 2.0.
+        assert_eq!(log, ["tick", "tick", "tick"]);
+    }
+
+    #[test]
+    fn cancel_stops_a_timer() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+        let id = timers.repeat(1.0, 1.0, push("tick"));
+
+        timers.advance(1.0, &mut log);
+        assert!(timers.cancel(id));
+        assert!(!timers.cancel(id));
+
+        timers.advance(5.0, &mut log);
+        assert_eq!(log, ["tick"]);
+    }
+
+    #[test]
+    fn advance_skips_missed_repeats() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+
+        timers.repeat(1.0, 1.0, push("tick"));
+
+        // A long stall only fires once and the next is an interval later.
+        timers.advance(10.5, &mut log);
+        assert_eq!(log.len(), 1);
+
+        timers.advance(0.5, &mut log);
+        assert_eq!(log.len(), 1);
+
+        timers.advance(0.5, &mut log);
+        assert_eq!(log.len(), 2);
+    }
+
+    #[test]
+    fn callbacks_can_stop_their_own_timer() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+        let id = timers.repeat(0.0, 1.0, |log: &mut Log, timer| {
+            log.push("tick");
+
+            if log.len() == 3 {
+                timer.stop();
+            }
+        });
+
+        for _ in 0..10 {
+            timers.advance(1.0, &mut log);
+        }
+
+        assert_eq!(log.len(), 3);
+        assert!(!timers.is_pending(id));
+    }
+
+    #[test]
+    fn callbacks_can_schedule_follow_ups() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+
+        timers.once(1.0, |log: &mut Log, timer| {
+            log.push("first");
+            timer.once(0.0, push("second"));
+        });
+
+        timers.advance(1.0, &mut log);
+        assert_eq!(log, ["first"]);
+
+        timers.advance(0.1, &mut log);
+        assert_eq!(log, ["first", "second"]);
+    }
+
+    #[test]
+    fn callbacks_can_cancel_other_timers() {
+        let mut timers = Scheduler::new();
+        let mut log = Log::new();
+        let later = TimerId(1);
+
+        timers.once(1.0, move |log: &mut Log, timer| {
+            log.push("first");
+            timer.cancel(later);
+        });
+        assert_eq!(timers.once(1.0, push("later")), later);
+
+        timers.advance(2.0, &mut log);
+        assert_eq!(log, ["first"]);
+        assert!(!timers.is_pending(later));
+    }
+}
@@ -0,0 +1,281 @@
+use graphics::{
+    cosmic_text::{Align, Attrs, Metrics},
+    *,
+};
+use std::{collections::VecDeque, fmt::Write, time::Instant};
+
+/// How many frame times are kept for the graph and min/avg/max.
+pub const FRAME_SAMPLES: usize = 120;
+/// Where the overlay is drawn on the screen.
+const OVERLAY_POS: Vec3 = Vec3::new(10.0, 80.0, 1.0);
+const GRAPH_POS: Vec3 = Vec3::new(10.0, 240.0, 1.0);
+const GRAPH_SIZE: Vec2 = Vec2::new(240.0, 60.0);
+/// Frame time the graph's top edge represents.
+const GRAPH_MAX_MS: f32 = 50.0;
+/// Frame time of 60 FPS, drawn as a line within the graph.
+const TARGET_MS: f32 = 1000.0 / 60.0;
+
+/// Rolling frame times used to spot stutters.
+pub struct FrameStats {
+    /// Frame times in milliseconds, oldest first.
+    samples: VecDeque<f32>,
+    last: Instant,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(FRAME_SAMPLES),
+            last: Instant::now(),
+        }
+    }
+}
+
+impl FrameStats {
+    /// Records the time since the last call as a frame.
+    pub fn record(&mut self) {
+        let now = Instant::now();
+
+        self.push((now - self.last).as_secs_f32() * 1000.0);
+        self.last = now;
+    }
+
+    /// Adds a frame time in milliseconds, dropping the oldest once there
+    /// are FRAME_SAMPLES of them.
+    pub fn push(&mut self, ms: f32) {
+        if self.samples.len() == FRAME_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(ms);
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn min_ms(&self) -> f32 {
+        self.samples().reduce(f32::min).unwrap_or_default()
+    }
+
+    pub fn max_ms(&self) -> f32 {
+        self.samples().reduce(f32::max).unwrap_or_default()
+    }
+
+    pub fn avg_ms(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        self.samples().sum::<f32>() / self.samples.len() as f32
+    }
+
+    pub fn fps(&self) -> f32 {
+        let avg = self.avg_ms();
+
+        if avg > 0.0 { 1000.0 / avg } else { 0.0 }
+    }
+}
+
+/// How many of each render type are pushed into their renderers per frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InstanceCounts {
+    pub sprites: usize,
+    pub animations: usize,
+    pub texts: usize,
+    pub maps: usize,
+    pub area_lights: usize,
+    pub directional_lights: usize,
+    pub meshes: usize,
+    pub rects: usize,
+}
+
+/// How much of an atlas is in use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AtlasUsage {
+    pub name: &'static str,
+    pub layers: usize,
+    pub allocations: usize,
+}
+
+/// Debug overlay showing frame times, instance counts and atlas usage.
+pub struct StatsOverlay {
+    pub visible: bool,
+    pub frames: FrameStats,
+    pub text: Text,
+    /// Rolling graph of the frame times.
+    pub graph: Mesh2D,
+}
+
+impl StatsOverlay {
+    pub fn new(
+        renderer: &mut GpuRenderer,
+        text_renderer: &mut TextRenderer,
+    ) -> Self {
+        let mut text = Text::new_with_buffer(
+            renderer,
+            text_renderer,
+            Some(Metrics::new(14.0, 16.0)),
+            OVERLAY_POS,
+            Vec2::new(400.0, 150.0),
+            1.0,
+            1,
+        );
+
+        text.set_buffer_size(Some(400.0), Some(150.0))
+            .set_default_color(Color::rgba(255, 255, 0, 255));
+        // Stays put on screen instead of moving with the world camera.
+        text.camera_view = CameraView::SubView1;
+
+        let mut graph = Mesh2D::new(renderer, GRAPH_POS, 1);
+
+        graph.camera_view = CameraView::SubView1;
+
+        Self {
+            visible: false,
+            frames: FrameStats::default(),
+            text,
+            graph,
+        }
+    }
+
+    /// Rebuilds the text and graph from the latest stats. This reshapes the
+    /// text so it should not be called every frame.
+    pub fn refresh(
+        &mut self,
+        counts: &InstanceCounts,
+        atlases: &[AtlasUsage],
+    ) -> Result<(), GraphicsError> {
+        let frames = &self.frames;
+        let mut info = format!(
+            "FPS: {:.0}  frame ms min {:.2} avg {:.2} max {:.2}\n",
+            frames.fps(),
+            frames.min_ms(),
+            frames.avg_ms(),
+            frames.max_ms()
+        );
+
+        let _ = writeln!(
+            info,
+            "sprites {}  animations {}  texts {}  maps {}",
+            counts.sprites, counts.animations, counts.texts, counts.maps
+        );
+        let _ = writeln!(
+            info,
+            "area lights {}  dir lights {}  meshes {}  rects {}",
+            counts.area_lights,
+            counts.directional_lights,
+            counts.meshes,
+            counts.rects
+        );
+
+        for atlas in atlases {
+            let _ = writeln!(
+                info,
+                "{} atlas: {} layers, {} allocations",
+                atlas.name, atlas.layers, atlas.allocations
+            );
+        }
+
+        self.text.set_text(
+            &info,
+            &Attrs::new(),
+            Shaping::Advanced,
+            Some(Align::Left),
+        );
+
+        let mut builder = Mesh2DBuilder::default();
+        let to_y = |ms: f32| (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_SIZE.y;
+        let step = GRAPH_SIZE.x / (FRAME_SAMPLES - 1) as f32;
+
+        builder.polyline(
+            DrawMode::Stroke(StrokeOptions::DEFAULT),
+            &[
+                Vec2::new(0.0, GRAPH_SIZE.y),
+                Vec2::ZERO,
+                Vec2::new(GRAPH_SIZE.x, 0.0),
+            ],
+            1.0,
+            Color::rgba(255, 255, 255, 120),
+        )?;
+        builder.polyline(
+            DrawMode::Stroke(StrokeOptions::DEFAULT),
+            &[
+                Vec2::new(0.0, to_y(TARGET_MS)),
+                Vec2::new(GRAPH_SIZE.x, to_y(TARGET_MS)),
+            ],
+            1.0,
+            Color::rgba(0, 255, 0, 160),
+        )?;
+
+        let points: Vec<Vec2> = frames
+            .samples()
+            .enumerate()
+            .map(|(i, ms)| Vec2::new(i as f32 * step, to_y(ms)))
+            .collect();
+
+        // A line needs at least two points.
+        if points.len() > 1 {
+            builder.polyline(
+                DrawMode::Stroke(StrokeOptions::DEFAULT),
+                &points,
+                1.0,
+                Color::rgba(255, 255, 0, 255),
+            )?;
+        }
+
+        self.graph.from_builder(builder.finalize());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(samples: &[f32]) -> FrameStats {
+        let mut stats = FrameStats::default();
+
+        samples.iter().for_each(|&ms| stats.push(ms));
+        stats
+    }
+
+    #[test]
+    fn empty_stats_are_zero() {
+        let stats = FrameStats::default();
+
+        assert_eq!(stats.min_ms(), 0.0);
+        assert_eq!(stats.max_ms(), 0.0);
+        assert_eq!(stats.avg_ms(), 0.0);
+        assert_eq!(stats.fps(), 0.0);
+    }
+
+    #[test]
+    fn averages_the_samples() {
+        let stats = stats(&[10.0, 20.0, 30.0, 20.0]);
+
+        assert_eq!(stats.min_ms(), 10.0);
+        assert_eq!(stats.max_ms(), 30.0);
+        assert_eq!(stats.avg_ms(), 20.0);
+        assert_eq!(stats.fps(), 50.0);
+    }
+
+    #[test]
+    fn keeps_only_the_latest_samples() {
+        let mut stats = stats(&[100.0]);
+
+        for _ in 0..FRAME_SAMPLES - 1 {
+            stats.push(10.0);
+        }
+
+        assert_eq!(stats.samples().count(), FRAME_SAMPLES);
+        assert_eq!(stats.max_ms(), 100.0);
+
+        // The slow frame is the oldest so it is the first to go.
+        stats.push(5.0);
+        assert_eq!(stats.samples().count(), FRAME_SAMPLES);
+        assert_eq!(stats.max_ms(), 10.0);
+        assert_eq!(stats.min_ms(), 5.0);
+        assert_eq!(stats.samples().last(), Some(5.0));
+    }
+}
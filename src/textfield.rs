@@ -0,0 +1,697 @@
+use crate::clipboard::Clipboard;
+use graphics::{
+    cosmic_text::{Align, Attrs, Metrics, Wrap},
+    input::{InputEvent, Key},
+    *,
+};
+use std::ops::Range;
+use winit::{event::Ime, event::MouseButton, keyboard::NamedKey};
+
+/// Space between the edge of the field and its text.
+const PADDING: f32 = 4.0;
+const CARET_WIDTH: f32 = 2.0;
+const FONT_SIZE: f32 = 16.0;
+const LINE_HEIGHT: f32 = 18.0;
+/// Most characters a field holds unless set otherwise.
+const MAX_CHARS: usize = 64;
+
+/// What a text field did with an event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextFieldEvent {
+    /// The event was not for this field.
+    Ignored,
+    /// The event was used but the text did not change.
+    Handled,
+    Changed,
+    /// Enter was pressed. Holds the fields text.
+    Submitted(String),
+    /// The field lost focus from a click outside of it. The click is left
+    /// for anything else to use.
+    Blurred,
+}
+
+/// A single line editable text box built from a Text and a few Rects.
+/// Supports a caret, selection, clipboard, keyboard navigation and IME
+/// composition while focused.
+pub struct TextField {
+    pub text: Text,
+    background: Rect,
+    caret_rect: Rect,
+    selection_rect: Rect,
+    pos: Vec3,
+    size: Vec2,
+    edit: LineEdit,
+    /// The text needs to be set again before it is next rendered.
+    dirty: bool,
+}
+
+impl TextField {
+    pub fn new(
+        renderer: &mut GpuRenderer,
+        text_renderer: &mut TextRenderer,
+        pos: Vec3,
+        size: Vec2,
+    ) -> Self {
+        let inner = size - Vec2::splat(PADDING * 2.0);
+        let mut text = Text::new_with_buffer(
+            renderer,
+            text_renderer,
+            Some(Metrics::new(FONT_SIZE, LINE_HEIGHT)),
+            pos + Vec3::new(PADDING, PADDING, 0.0),
+            inner,
+            1.0,
+            1,
+        );
+
+        text.set_buffer_size(Some(inner.x), Some(inner.y))
+            .set_default_color(Color::rgba(255, 255, 255, 255));
+        // Everything stays on one line so the caret and selection can be
+        // placed from a single layout run.
+        text.buffer.set_wrap(&mut renderer.font_sys, Wrap::None);
+
+        // These are see through so the text shows no matter the draw order.
+        let mut background =
+            Rect::new(renderer, pos, size, Color::rgba(0, 0, 0, 160), 0);
+
+        background
+            .set_border_width(1.0)
+            .set_border_color(Color::rgba(255, 255, 255, 120));
+
+        Self {
+            text,
+            background,
+            caret_rect: Rect::new(
+                renderer,
+                pos,
+                Vec2::new(CARET_WIDTH, LINE_HEIGHT),
+                Color::rgba(255, 255, 255, 220),
+                1,
+            ),
+            selection_rect: Rect::new(
+                renderer,
+                pos,
+                Vec2::ZERO,
+                Color::rgba(80, 120, 255, 120),
+                1,
+            ),
+            pos,
+            size,
+            edit: LineEdit::new(MAX_CHARS),
+            dirty: true,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.edit.value
+    }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.edit.set_value(value);
+        self.dirty = true;
+    }
+
+    /// Most characters the field will hold. Anything past it is cut off.
+    pub fn set_max_chars(&mut self, max_chars: usize) {
+        self.edit.max_chars = max_chars;
+        self.set_value(&self.edit.value.clone());
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.edit.focused
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        if self.edit.focused != focused {
+            self.edit.set_focused(focused);
+            self.dirty = true;
+        }
+    }
+
+    /// If the world position is within the field.
+    pub fn contains(&self, world: Vec2) -> bool {
+        let min = self.pos.truncate();
+        let max = min + self.size;
+
+        world.cmpge(min).all() && world.cmplt(max).all()
+    }
+
+    /// Where the IME should place its candidate window, in world space.
+    pub fn caret_pos(&self) -> Vec2 {
+        self.caret_rect.pos.truncate()
+    }
+
+    /// Byte range of the selected text if anything is selected.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        self.edit.selection()
+    }
+
+    /// `mouse` is the mouse position within the world so clicks can focus
+    /// the field.
+    pub fn handle_event(
+        &mut self,
+        event: &InputEvent,
+        mouse: Option<Vec2>,
+        clipboard: &mut Clipboard,
+    ) -> TextFieldEvent {
+        match event {
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: true,
+            } => {
+                let inside = mouse.is_some_and(|mouse| self.contains(mouse));
+
+                if inside {
+                    self.set_focused(true);
+                    TextFieldEvent::Handled
+                } else if self.edit.focused {
+                    self.set_focused(false);
+                    TextFieldEvent::Blurred
+                } else {
+                    TextFieldEvent::Ignored
+                }
+            }
+            InputEvent::KeyInput { key, pressed, .. } if self.edit.focused => {
+                let event = self.edit.handle_key(key, *pressed, clipboard);
+
+                self.dirty |= *pressed;
+                event
+            }
+            InputEvent::WindowFocused(false) => {
+                self.edit.shift = false;
+                self.edit.ctrl = false;
+                TextFieldEvent::Ignored
+            }
+            _ => TextFieldEvent::Ignored,
+        }
+    }
+
+    /// Handles composition from the IME. These come straight from winit as
+    /// the InputHandler does not pass them on.
+    pub fn handle_ime(&mut self, ime: &Ime) -> TextFieldEvent {
+        let event = self.edit.handle_ime(ime);
+
+        self.dirty |= event != TextFieldEvent::Ignored;
+        event
+    }
+
+    /// Sets the text if it changed. Called before the text is pushed into
+    /// its renderer.
+    pub fn refresh_text(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        let mut display = self.edit.value.clone();
+
+        if let Some((preedit, _)) = &self.edit.preedit {
+            display.insert_str(self.edit.caret, preedit);
+        }
+
+        self.text.set_text(
+            &display,
+            &Attrs::new(),
+            Shaping::Advanced,
+            Some(Align::Left),
+        );
+    }
+
+    /// Places the caret and selection over the shaped text. Called once the
+    /// text has been updated within its renderer so its layout is current.
+    pub fn refresh_rects(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        self.dirty = false;
+
+        // Where the caret sits within the displayed text including any
+        // text the IME is composing.
+        let caret = match &self.edit.preedit {
+            Some((_, Some((_, end)))) => self.edit.caret + end,
+            Some((preedit, None)) => self.edit.caret + preedit.len(),
+            None => self.edit.caret,
+        };
+        let origin = self.pos.truncate() + Vec2::splat(PADDING);
+        let top = self.size.y - PADDING * 2.0;
+        let caret_x = self.x_of(caret);
+        let y = origin.y + top - LINE_HEIGHT;
+
+        self.caret_rect.pos = Vec3::new(origin.x + caret_x, y, self.pos.z);
+        self.caret_rect.changed = true;
+
+        match self.selection() {
+            Some(range) => {
+                let start = self.x_of(range.start);
+                let end = self.x_of(range.end);
+
+                self.selection_rect.pos =
+                    Vec3::new(origin.x + start, y, self.pos.z);
+                self.selection_rect.size = Vec2::new(end - start, LINE_HEIGHT);
+            }
+            None => self.selection_rect.size = Vec2::ZERO,
+        }
+
+        self.selection_rect.changed = true;
+    }
+
+    // X offset of the byte index within the shaped text. Wrapping is off so
+    // it is all within the first layout run.
+    fn x_of(&self, index: usize) -> f32 {
+        let Some(run) = self.text.buffer.layout_runs().next() else {
+            return 0.0;
+        };
+
+        run.glyphs
+            .iter()
+            .find(|glyph| index < glyph.end)
+            .map(|glyph| glyph.x)
+            .or_else(|| run.glyphs.last().map(|glyph| glyph.x + glyph.w))
+            .unwrap_or(0.0)
+    }
+
+    /// Pushes the background and, while focused, the selection and caret.
+    /// Returns how many rects were pushed.
+    pub fn update_rects(
+        &mut self,
+        ui_renderer: &mut RectRenderer,
+        renderer: &mut GpuRenderer,
+        atlas: &mut AtlasSet,
+    ) -> usize {
+        ui_renderer.update(&mut self.background, renderer, atlas, 0);
+
+        if !self.edit.focused {
+            return 1;
+        }
+
+        let mut count = 2;
+
+        if self.selection().is_some() {
+            ui_renderer.update(&mut self.selection_rect, renderer, atlas, 0);
+            count += 1;
+        }
+
+        ui_renderer.update(&mut self.caret_rect, renderer, atlas, 0);
+        count
+    }
+}
+
+/// The value, caret and selection of a TextField and the keys that edit
+/// them. Kept apart from the Text and Rects it is drawn with.
+#[derive(Clone, Debug, Default)]
+struct LineEdit {
+    value: String,
+    /// Byte index of the caret within `value`.
+    caret: usize,
+    /// The other end of the selection from the caret.
+    anchor: Option<usize>,
+    /// Text the IME is composing and the cursor range within it.
+    preedit: Option<(String, Option<(usize, usize)>)>,
+    focused: bool,
+    shift: bool,
+    ctrl: bool,
+    /// Most characters the field will hold.
+    max_chars: usize,
+}
+
+impl LineEdit {
+    fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            ..Self::default()
+        }
+    }
+
+    fn set_value(&mut self, value: &str) {
+        self.value = value.chars().take(self.max_chars).collect();
+        self.caret = self.value.len();
+        self.anchor = None;
+    }
+
+    fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.anchor = None;
+        self.preedit = None;
+        self.shift = false;
+        self.ctrl = false;
+    }
+
+    fn handle_ime(&mut self, ime: &Ime) -> TextFieldEvent {
+        if !self.focused {
+            return TextFieldEvent::Ignored;
+        }
+
+        match ime {
+            Ime::Preedit(text, cursor) => {
+                self.preedit =
+                    (!text.is_empty()).then(|| (text.clone(), *cursor));
+                TextFieldEvent::Handled
+            }
+            Ime::Commit(text) => {
+                self.preedit = None;
+                self.insert(text);
+                TextFieldEvent::Changed
+            }
+            Ime::Enabled | Ime::Disabled => {
+                self.preedit = None;
+                TextFieldEvent::Handled
+            }
+        }
+    }
+
+    fn handle_key(
+        &mut self,
+        key: &Key,
+        pressed: bool,
+        clipboard: &mut Clipboard,
+    ) -> TextFieldEvent {
+        match key {
+            Key::Named(NamedKey::Shift) => self.shift = pressed,
+            Key::Named(NamedKey::Control) => self.ctrl = pressed,
+            _ => {}
+        }
+
+        // Releases are still taken so they never reach the game.
+        if !pressed {
+            return TextFieldEvent::Handled;
+        }
+
+        // The IME owns the keyboard while it is composing.
+        if self.preedit.is_some() {
+            return TextFieldEvent::Handled;
+        }
+
+        let old = self.value.clone();
+
+        match key {
+            Key::Character(c) if self.ctrl => self.shortcut(*c, clipboard),
+            Key::Character(c) => self.insert(&c.to_string()),
+            Key::Named(NamedKey::Space) => self.insert(" "),
+            Key::Named(NamedKey::ArrowLeft) => {
+                let to = if self.ctrl {
+                    self.prev_word()
+                } else {
+                    self.prev_char()
+                };
+
+                self.move_caret(to);
+            }
+            Key::Named(NamedKey::ArrowRight) => {
+                let to = if self.ctrl {
+                    self.next_word()
+                } else {
+                    self.next_char()
+                };
+
+                self.move_caret(to);
+            }
+            Key::Named(NamedKey::Home) => self.move_caret(0),
+            Key::Named(NamedKey::End) => self.move_caret(self.value.len()),
+            Key::Named(NamedKey::Backspace) => {
+                // With nothing selected remove what is before the caret.
+                if self.selection().is_none() {
+                    self.anchor = Some(if self.ctrl {
+                        self.prev_word()
+                    } else {
+                        self.prev_char()
+                    });
+                }
+
+                self.delete_selection();
+            }
+            Key::Named(NamedKey::Delete) => {
+                if self.selection().is_none() {
+                    self.anchor = Some(if self.ctrl {
+                        self.next_word()
+                    } else {
+                        self.next_char()
+                    });
+                }
+
+                self.delete_selection();
+            }
+            Key::Named(NamedKey::Enter) => {
+                return TextFieldEvent::Submitted(self.value.clone());
+            }
+            Key::Named(NamedKey::Escape) => self.set_focused(false),
+            _ => {}
+        }
+
+        if self.value != old {
+            TextFieldEvent::Changed
+        } else {
+            TextFieldEvent::Handled
+        }
+    }
+
+    // Ctrl+A selects everything, Ctrl+C, Ctrl+X and Ctrl+V copy, cut and
+    // paste.
+    fn shortcut(&mut self, c: char, clipboard: &mut Clipboard) {
+        match c.to_ascii_lowercase() {
+            'a' => {
+                self.anchor = Some(0);
+                self.caret = self.value.len();
+            }
+            'c' => {
+                if let Some(range) = self.selection() {
+                    clipboard.set(&self.value[range]);
+                }
+            }
+            'x' => {
+                if let Some(range) = self.selection() {
+                    clipboard.set(&self.value[range]);
+                    self.delete_selection();
+                }
+            }
+            'v' => {
+                // Only a single line fits within the field.
+                let text = clipboard.get().replace(['\r', '\n'], " ");
+
+                self.insert(&text);
+            }
+            _ => {}
+        }
+    }
+
+    fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+
+        (anchor != self.caret)
+            .then(|| anchor.min(self.caret)..anchor.max(self.caret))
+    }
+
+    // Moves the caret and extends the selection while shift is held.
+    fn move_caret(&mut self, to: usize) {
+        if self.shift {
+            self.anchor.get_or_insert(self.caret);
+        } else {
+            self.anchor = None;
+        }
+
+        self.caret = to;
+    }
+
+    fn delete_selection(&mut self) {
+        if let Some(range) = self.selection() {
+            self.caret = range.start;
+            self.value.replace_range(range, "");
+        }
+
+        self.anchor = None;
+    }
+
+    // Replaces the selection with the text, cutting it short if the field
+    // would go over max_chars.
+    fn insert(&mut self, text: &str) {
+        self.delete_selection();
+
+        let room = self.max_chars.saturating_sub(self.value.chars().count());
+        let text: String = text.chars().take(room).collect();
+
+        self.value.insert_str(self.caret, &text);
+        self.caret += text.len();
+    }
+
+    fn prev_char(&self) -> usize {
+        self.value[..self.caret]
+            .chars()
+            .next_back()
+            .map_or(0, |c| self.caret - c.len_utf8())
+    }
+
+    fn next_char(&self) -> usize {
+        self.value[self.caret..]
+            .chars()
+            .next()
+            .map_or(self.caret, |c| self.caret + c.len_utf8())
+    }
+
+    // Skips back over any spaces and then the word before them.
+    fn prev_word(&self) -> usize {
+        let before = self.value[..self.caret].trim_end();
+
+        before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8())
+    }
+
+    // Skips over the rest of the word and any spaces after it.
+    fn next_word(&self) -> usize {
+        let after = &self.value[self.caret..];
+        let word_end = after.find(char::is_whitespace).unwrap_or(after.len());
+        let spaces =
+            after[word_end..].len() - after[word_end..].trim_start().len();
+
+        self.caret + word_end + spaces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(edit: &mut LineEdit, clipboard: &mut Clipboard, key: Key) {
+        edit.handle_key(&key, true, clipboard);
+    }
+
+    // Presses the key while holding each modifier.
+    fn chord(
+        edit: &mut LineEdit,
+        clipboard: &mut Clipboard,
+        modifiers: &[NamedKey],
+        key: Key,
+    ) {
+        for modifier in modifiers {
+            edit.handle_key(&Key::Named(*modifier), true, clipboard);
+        }
+
+        press(edit, clipboard, key);
+
+        for modifier in modifiers {
+            edit.handle_key(&Key::Named(*modifier), false, clipboard);
+        }
+    }
+
+    fn field(value: &str) -> LineEdit {
+        let mut edit = LineEdit::new(MAX_CHARS);
+
+        edit.set_focused(true);
+        edit.set_value(value);
+        edit
+    }
+
+    #[test]
+    fn words_skip_the_spaces_around_them() {
+        let mut edit = field("hello  big world");
+
+        assert_eq!(edit.prev_word(), 11);
+        edit.caret = 11;
+        assert_eq!(edit.prev_word(), 7);
+        edit.caret = 7;
+        assert_eq!(edit.prev_word(), 0);
+
+        edit.caret = 0;
+        assert_eq!(edit.next_word(), 7);
+        edit.caret = 7;
+        assert_eq!(edit.next_word(), 11);
+        edit.caret = 11;
+        assert_eq!(edit.next_word(), 16);
+    }
+
+    #[test]
+    fn chars_step_over_whole_code_points() {
+        let mut edit = field("aé漢");
+
+        assert_eq!(edit.caret, 6);
+        assert_eq!(edit.prev_char(), 3);
+        edit.caret = 3;
+        assert_eq!(edit.prev_char(), 1);
+        assert_eq!(edit.next_char(), 6);
+        edit.caret = 1;
+        assert_eq!(edit.next_char(), 3);
+        edit.caret = 0;
+        assert_eq!(edit.prev_char(), 0);
+
+        let mut clipboard = Clipboard::local();
+
+        edit.caret = 6;
+        press(&mut edit, &mut clipboard, Key::Named(NamedKey::Backspace));
+        assert_eq!(edit.value, "aé");
+        assert_eq!(edit.caret, 3);
+    }
+
+    #[test]
+    fn insert_stops_at_max_chars() {
+        let mut edit = LineEdit::new(5);
+
+        edit.set_value("生活,삶,जिं");
+        assert_eq!(edit.value.chars().count(), 5);
+
+        edit.set_value("abc");
+        edit.insert("defgh");
+        assert_eq!(edit.value, "abcde");
+        assert_eq!(edit.caret, 5);
+
+        edit.insert("f");
+        assert_eq!(edit.value, "abcde");
+    }
+
+    #[test]
+    fn typing_replaces_the_selection() {
+        let mut edit = field("hello world");
+        let mut clipboard = Clipboard::local();
+
+        chord(
+            &mut edit,
+            &mut clipboard,
+            &[NamedKey::Control, NamedKey::Shift],
+            Key::Named(NamedKey::ArrowLeft),
+        );
+        assert_eq!(edit.selection(), Some(6..11));
+
+        press(&mut edit, &mut clipboard, Key::Character('x'));
+        assert_eq!(edit.value, "hello x");
+        assert_eq!(edit.caret, 7);
+        assert_eq!(edit.selection(), None);
+    }
+
+    #[test]
+    fn cut_and_paste_use_the_clipboard() {
+        let mut edit = field("hello world");
+        let mut clipboard = Clipboard::local();
+
+        chord(
+            &mut edit,
+            &mut clipboard,
+            &[NamedKey::Control],
+            Key::Character('a'),
+        );
+        chord(
+            &mut edit,
+            &mut clipboard,
+            &[NamedKey::Control],
+            Key::Character('x'),
+        );
+        assert_eq!(edit.value, "");
+        assert_eq!(clipboard.get(), "hello world");
+
+        chord(
+            &mut edit,
+            &mut clipboard,
+            &[NamedKey::Control],
+            Key::Character('v'),
+        );
+        assert_eq!(edit.value, "hello world");
+
+        // Pasted lines are joined as only one fits.
+        clipboard.set("a\nb");
+        chord(
+            &mut edit,
+            &mut clipboard,
+            &[NamedKey::Control],
+            Key::Character('v'),
+        );
+        assert_eq!(edit.value, "hello worlda b");
+    }
+}
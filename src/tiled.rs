@@ -0,0 +1,753 @@
+use crate::{
+    mapfile::Tilesheets,
+    scene::{MAP_LAYERS, MAP_SIZE, TILE_SIZE},
+};
+use graphics::*;
+use log::warn;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+/// The top bits of a gid say how the tile is flipped. We can not flip map
+/// tiles so they are only used to warn about it.
+const GID_FLIP_FLAGS: u32 = 0xF000_0000;
+
+/// A map made in Tiled. Loaded from either its JSON (.tmj) or XML (.tmx)
+/// format. Only orthogonal, finite maps with CSV or uncompressed base64 layer
+/// data can be imported.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TiledMap {
+    pub width: u32,
+    pub height: u32,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    #[serde(default)]
+    pub orientation: String,
+    #[serde(default)]
+    pub infinite: bool,
+    #[serde(default)]
+    pub layers: Vec<TiledLayer>,
+    #[serde(default)]
+    pub tilesets: Vec<TiledTileset>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TiledLayer {
+    /// tilelayer, group, objectgroup or imagelayer. Only tile layers and
+    /// the tile layers within groups are imported.
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub data: Option<TiledData>,
+    #[serde(default)]
+    pub encoding: Option<String>,
+    #[serde(default)]
+    pub compression: Option<String>,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    /// #RRGGBB or #AARRGGBB the layer's tiles are tinted with.
+    #[serde(default)]
+    pub tintcolor: Option<String>,
+    /// Layers within a group.
+    #[serde(default)]
+    pub layers: Vec<TiledLayer>,
+    #[serde(default)]
+    pub properties: Vec<TiledProperty>,
+}
+
+/// Layer data is either a list of gids or an encoded string.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TiledData {
+    Gids(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TiledProperty {
+    pub name: String,
+    #[serde(default)]
+    pub value: serde_json::Value,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TiledTileset {
+    /// The gid of the tileset's first tile within the map.
+    pub firstgid: u32,
+    /// Set when the tileset is kept within its own file.
+    pub source: Option<String>,
+    pub name: String,
+    /// Path to the tileset image relative to where the tileset was loaded.
+    pub image: String,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    /// Only the tiles with extra data like animations are listed.
+    pub tiles: Vec<TiledTile>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TiledTile {
+    pub id: u32,
+    #[serde(default)]
+    pub animation: Vec<TiledFrame>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TiledFrame {
+    pub tileid: u32,
+    /// Milliseconds the frame is shown for.
+    pub duration: u32,
+}
+
+impl TiledMap {
+    /// Loads a .tmj or .tmx map along with any tilesets it keeps in their
+    /// own files. Image paths within them are resolved from the file they
+    /// are in.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, GraphicsError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+        let mut map: Self = if is_xml(path) {
+            xml::parse_map(&data)
+        } else {
+            serde_json::from_str(&data).map_err(|e| e.to_string())
+        }
+        .map_err(|e| {
+            OtherError::new(&format!(
+                "failed to parse Tiled map {}: {e}",
+                path.display()
+            ))
+        })?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        for tileset in map.tilesets.iter_mut() {
+            if let Some(source) = tileset.source.take() {
+                let firstgid = tileset.firstgid;
+
+                *tileset = load_tileset(&dir.join(source))?;
+                tileset.firstgid = firstgid;
+            } else {
+                tileset.image = join(dir, &tileset.image);
+            }
+        }
+
+        map.validate(path)?;
+        Ok(map)
+    }
+
+    fn validate(&self, path: &Path) -> Result<(), GraphicsError> {
+        let error = |msg: String| -> Result<(), GraphicsError> {
+            Err(OtherError::new(&format!(
+                "can not import Tiled map {}: {msg}",
+                path.display()
+            ))
+            .into())
+        };
+
+        if !self.orientation.is_empty() && self.orientation != "orthogonal" {
+            return error(format!(
+                "{} maps are not supported",
+                self.orientation
+            ));
+        }
+
+        if self.infinite {
+            return error("infinite maps are not supported".into());
+        }
+
+        if self.width > MAP_SIZE || self.height > MAP_SIZE {
+            return error(format!(
+                "it is {}x{} tiles but maps are at most {MAP_SIZE}x{MAP_SIZE}",
+                self.width, self.height
+            ));
+        }
+
+        if self.tilewidth != TILE_SIZE || self.tileheight != TILE_SIZE {
+            return error(format!(
+                "tiles are {}x{} but have to be {TILE_SIZE}x{TILE_SIZE}",
+                self.tilewidth, self.tileheight
+            ));
+        }
+
+        for tileset in &self.tilesets {
+            if tileset.tilewidth != TILE_SIZE || tileset.tileheight != TILE_SIZE
+            {
+                return error(format!(
+                    "tileset {} tiles have to be {TILE_SIZE}x{TILE_SIZE}",
+                    tileset.name
+                ));
+            }
+
+            if tileset.margin != 0 || tileset.spacing != 0 {
+                return error(format!(
+                    "tileset {} can not have a margin or spacing",
+                    tileset.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads each tileset into the map atlas as a tilesheet and sets the
+    /// tiles of each visible tile layer onto the map layer of the same index.
+    /// A layer can be put onto another map layer with an int `layer` property.
+    pub fn apply(
+        &self,
+        map: &mut Map,
+        atlas: &mut AtlasSet,
+        tilesheets: &mut Tilesheets,
+        renderer: &mut GpuRenderer,
+    ) -> Result<(), GraphicsError> {
+        // The atlas index of each tile within each tileset by firstgid.
+        let mut sheets = Vec::with_capacity(self.tilesets.len());
+
+        for tileset in &self.tilesets {
+            let sheet = Texture::from_file(&tileset.image)?
+                .new_tilesheet(
+                    tileset.name.as_str(),
+                    atlas,
+                    renderer,
+                    TILE_SIZE,
+                )
+                .ok_or_else(|| {
+                    OtherError::new(&format!(
+                        "failed to upload tileset {}",
+                        tileset.name
+                    ))
+                })?;
+            let tiles: Vec<usize> =
+                sheet.tiles.iter().map(|tile| tile.tex).collect();
+
+            tilesheets.insert(&tileset.name, tiles.clone())?;
+            sheets.push((tileset, tiles, anim_times(tileset)));
+        }
+
+        // Later tilesets start at higher gids.
+        sheets.sort_by_key(|(tileset, ..)| std::cmp::Reverse(tileset.firstgid));
+
+        let mut layers = Vec::new();
+
+        flatten_layers(&self.layers, 1.0, &mut layers);
+
+        for (index, (layer, opacity)) in layers.into_iter().enumerate() {
+            let z = layer_property(layer, "layer").unwrap_or(index as u32);
+
+            if z >= MAP_LAYERS {
+                return Err(OtherError::new(&format!(
+                    "Tiled layer {} can not go on map layer {z} of {MAP_LAYERS}",
+                    layer.name
+                ))
+                .into());
+            }
+
+            let gids = layer_gids(layer).map_err(|e| {
+                OtherError::new(&format!(
+                    "failed to read Tiled layer {}: {e}",
+                    layer.name
+                ))
+            })?;
+            let color = layer_color(layer, opacity);
+            let mut flipped = false;
+
+            let tile_count = (self.width * self.height) as usize;
+
+            for (i, gid) in gids.into_iter().take(tile_count).enumerate() {
+                flipped |= gid & GID_FLIP_FLAGS != 0;
+
+                let gid = gid & !GID_FLIP_FLAGS;
+
+                if gid == 0 {
+                    continue;
+                }
+
+                let Some((tileset, tiles, anim_times)) =
+                    sheets.iter().find(|(tileset, ..)| tileset.firstgid <= gid)
+                else {
+                    continue;
+                };
+                let local = gid - tileset.firstgid;
+                let Some(&id) = tiles.get(local as usize) else {
+                    continue;
+                };
+                let anim_time = anim_times.get(&local).copied().unwrap_or(0);
+
+                map.set_tile(
+                    tile_pos(i, self.width, self.height).extend(z),
+                    TileData {
+                        id,
+                        color,
+                        anim_time,
+                    },
+                );
+            }
+
+            if flipped {
+                warn!(
+                    "Tiled layer {} has flipped tiles which are drawn unflipped",
+                    layer.name
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Map tiles can only animate through the tiles after them in their
+// tilesheet at a single rate. Any other animation is warned about and drawn
+// as its first tile.
+fn anim_times(tileset: &TiledTileset) -> HashMap<u32, u32> {
+    let mut times = HashMap::new();
+
+    for tile in tileset
+        .tiles
+        .iter()
+        .filter(|tile| !tile.animation.is_empty())
+    {
+        match anim_time(tile) {
+            Some(time) => {
+                times.insert(tile.id, time);
+            }
+            None => warn!(
+                "tileset {} tile {} is drawn unanimated as map tiles can only \
+                 animate through the tiles after them at one rate",
+                tileset.name, tile.id
+            ),
+        }
+    }
+
+    times
+}
+
+fn anim_time(tile: &TiledTile) -> Option<u32> {
+    let first = tile.animation.first()?;
+    let supported = tile.animation.iter().enumerate().all(|(i, frame)| {
+        frame.tileid == tile.id + i as u32 && frame.duration == first.duration
+    });
+
+    supported.then_some(first.duration)
+}
+
+// Where the tile at index `i` of a layer goes. Tiled rows go down from the
+// top while ours go up.
+fn tile_pos(i: usize, width: u32, height: u32) -> UVec2 {
+    let i = i as u32;
+
+    UVec2::new(i % width, height - 1 - i / width)
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn is_xml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ext.eq_ignore_ascii_case("tmx") || ext.eq_ignore_ascii_case("tsx")
+    })
+}
+
+fn join(dir: &Path, path: &str) -> String {
+    dir.join(path).to_string_lossy().into_owned()
+}
+
+fn load_tileset(path: &Path) -> Result<TiledTileset, GraphicsError> {
+    let data = fs::read_to_string(path)?;
+    let mut tileset: TiledTileset = if is_xml(path) {
+        xml::parse_tileset(&data)
+    } else {
+        serde_json::from_str(&data).map_err(|e| e.to_string())
+    }
+    .map_err(|e| {
+        OtherError::new(&format!(
+            "failed to parse Tiled tileset {}: {e}",
+            path.display()
+        ))
+    })?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    tileset.image = join(dir, &tileset.image);
+    Ok(tileset)
+}
+
+// Collects the visible tile layers in the order they are drawn along with
+// their opacity multiplied by that of the groups they are in.
+fn flatten_layers<'a>(
+    layers: &'a [TiledLayer],
+    opacity: f32,
+    out: &mut Vec<(&'a TiledLayer, f32)>,
+) {
+    for layer in layers.iter().filter(|layer| layer.visible) {
+        match layer.kind.as_str() {
+            "tilelayer" => out.push((layer, opacity * layer.opacity)),
+            "group" => {
+                flatten_layers(&layer.layers, opacity * layer.opacity, out)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn layer_property(layer: &TiledLayer, name: &str) -> Option<u32> {
+    layer
+        .properties
+        .iter()
+        .find(|property| property.name == name)
+        .and_then(|property| property.value.as_u64())
+        .map(|value| value as u32)
+}
+
+fn layer_color(layer: &TiledLayer, opacity: f32) -> Color {
+    let [a, r, g, b] = layer
+        .tintcolor
+        .as_deref()
+        .and_then(parse_tint)
+        .unwrap_or([255; 4]);
+
+    Color::rgba(r, g, b, (a as f32 * opacity).round() as u8)
+}
+
+// Tiled writes tints as #RRGGBB or #AARRGGBB.
+fn parse_tint(tint: &str) -> Option<[u8; 4]> {
+    let hex = tint.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).ok()?;
+
+    match hex.len() {
+        6 => Some((value | 0xFF00_0000).to_be_bytes()),
+        8 => Some(value.to_be_bytes()),
+        _ => None,
+    }
+}
+
+fn layer_gids(layer: &TiledLayer) -> Result<Vec<u32>, String> {
+    let data = match &layer.data {
+        Some(TiledData::Gids(gids)) => return Ok(gids.clone()),
+        Some(TiledData::Encoded(data)) => data,
+        None => return Ok(Vec::new()),
+    };
+
+    if let Some(compression) =
+        layer.compression.as_deref().filter(|c| !c.is_empty())
+    {
+        return Err(format!(
+            "{compression} compressed layers are not supported, save them as CSV"
+        ));
+    }
+
+    match layer.encoding.as_deref() {
+        Some("base64") => {
+            let bytes = decode_base64(data)?;
+
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect())
+        }
+        _ => data
+            .split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse::<u32>().map_err(|e| e.to_string()))
+            .collect(),
+    }
+}
+
+// Only used for layer data so a small decoder saves pulling in a crate.
+fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in data
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = value(c)
+            .ok_or_else(|| format!("invalid base64 character {}", c as char))?;
+
+        bits = (bits << 6) | value as u32;
+        count += 6;
+
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+// Reads the .tmx and .tsx formats into the same types the JSON ones use.
+mod xml {
+    use super::*;
+    use roxmltree::{Document, Node};
+
+    pub fn parse_map(data: &str) -> Result<TiledMap, String> {
+        let doc = Document::parse(data).map_err(|e| e.to_string())?;
+        let root = doc.root_element();
+
+        Ok(TiledMap {
+            width: attr(root, "width")?,
+            height: attr(root, "height")?,
+            tilewidth: attr(root, "tilewidth")?,
+            tileheight: attr(root, "tileheight")?,
+            orientation: root.attribute("orientation").unwrap_or("").into(),
+            infinite: attr_or(root, "infinite", 0u32)? != 0,
+            layers: layers(root)?,
+            tilesets: root
+                .children()
+                .filter(|node| node.has_tag_name("tileset"))
+                .map(tileset)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn parse_tileset(data: &str) -> Result<TiledTileset, String> {
+        let doc = Document::parse(data).map_err(|e| e.to_string())?;
+
+        tileset(doc.root_element())
+    }
+
+    fn attr<T: std::str::FromStr>(node: Node, name: &str) -> Result<T, String> {
+        node.attribute(name)
+            .ok_or_else(|| {
+                format!("<{}> is missing {name}", node.tag_name().name())
+            })?
+            .parse()
+            .map_err(|_| {
+                format!("<{}> has an invalid {name}", node.tag_name().name())
+            })
+    }
+
+    fn attr_or<T: std::str::FromStr>(
+        node: Node,
+        name: &str,
+        default: T,
+    ) -> Result<T, String> {
+        match node.attribute(name) {
+            Some(_) => attr(node, name),
+            None => Ok(default),
+        }
+    }
+
+    fn tileset(node: Node) -> Result<TiledTileset, String> {
+        let image = node.children().find(|child| child.has_tag_name("image"));
+
+        Ok(TiledTileset {
+            firstgid: attr_or(node, "firstgid", 0)?,
+            source: node.attribute("source").map(Into::into),
+            name: node.attribute("name").unwrap_or("").into(),
+            image: image
+                .and_then(|image| image.attribute("source"))
+                .unwrap_or("")
+                .into(),
+            tilewidth: attr_or(node, "tilewidth", 0)?,
+            tileheight: attr_or(node, "tileheight", 0)?,
+            margin: attr_or(node, "margin", 0)?,
+            spacing: attr_or(node, "spacing", 0)?,
+            tiles: node
+                .children()
+                .filter(|child| child.has_tag_name("tile"))
+                .map(|tile| {
+                    Ok(TiledTile {
+                        id: attr(tile, "id")?,
+                        animation: tile
+                            .descendants()
+                            .filter(|frame| frame.has_tag_name("frame"))
+                            .map(|frame| {
+                                Ok(TiledFrame {
+                                    tileid: attr(frame, "tileid")?,
+                                    duration: attr(frame, "duration")?,
+                                })
+                            })
+                            .collect::<Result<_, String>>()?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        })
+    }
+
+    fn layers(node: Node) -> Result<Vec<TiledLayer>, String> {
+        node.children()
+            .filter(|child| child.is_element())
+            .filter_map(|child| {
+                let kind = match child.tag_name().name() {
+                    "layer" => "tilelayer",
+                    "group" => "group",
+                    _ => return None,
+                };
+
+                Some(layer(child, kind))
+            })
+            .collect()
+    }
+
+    fn layer(node: Node, kind: &str) -> Result<TiledLayer, String> {
+        let data = node.children().find(|child| child.has_tag_name("data"));
+        let properties = node
+            .children()
+            .filter(|child| child.has_tag_name("properties"))
+            .flat_map(|properties| properties.children())
+            .filter(|property| property.has_tag_name("property"))
+            .map(|property| {
+                let value = property.attribute("value").unwrap_or("");
+
+                TiledProperty {
+                    name: property.attribute("name").unwrap_or("").into(),
+                    // Numbers are kept as numbers like in the JSON format.
+                    value: value
+                        .parse::<u64>()
+                        .map(Into::into)
+                        .unwrap_or_else(|_| value.into()),
+                }
+            })
+            .collect();
+
+        Ok(TiledLayer {
+            kind: kind.into(),
+            name: node.attribute("name").unwrap_or("").into(),
+            encoding: data
+                .and_then(|data| data.attribute("encoding"))
+                .map(Into::into),
+            compression: data
+                .and_then(|data| data.attribute("compression"))
+                .map(Into::into),
+            data: data.map(layer_data).transpose()?,
+            opacity: attr_or(node, "opacity", 1.0)?,
+            visible: attr_or(node, "visible", 1u32)? != 0,
+            tintcolor: node.attribute("tintcolor").map(Into::into),
+            layers: layers(node)?,
+            properties,
+        })
+    }
+
+    // Without an encoding each tile is its own <tile gid=""/> element.
+    fn layer_data(data: Node) -> Result<TiledData, String> {
+        if data.attribute("encoding").is_some() {
+            return Ok(TiledData::Encoded(data.text().unwrap_or("").into()));
+        }
+
+        data.children()
+            .filter(|tile| tile.has_tag_name("tile"))
+            .map(|tile| attr_or(tile, "gid", 0))
+            .collect::<Result<_, _>>()
+            .map(TiledData::Gids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(json: &str) -> TiledLayer {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn frames(id: u32, frames: &[(u32, u32)]) -> TiledTile {
+        TiledTile {
+            id,
+            animation: frames
+                .iter()
+                .map(|&(tileid, duration)| TiledFrame { tileid, duration })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn decodes_base64() {
+        assert_eq!(decode_base64("VGlsZWQ=").unwrap(), b"Tiled");
+        assert_eq!(decode_base64("VGk").unwrap(), b"Ti");
+        assert_eq!(decode_base64("\n  VGls\n  ZWQ=\n").unwrap(), b"Tiled");
+        assert!(decode_base64("VG*k").is_err());
+    }
+
+    #[test]
+    fn parses_tints_with_and_without_alpha() {
+        assert_eq!(parse_tint("#ff8000"), Some([255, 255, 128, 0]));
+        assert_eq!(parse_tint("#80ff8000"), Some([128, 255, 128, 0]));
+        assert_eq!(parse_tint("ff8000"), Some([255, 255, 128, 0]));
+        assert_eq!(parse_tint("#fff"), None);
+        assert_eq!(parse_tint("#gggggg"), None);
+    }
+
+    #[test]
+    fn reads_gids_from_arrays_and_csv() {
+        let array = layer(r#"{"type": "tilelayer", "data": [1, 0, 2]}"#);
+        let csv = layer(
+            r#"{"type": "tilelayer", "encoding": "csv", "data": "1,0,\n2\n"}"#,
+        );
+
+        assert_eq!(layer_gids(&array).unwrap(), [1, 0, 2]);
+        assert_eq!(layer_gids(&csv).unwrap(), [1, 0, 2]);
+        assert!(
+            layer_gids(&layer(r#"{"type": "tilelayer", "data": "1,x"}"#))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reads_gids_from_base64() {
+        // 1, 2 and a flipped 3 as little endian u32s.
+        let base64 = layer(
+            r#"{"type": "tilelayer", "encoding": "base64",
+                "data": "AQAAAAIAAAADAACA"}"#,
+        );
+        let compressed = layer(
+            r#"{"type": "tilelayer", "encoding": "base64",
+                "compression": "zlib", "data": "AQAAAAIAAAADAACA"}"#,
+        );
+
+        assert_eq!(layer_gids(&base64).unwrap(), [1, 2, 0x8000_0003]);
+        assert!(layer_gids(&compressed).is_err());
+    }
+
+    #[test]
+    fn reads_gids_from_xml() {
+        let map = xml::parse_map(
+            r#"<map width="2" height="1" tilewidth="20" tileheight="20">
+                <layer name="csv"><data encoding="csv">3,4</data></layer>
+                <layer name="tiles">
+                    <data><tile gid="5"/><tile/></data>
+                </layer>
+            </map>"#,
+        )
+        .unwrap();
+
+        assert_eq!(layer_gids(&map.layers[0]).unwrap(), [3, 4]);
+        assert_eq!(layer_gids(&map.layers[1]).unwrap(), [5, 0]);
+    }
+
+    #[test]
+    fn rows_are_flipped_so_the_first_is_the_top() {
+        assert_eq!(tile_pos(0, 3, 2), UVec2::new(0, 1));
+        assert_eq!(tile_pos(2, 3, 2), UVec2::new(2, 1));
+        assert_eq!(tile_pos(3, 3, 2), UVec2::new(0, 0));
+        assert_eq!(tile_pos(5, 3, 2), UVec2::new(2, 0));
+    }
+
+    #[test]
+    fn only_animations_through_the_next_tiles_at_one_rate_are_kept() {
+        assert_eq!(anim_time(&frames(4, &[(4, 100), (5, 100)])), Some(100));
+        assert_eq!(anim_time(&frames(4, &[(4, 100), (7, 100)])), None);
+        assert_eq!(anim_time(&frames(4, &[(5, 100), (6, 100)])), None);
+        assert_eq!(anim_time(&frames(4, &[(4, 100), (5, 200)])), None);
+        assert_eq!(anim_time(&frames(4, &[])), None);
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+/// Splits real time up into fixed sized steps for the game logic so it runs
+/// at the same speed no matter how fast or often we render.
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+    last: Instant,
+    /// Caps how many steps can run within one frame so a long stall does not
+    /// leave us forever trying to catch back up.
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    /// Creates a timestep running `rate` steps per second.
+    pub fn new(rate: u32) -> Self {
+        Self {
+            step: Duration::from_secs(1) / rate.max(1),
+            accumulator: Duration::ZERO,
+            last: Instant::now(),
+            max_steps: 8,
+        }
+    }
+
+    /// Adds the time since the last call and returns how many steps the
+    /// game logic should run this frame.
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+
+        self.accumulator += now - self.last;
+        self.last = now;
+
+        let mut steps = 0;
+
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+
+            if steps == self.max_steps {
+                // Drop the time we could not catch up on.
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+        }
+
+        steps
+    }
+
+    /// Length of a single step in seconds.
+    pub fn dt(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// How far we are between the last step and the next one. Used to
+    /// interpolate what is rendered between the last two steps.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
@@ -0,0 +1,141 @@
+use crate::scene::{CameraDesc, world_projection};
+use camera::controls::{FlatControls, FlatSettings};
+use graphics::*;
+use serde::{Deserialize, Serialize};
+use time::FrameTime;
+use winit::dpi::PhysicalSize;
+
+/// Part of the window a viewport is drawn into. Each value is a fraction of
+/// the window so viewports keep their place as it resizes. Starts at the top
+/// left like window positions do.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewportRect {
+    /// Top left position and size of the rect in window pixels. Rounded to
+    /// whole pixels since that is all the scissor rect can take.
+    pub fn to_pixels(self, window: Vec2) -> (Vec2, Vec2) {
+        let pos = (Vec2::new(self.x, self.y) * window).round();
+        let end = (Vec2::new(self.x + self.width, self.y + self.height)
+            * window)
+            .round()
+            .min(window);
+
+        (pos, (end - pos).max(Vec2::ONE))
+    }
+
+    pub fn contains(self, window: Vec2, pos: Vec2) -> bool {
+        let (min, size) = self.to_pixels(window);
+        let max = min + size;
+
+        pos.x >= min.x && pos.y >= min.y && pos.x < max.x && pos.y < max.y
+    }
+}
+
+/// A second camera into the world drawn over the main one. Used for a
+/// minimap in the corner or the second player of a split screen.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ViewportDesc {
+    pub name: String,
+    pub rect: ViewportRect,
+    /// Where the viewport's camera starts and what it follows.
+    #[serde(default)]
+    pub camera: CameraDesc,
+}
+
+/// Only the world is drawn within a viewport. The UI is drawn over all of
+/// them by the main view.
+pub struct Viewport {
+    pub name: String,
+    pub rect: ViewportRect,
+    /// Its own camera and projection so it can look anywhere in the world.
+    pub system: System<FlatControls>,
+    pub visible: bool,
+}
+
+impl Viewport {
+    /// `sub_views` are copied into the viewport's System so anything drawn
+    /// with a sub view looks the same as in the main view.
+    pub fn new(
+        renderer: &mut GpuRenderer,
+        desc: &ViewportDesc,
+        sub_views: &[(CameraView, Mat4, f32)],
+    ) -> Self {
+        let (_, size) = desc.rect.to_pixels(window_size(renderer));
+        let mut system = System::new(
+            renderer,
+            world_projection(PhysicalSize::new(size.x, size.y)),
+            FlatControls::new(FlatSettings {
+                zoom: desc.camera.zoom,
+            }),
+            [size.x, size.y],
+        );
+
+        system.controls_mut().inputs_mut().translation.x =
+            desc.camera.translation.x;
+        system.controls_mut().inputs_mut().translation.y =
+            desc.camera.translation.y;
+
+        for (view, mat, scale) in sub_views {
+            system.set_view(*view, *mat, *scale);
+        }
+
+        Self {
+            name: desc.name.clone(),
+            rect: desc.rect,
+            system,
+            visible: true,
+        }
+    }
+
+    /// Size of the viewport in window pixels.
+    pub fn size(&self, window: Vec2) -> Vec2 {
+        self.rect.to_pixels(window).1
+    }
+
+    /// Resets the projection so one world pixel stays one window pixel.
+    pub fn resize(&mut self, window: Vec2) {
+        let size = self.size(window);
+
+        self.system
+            .set_projection(world_projection(PhysicalSize::new(
+                size.x, size.y,
+            )));
+    }
+
+    /// Uploads the viewport's camera and screen size for rendering.
+    pub fn update(
+        &mut self,
+        renderer: &mut GpuRenderer,
+        frame_time: &FrameTime,
+    ) {
+        let size = self.size(window_size(renderer));
+
+        self.system.update(renderer, frame_time);
+        self.system.update_screen(renderer, [size.x, size.y]);
+    }
+
+    /// Limits drawing within the pass to the viewport.
+    pub fn set_within(&self, pass: &mut wgpu::RenderPass, window: Vec2) {
+        let (pos, size) = self.rect.to_pixels(window);
+
+        pass.set_viewport(pos.x, pos.y, size.x, size.y, 0.0, 1.0);
+        pass.set_scissor_rect(
+            pos.x as u32,
+            pos.y as u32,
+            size.x as u32,
+            size.y as u32,
+        );
+    }
+}
+
+pub fn window_size(renderer: &GpuRenderer) -> Vec2 {
+    let size = renderer.size();
+
+    Vec2::new(size.width, size.height)
+}
@@ -0,0 +1,505 @@
+use crate::{
+    collision::AttributeGrid,
+    gamestate::top_tile,
+    mapfile::{MapFile, Tilesheets},
+    scene::{MAP_COUNT, MAP_SIZE, TILE_SIZE},
+};
+use graphics::*;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    path::{Path, PathBuf},
+};
+use tokio::{runtime::Handle, sync::mpsc};
+
+/// The scene's own map takes one of the MapRenderer's maps and the world
+/// can have the rest.
+const MAX_CHUNKS: usize = (MAP_COUNT - 1) as usize;
+
+/// A world far larger than a single map split into a grid of map chunks.
+/// Each chunk is a map file named `<x>_<y>.bin` or `<x>_<y>.ron` within
+/// `dir`. Chunks without a file are left empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldDesc {
+    pub dir: String,
+    /// World position of the bottom left of chunk 0,0.
+    pub pos: Vec2,
+    /// How many chunks across and up the world is.
+    pub size: UVec2,
+    /// Chunks this many chunks past the edge of a view are loaded too so
+    /// they are ready before they scroll into view.
+    pub load_margin: u32,
+}
+
+impl Default for WorldDesc {
+    fn default() -> Self {
+        Self {
+            dir: String::from("world"),
+            pos: Vec2::ZERO,
+            size: UVec2::ONE,
+            load_margin: 1,
+        }
+    }
+}
+
+enum Chunk {
+    /// Still being read on one of tokio's blocking threads.
+    Loading,
+    /// Has no file or failed to load so there is nothing to draw.
+    Empty,
+    /// Index of the chunk's map within the World's maps.
+    Loaded(usize),
+}
+
+/// Which of the World's maps a loaded chunk goes onto.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MapSlot {
+    /// Index of a map freed by an unloaded chunk.
+    Free(usize),
+    /// There is room in the MapRenderer for another map.
+    New,
+    /// Every map is in use.
+    Full,
+}
+
+type ChunkResult = (UVec2, Result<Option<MapFile>, String>);
+
+/// Loads the chunks around each camera in the background and unloads them
+/// once they are out of range again.
+pub struct World {
+    dir: PathBuf,
+    pos: Vec2,
+    size: UVec2,
+    load_margin: u32,
+    chunks: HashMap<UVec2, Chunk>,
+    /// Chunks within a view. Only these are sent to the MapRenderer.
+    visible: Vec<UVec2>,
+    /// Maps are reused for other chunks once unloaded since the MapRenderer
+    /// only has room for so many.
+    maps: Vec<Map>,
+    /// The TileAttributes of each map's chunk, by the same index.
+    grids: Vec<AttributeGrid>,
+    free: Vec<usize>,
+    runtime: Handle,
+    sender: mpsc::UnboundedSender<ChunkResult>,
+    receiver: mpsc::UnboundedReceiver<ChunkResult>,
+}
+
+impl World {
+    /// Has to be called within the tokio runtime since chunks are loaded on
+    /// it.
+    pub fn new(desc: &WorldDesc) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        Self {
+            dir: PathBuf::from(&desc.dir),
+            pos: desc.pos,
+            size: desc.size,
+            load_margin: desc.load_margin,
+            chunks: HashMap::new(),
+            visible: Vec::new(),
+            maps: Vec::new(),
+            grids: Vec::new(),
+            free: Vec::new(),
+            runtime: Handle::current(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Bottom left and top right of the whole world.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        (self.pos, self.pos + self.size.as_vec2() * chunk_size())
+    }
+
+    /// How many chunks have a map loaded.
+    pub fn loaded(&self) -> usize {
+        self.maps.len() - self.free.len()
+    }
+
+    /// The TileAttributes of every loaded chunk along with the world
+    /// position of its bottom left.
+    pub fn attribute_grids(
+        &self,
+    ) -> impl Iterator<Item = (Vec2, &AttributeGrid)> + '_ {
+        self.chunks.iter().filter_map(|(pos, chunk)| match chunk {
+            Chunk::Loaded(index) => Some((
+                self.pos + pos.as_vec2() * chunk_size(),
+                &self.grids[*index],
+            )),
+            _ => None,
+        })
+    }
+
+    /// The chunk and the tile on its highest layer at the world position.
+    /// Only chunks that finished loading have tiles to pick.
+    pub fn pick_tile(&self, world: Vec2) -> Option<(UVec2, UVec3)> {
+        let chunk = ((world - self.pos) / chunk_size()).floor();
+
+        if chunk.x < 0.0
+            || chunk.y < 0.0
+            || chunk.x >= self.size.x as f32
+            || chunk.y >= self.size.y as f32
+        {
+            return None;
+        }
+
+        let chunk = chunk.as_uvec2();
+        let Some(&Chunk::Loaded(index)) = self.chunks.get(&chunk) else {
+            return None;
+        };
+
+        top_tile(&self.maps[index], world).map(|tile| (chunk, tile))
+    }
+
+    /// Starts loading every chunk near the views and unloads the rest. Each
+    /// view is the bottom left and top right of what a camera shows.
+    pub fn stream(&mut self, views: &[(Vec2, Vec2)]) {
+        let mut wanted = HashSet::new();
+        let mut visible = Vec::new();
+
+        for &(min, max) in views {
+            wanted.extend(self.chunks_within(min, max, self.load_margin));
+            visible.extend(self.chunks_within(min, max, 0));
+        }
+
+        visible.sort_by_key(|chunk: &UVec2| (chunk.y, chunk.x));
+        visible.dedup();
+        self.visible = visible;
+
+        self.chunks.retain(|pos, chunk| {
+            if wanted.contains(pos) {
+                return true;
+            }
+
+            if let Chunk::Loaded(index) = chunk {
+                self.free.push(*index);
+            }
+
+            false
+        });
+
+        for pos in wanted {
+            let Entry::Vacant(entry) = self.chunks.entry(pos) else {
+                continue;
+            };
+
+            entry.insert(Chunk::Loading);
+
+            let sender = self.sender.clone();
+            let dir = self.dir.clone();
+
+            self.runtime.spawn_blocking(move || {
+                // Only fails once the World is gone so nobody is waiting.
+                let _ = sender.send((pos, load_chunk(&dir, pos)));
+            });
+        }
+    }
+
+    /// Puts any chunks that finished loading into maps and sends the visible
+    /// ones to the MapRenderer. Returns how many maps were sent.
+    pub fn update(
+        &mut self,
+        renderer: &mut GpuRenderer,
+        map_renderer: &mut MapRenderer,
+        atlas: &mut AtlasSet,
+        tilesheets: &Tilesheets,
+    ) -> usize {
+        for (pos, file) in self.receive() {
+            let chunk =
+                self.place(renderer, map_renderer, tilesheets, pos, file);
+
+            self.chunks.insert(pos, chunk);
+        }
+
+        let mut sent = 0;
+
+        for pos in &self.visible {
+            if let Some(&Chunk::Loaded(index)) = self.chunks.get(pos) {
+                map_renderer.update(
+                    &mut self.maps[index],
+                    renderer,
+                    atlas,
+                    [0, 1],
+                );
+                sent += 1;
+            }
+        }
+
+        sent
+    }
+
+    // Takes every chunk that finished loading. Chunks without tiles are
+    // marked empty right away and the rest are returned to be placed.
+    fn receive(&mut self) -> Vec<(UVec2, MapFile)> {
+        let mut files = Vec::new();
+
+        while let Ok((pos, result)) = self.receiver.try_recv() {
+            // The chunk was unloaded again while it was being read.
+            if !matches!(self.chunks.get(&pos), Some(Chunk::Loading)) {
+                continue;
+            }
+
+            match result {
+                Ok(Some(file)) => files.push((pos, file)),
+                Ok(None) => {
+                    self.chunks.insert(pos, Chunk::Empty);
+                }
+                Err(e) => {
+                    warn!("failed to load world chunk {pos}: {e}");
+                    self.chunks.insert(pos, Chunk::Empty);
+                }
+            }
+        }
+
+        files
+    }
+
+    // Maps freed by unloaded chunks are used before any new ones are made.
+    fn map_slot(&mut self) -> MapSlot {
+        match self.free.pop() {
+            Some(index) => MapSlot::Free(index),
+            None if self.maps.len() < MAX_CHUNKS => MapSlot::New,
+            None => MapSlot::Full,
+        }
+    }
+
+    // Sets the chunk's tiles onto a free map or a new one if there is room.
+    fn place(
+        &mut self,
+        renderer: &mut GpuRenderer,
+        map_renderer: &mut MapRenderer,
+        tilesheets: &Tilesheets,
+        chunk: UVec2,
+        mut file: MapFile,
+    ) -> Chunk {
+        file.pos = self.pos + chunk.as_vec2() * chunk_size();
+
+        let index = match self.map_slot() {
+            MapSlot::Free(index) => index,
+            MapSlot::New => {
+                let Some(map) = Map::new(
+                    renderer,
+                    map_renderer,
+                    TILE_SIZE,
+                    file.pos,
+                    MapZLayers::default(),
+                ) else {
+                    warn!("failed to create a map for world chunk {chunk}");
+                    return Chunk::Empty;
+                };
+
+                self.maps.push(map);
+                self.grids.push(AttributeGrid::default());
+                self.maps.len() - 1
+            }
+            MapSlot::Full => {
+                warn!("no maps left for world chunk {chunk}");
+                return Chunk::Empty;
+            }
+        };
+
+        if let Err(e) = file.apply(&mut self.maps[index], tilesheets) {
+            warn!("failed to load world chunk {chunk}: {e}");
+            self.free.push(index);
+            return Chunk::Empty;
+        }
+
+        self.grids[index] = file.attribute_grid();
+        Chunk::Loaded(index)
+    }
+
+    // Every chunk overlapping the area from `min` to `max` grown by `margin`
+    // chunks on each side.
+    fn chunks_within(&self, min: Vec2, max: Vec2, margin: u32) -> Vec<UVec2> {
+        let margin = margin as f32;
+        let first = (((min - self.pos) / chunk_size()).floor() - margin)
+            .max(Vec2::ZERO)
+            .as_uvec2();
+        let last = (((max - self.pos) / chunk_size()).ceil() + margin)
+            .clamp(Vec2::ZERO, self.size.as_vec2())
+            .as_uvec2();
+
+        (first.y..last.y)
+            .flat_map(|y| (first.x..last.x).map(move |x| UVec2::new(x, y)))
+            .collect()
+    }
+}
+
+/// Size of a single chunk in world pixels.
+pub fn chunk_size() -> Vec2 {
+    Vec2::splat((MAP_SIZE * TILE_SIZE) as f32)
+}
+
+// Runs on a blocking thread. Chunks without a file are fine and left empty.
+fn load_chunk(dir: &Path, pos: UVec2) -> Result<Option<MapFile>, String> {
+    ["bin", "ron"]
+        .iter()
+        .map(|ext| dir.join(format!("{}_{}.{ext}", pos.x, pos.y)))
+        .find(|path| path.exists())
+        .map(|path| MapFile::load(path).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapfile::MAP_FILE_VERSION;
+
+    // Chunks are 640 pixels across so this world is 1920 by 1280.
+    fn world() -> World {
+        World::new(&WorldDesc {
+            dir: String::from("no-such-world"),
+            pos: Vec2::ZERO,
+            size: UVec2::new(3, 2),
+            load_margin: 1,
+        })
+    }
+
+    fn empty_file() -> MapFile {
+        MapFile {
+            version: MAP_FILE_VERSION,
+            pos: Vec2::ZERO,
+            size: UVec2::splat(MAP_SIZE),
+            tile_size: TILE_SIZE,
+            layers: 1,
+            tilesheets: Vec::new(),
+            tiles: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+
+    fn chunks(list: &[(u32, u32)]) -> Vec<UVec2> {
+        list.iter().map(|&(x, y)| UVec2::new(x, y)).collect()
+    }
+
+    #[tokio::test]
+    async fn chunks_within_stays_inside_the_world() {
+        let world = world();
+
+        // A view ending on a chunk's edge does not reach into the next.
+        assert_eq!(
+            world.chunks_within(Vec2::ZERO, Vec2::splat(640.0), 0),
+            chunks(&[(0, 0)])
+        );
+        // Past the bottom left and top right only the corners are left.
+        assert_eq!(
+            world.chunks_within(Vec2::splat(-100.0), Vec2::splat(100.0), 0),
+            chunks(&[(0, 0)])
+        );
+        assert_eq!(
+            world.chunks_within(
+                Vec2::new(1900.0, 1200.0),
+                Vec2::new(2500.0, 1500.0),
+                0
+            ),
+            chunks(&[(2, 1)])
+        );
+        // Views entirely outside of the world have no chunks.
+        assert!(
+            world
+                .chunks_within(Vec2::splat(5000.0), Vec2::splat(6000.0), 1)
+                .is_empty()
+        );
+        assert!(
+            world
+                .chunks_within(Vec2::splat(-2000.0), Vec2::splat(-1000.0), 1)
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn chunks_within_adds_the_margin() {
+        let world = world();
+
+        assert_eq!(
+            world.chunks_within(Vec2::splat(10.0), Vec2::splat(20.0), 1),
+            chunks(&[(0, 0), (1, 0), (0, 1), (1, 1)])
+        );
+        assert_eq!(
+            world.chunks_within(
+                Vec2::new(700.0, 10.0),
+                Vec2::new(710.0, 20.0),
+                1
+            ),
+            chunks(&[(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_the_margin_but_only_shows_the_view() {
+        let mut world = world();
+
+        world.stream(&[(Vec2::splat(10.0), Vec2::splat(20.0))]);
+
+        assert_eq!(world.visible, chunks(&[(0, 0)]));
+        assert_eq!(world.chunks.len(), 4);
+        assert!(
+            world
+                .chunks
+                .values()
+                .all(|chunk| matches!(chunk, Chunk::Loading))
+        );
+    }
+
+    #[tokio::test]
+    async fn unloaded_chunks_free_their_maps_for_reuse() {
+        let mut world = world();
+
+        world.chunks.insert(UVec2::new(2, 1), Chunk::Loaded(3));
+        world.chunks.insert(UVec2::new(0, 0), Chunk::Loaded(5));
+        world.stream(&[(Vec2::splat(10.0), Vec2::splat(20.0))]);
+
+        // Only the chunk out of range gave its map up.
+        assert!(matches!(
+            world.chunks.get(&UVec2::ZERO),
+            Some(Chunk::Loaded(5))
+        ));
+        assert!(!world.chunks.contains_key(&UVec2::new(2, 1)));
+        assert_eq!(world.map_slot(), MapSlot::Free(3));
+        assert_eq!(world.map_slot(), MapSlot::New);
+    }
+
+    #[tokio::test]
+    async fn chunks_unloaded_while_loading_are_dropped() {
+        let mut world = world();
+        let far = UVec2::new(2, 1);
+
+        world.stream(&[(Vec2::new(1900.0, 1200.0), Vec2::splat(1910.0))]);
+        assert!(matches!(world.chunks.get(&far), Some(Chunk::Loading)));
+
+        // Scrolled away before the read finished.
+        world.stream(&[(Vec2::splat(10.0), Vec2::splat(20.0))]);
+        world.sender.send((far, Ok(Some(empty_file())))).unwrap();
+        world.sender.send((UVec2::ZERO, Ok(None))).unwrap();
+
+        assert!(world.receive().is_empty());
+        assert!(!world.chunks.contains_key(&far));
+        assert!(matches!(world.chunks.get(&UVec2::ZERO), Some(Chunk::Empty)));
+    }
+
+    #[tokio::test]
+    async fn loaded_files_are_returned_to_be_placed() {
+        let mut world = world();
+
+        // Marked by hand so no real read can finish first.
+        world.chunks.insert(UVec2::ZERO, Chunk::Loading);
+        world.chunks.insert(UVec2::X, Chunk::Loading);
+        world
+            .sender
+            .send((UVec2::ZERO, Ok(Some(empty_file()))))
+            .unwrap();
+        world
+            .sender
+            .send((UVec2::X, Err(String::from("bad file"))))
+            .unwrap();
+
+        let files = world.receive();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, UVec2::ZERO);
+        assert!(matches!(world.chunks.get(&UVec2::X), Some(Chunk::Empty)));
+    }
+}
@@ -0,0 +1,26 @@
+//! Renders each scene headless on a software adapter and compares it
+//! against its reference within `golden/`. Needs a display server for the
+//! demo's hidden window and Mesa's lavapipe or llvmpipe so these only run
+//! with `cargo test -- --ignored`.
+use std::process::Command;
+
+fn check_scene(scene: &str) {
+    let output = Command::new(env!("CARGO_BIN_EXE_demo"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--golden", "--headless-frames", "2", "--scene", scene])
+        .output()
+        .expect("failed to run the demo");
+
+    assert!(
+        output.status.success(),
+        "golden run of {scene} failed:\n{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+#[ignore = "needs a display server and a software adapter"]
+fn demo_scene_matches_reference() {
+    check_scene("scenes/demo.ron");
+}